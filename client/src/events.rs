@@ -1,7 +1,7 @@
-use crate::gui::{host, hosting, login, RpcConn};
+use crate::gui::{chat, host, hosting, join, login, RpcConn};
 use crate::Error;
 use futures::stream::{self, BoxStream};
-use protocol::{HostState, AWAIT_EVENT_TIMEOUT};
+use protocol::{HostState, HostTimings, AWAIT_EVENT_TIMEOUT};
 use shared::tarpc::context::Context;
 use std::cell::Cell;
 use std::hash::{Hash, Hasher};
@@ -11,16 +11,26 @@ use tracing::instrument;
 
 #[derive(Debug, Clone)]
 pub enum Event {
-    LoggedIn(RpcConn, HostState),
+    LoggedIn(RpcConn, HostState, HostTimings),
     WorldUpdated,
     HostPage(host::Event),
     LoginPage(login::Event),
     HostingPage(hosting::Event),
+    JoinPage(join::Event),
+    Chat(chat::Event),
     Server(protocol::Event),
     Mc(Result<wrapper::parser::Line, wrapper::Error>),
     McHandle(Arc<wrapper::Handle>),
+    /// the minecraft server process exited after stopping
+    McStopped,
+    /// start the minecraft server again after it crashed
+    McRestart,
+    /// the server refused the minecraft version we run, it is stopped
+    /// without uploading the world
+    McRefused(protocol::Error),
     Error(Error),
     ClipHost,
+    TakeOver,
     Empty,
 }
 
@@ -0,0 +1,130 @@
+pub use crate::Event as Msg;
+use iced::{
+    button, scrollable, text_input, Button, Column, Command, Element, Length, Row, Scrollable,
+    Text, TextInput,
+};
+use protocol::ChatMessage;
+use shared::tarpc::client::RpcError;
+use std::collections::VecDeque;
+
+use super::parts::{ClearError, ErrorBar};
+use super::RpcConn;
+
+mod tasks;
+
+/// messages kept, the server remembers as many
+const KEPT: usize = 100;
+
+#[derive(thiserror::Error, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Error {
+    #[error("Lost connection to worldsync server: {0:?}")]
+    NoMetaConn(#[from] RpcError),
+    #[error("Could not send message: {0}")]
+    Send(protocol::Error),
+    #[error("Could not get earlier messages: {0}")]
+    History(protocol::Error),
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    History(Vec<ChatMessage>),
+    Received(ChatMessage),
+    Draft(String),
+    Send,
+    Error(Error),
+    ClearError(Error),
+}
+
+impl ClearError for Event {
+    type Error = Error;
+    fn clear(e: Error) -> Self {
+        Self::ClearError(e)
+    }
+}
+
+/// chat with everyone logged in and the players on the host
+pub struct Pane {
+    errorbar: ErrorBar<Error>,
+    rpc: RpcConn,
+    /// newest first
+    messages: VecDeque<ChatMessage>,
+    draft: String,
+    input: text_input::State,
+    send: button::State,
+    scroll: scrollable::State,
+}
+
+impl Pane {
+    pub fn from(rpc: RpcConn) -> Self {
+        Self {
+            errorbar: ErrorBar::default(),
+            rpc,
+            messages: VecDeque::new(),
+            draft: String::new(),
+            input: text_input::State::default(),
+            send: button::State::default(),
+            scroll: scrollable::State::default(),
+        }
+    }
+
+    fn add(&mut self, msg: ChatMessage) {
+        self.messages.push_front(msg);
+        self.messages.truncate(KEPT);
+    }
+
+    pub fn update(&mut self, event: Event) -> Command<Msg> {
+        match event {
+            Event::History(history) => {
+                // messages may have come in while the history was on its way
+                let missed: Vec<_> = self
+                    .messages
+                    .drain(..)
+                    .rev()
+                    .filter(|msg| !history.contains(msg))
+                    .collect();
+                for msg in history.into_iter().chain(missed) {
+                    self.add(msg);
+                }
+            }
+            Event::Received(msg) => self.add(msg),
+            Event::Draft(text) => self.draft = text,
+            Event::Send => return self.send(),
+            Event::Error(e) => self.errorbar.add(e),
+            Event::ClearError(e) => self.errorbar.clear(e),
+        }
+        Command::none()
+    }
+
+    pub fn view(&mut self) -> Element<'_, Msg> {
+        let messages = self
+            .messages
+            .iter()
+            .fold(Scrollable::new(&mut self.scroll), |list, msg| {
+                list.push(Text::new(line(msg)).size(16))
+            })
+            .height(Length::Fill);
+
+        let input = TextInput::new(&mut self.input, "Say something", &self.draft, Event::Draft)
+            .on_submit(Event::Send)
+            .padding(4);
+        let send = Button::new(&mut self.send, Text::new("Send")).on_press(Event::Send);
+        let input = Row::new().push(input).push(send);
+
+        let pane: Element<Event> = Column::new()
+            .width(Length::FillPortion(1))
+            .padding(8)
+            .push(self.errorbar.view())
+            .push(messages)
+            .push(input)
+            .into();
+        pane.map(Msg::Chat)
+    }
+}
+
+/// in game messages look like they do in minecraft
+fn line(msg: &ChatMessage) -> String {
+    match msg.in_game {
+        true => msg.in_game_text(),
+        false => format!("[{}] {}", msg.from, msg.text),
+    }
+}
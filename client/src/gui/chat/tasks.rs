@@ -0,0 +1,45 @@
+use iced::Command;
+use protocol::ChatMessage;
+use shared::tarpc::context;
+use tracing::instrument;
+
+use super::{Error, Event, Msg, Pane};
+use crate::gui::RpcConn;
+
+#[instrument(err)]
+async fn history(rpc: RpcConn) -> Result<Vec<ChatMessage>, Error> {
+    rpc.client
+        .chat_history(context::current(), rpc.session)
+        .await?
+        .map_err(Error::History)
+}
+
+#[instrument(err)]
+async fn send_chat(rpc: RpcConn, text: String) -> Result<(), Error> {
+    rpc.client
+        .send_chat(context::current(), rpc.session, text)
+        .await?
+        .map_err(Error::Send)
+}
+
+impl Pane {
+    /// we only hear of new messages, get what was said before we logged in
+    pub fn fetch_history(&self) -> Command<Msg> {
+        Command::perform(history(self.rpc.clone()), |res| match res {
+            Ok(history) => Msg::Chat(Event::History(history)),
+            Err(err) => Msg::Chat(Event::Error(err)),
+        })
+    }
+
+    /// the server passes the message back to us like to everyone else
+    pub fn send(&mut self) -> Command<Msg> {
+        if self.draft.trim().is_empty() {
+            return Command::none();
+        }
+        let text = std::mem::take(&mut self.draft);
+        Command::perform(send_chat(self.rpc.clone(), text), |res| match res {
+            Ok(_) => Msg::Empty,
+            Err(err) => Msg::Chat(Event::Error(err)),
+        })
+    }
+}
@@ -2,8 +2,8 @@ use std::hash::Hash;
 
 use crate::gui::parts::ClearError;
 pub use crate::Event as Msg;
-use crate::{world_dl, mc};
-use protocol::HostId;
+use crate::{world_dl, mc, port_map};
+use protocol::{HostId, PortCheck};
 use iced::{Align, Button, Column, Command, Element, HorizontalAlignment, Length, Row, Space, Text, button};
 use shared::tarpc::client::RpcError;
 
@@ -11,6 +11,7 @@ use super::RpcConn;
 use super::parts::{ErrorBar, Loading};
 
 mod tasks;
+pub use tasks::remove_mapping;
 
 #[derive(thiserror::Error, Debug, Clone, Eq, PartialEq, Hash)]
 pub enum Error {
@@ -20,24 +21,78 @@ pub enum Error {
     Sync(#[from] world_dl::Error),
     #[error("Could not start minecraft server: {0}")]
     ServerStart(#[from] wrapper::Error),
+    #[error("Could not configure minecraft server port: {0:?}")]
+    Config(std::io::ErrorKind),
+    #[error("Minecraft server stopped before it finished loading")]
+    McStopped,
+    #[error("The worldsync server does not offer a relay")]
+    NoRelay,
+    #[error("Could not listen on the minecraft port to check it: {0:?}")]
+    PortCheck(std::io::ErrorKind),
+    #[error(
+        "Port {port} can not be reached from outside, {}. Hosting anyway, players on your network can still join",
+        advice(*.check, *.port)
+    )]
+    PortClosed { port: u16, check: PortCheck },
+    #[error("Could not forward the minecraft port automatically: {0}")]
+    PortMap(#[from] port_map::Error),
+    #[error("Refused to host: {0}")]
+    Refused(protocol::Error),
+    #[error("Could not stop hosting: {0}")]
+    Release(protocol::Error),
+}
+
+/// what to do about a port the server could not reach
+fn advice(check: PortCheck, port: u16) -> String {
+    match check {
+        PortCheck::Open => "it should work".to_owned(),
+        PortCheck::WrongTarget => format!(
+            "another device or program answered, make sure your router forwards port {} to this computer",
+            port
+        ),
+        PortCheck::Refused => format!(
+            "the connection was refused, forward port {} (TCP) on your router to this computer",
+            port
+        ),
+        PortCheck::NoAnswer => format!(
+            "there was no answer, allow incoming connections on port {} in your firewall and forward it on your router",
+            port
+        ),
+    }
 }
 
 impl From<protocol::Error> for Error {
     fn from(e: protocol::Error) -> Self {
-        unimplemented!("should not run into {:?} on login page", e)
+        match e {
+            protocol::Error::NoRelay => Self::NoRelay,
+            e => unimplemented!("should not run into {:?} on login page", e),
+        }
     }
 }
 
+/// why we are preparing to host
+#[derive(Debug, Clone, Copy)]
+pub enum Goal {
+    Host,
+    Takeover,
+}
+
 #[derive(Debug, Clone)]
 pub enum Event {
     Error(Error),
     ClearError(Error),
     WantToHost,
+    PortMapped(Goal, Result<port_map::Mapping, port_map::Error>),
+    /// hosting continues whatever the result, a closed port only
+    /// matters to players outside our network
+    PortChecked(Result<PortCheck, Error>),
     ObjToSync{left: usize},
     DlStarting{num_obj: usize},
     Loading(u8),
     WorldUpdated,
     Mc(Result<wrapper::parser::Line,wrapper::Error>),
+    /// the server refused the minecraft version we run
+    Refused(protocol::Error),
 }
 
 impl ClearError for Event {
@@ -54,10 +109,19 @@ pub struct Page {
     loading_server: Loading,
     rpc: RpcConn,
     pub host_id: Option<HostId>,
+    /// port picked for the minecraft server when requesting to host
+    pub port: Option<u16>,
+    preferred_port: Option<u16>,
+    /// result of the server trying to reach our port
+    port_check: Option<PortCheck>,
+    /// port our router forwards to us, if it could
+    pub mapping: Option<port_map::Mapping>,
+    /// have players connect through the relay of the server
+    pub relay: bool,
 }
 
 impl Page {
-    pub fn from(rpc: RpcConn) -> Self {
+    pub fn from(rpc: RpcConn, preferred_port: Option<u16>, relay: bool) -> Self {
         Self {
             errorbar: Default::default(),
             host: Default::default(),
@@ -65,7 +129,11 @@ impl Page {
             loading_server: Default::default(),
             rpc,
             host_id: None,
-
+            port: None,
+            preferred_port,
+            port_check: None,
+            mapping: None,
+            relay,
         }
     }
 
@@ -73,7 +141,27 @@ impl Page {
         match event {
             Event::Error(e) => self.errorbar.add(e),
             Event::ClearError(e) => self.errorbar.clear(e),
-            Event::WantToHost => return self.request_to_host(),
+            Event::WantToHost => return self.prepare(Goal::Host),
+            Event::PortMapped(goal, res) => {
+                // the port may have been forwarded by hand
+                match res {
+                    Ok(mapping) => self.mapping = Some(mapping),
+                    Err(e) => self.errorbar.add(e.into()),
+                }
+                return self.mapped(goal);
+            }
+            Event::PortChecked(res) => {
+                self.port_check = res.as_ref().ok().copied();
+                match res {
+                    Ok(PortCheck::Open) => (),
+                    Ok(check) => self.errorbar.add(Error::PortClosed {
+                        port: self.external_port(),
+                        check,
+                    }),
+                    Err(e) => self.errorbar.add(e),
+                }
+                return self.announce(Goal::Host);
+            }
             Event::ObjToSync{left} => self.downloading.set_progress(left as f32),
             Event::DlStarting{num_obj} => self.downloading.start(num_obj as f32, 0.0),
             Event::WorldUpdated => {
@@ -85,6 +173,10 @@ impl Page {
                 Ok(line) => return mc::send_line(line, self.rpc.clone(), self.host_id.unwrap()),
                 Err(e) => self.errorbar.add(e.into()),
             }
+            Event::Refused(e) => {
+                self.errorbar.add(Error::Refused(e));
+                return self.release();
+            }
         }
         Command::none()
     }
@@ -100,6 +192,7 @@ impl Page {
             .push(top_spacer)
             .push(title())
             .push(host_button(&mut self.host))
+            .push(port_status(self.port_check))
             .push(self.downloading.view())
             .push(self.loading_server.view())
             .push(bottom_spacer);
@@ -124,6 +217,17 @@ fn title() -> Text {
         .horizontal_alignment(HorizontalAlignment::Center)
 }
 
+fn port_status(check: Option<PortCheck>) -> Text {
+    let label = match check {
+        None => "",
+        Some(PortCheck::Open) => "port forwarding works",
+        Some(_) => "port forwarding does not work",
+    };
+    Text::new(label)
+        .width(Length::FillPortion(1))
+        .horizontal_alignment(HorizontalAlignment::Center)
+}
+
 fn host_button(state: &mut button::State) -> Button<Msg> {
     Button::new(state, Text::new("Host").horizontal_alignment(HorizontalAlignment::Center))
         .on_press(Msg::HostPage(Event::WantToHost))
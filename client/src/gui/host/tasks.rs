@@ -1,25 +1,170 @@
 use crate::gui::RpcConn;
-pub use protocol::{HostId, ServiceClient};
+pub use protocol::{HostId, PortCheck, ServiceClient, Uuid};
 use shared::tarpc;
 pub use tarpc::context;
 use tracing::instrument;
 
-use super::{Error, Event, Msg, Page};
+use super::{Error, Event, Goal, Msg, Page};
+use crate::gui::join;
+use crate::{mc, port_map};
 use iced::Command;
+use std::net::{Ipv4Addr, SocketAddr};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tracing::warn;
 
 #[instrument(err)]
-async fn request_to_host(rpc: RpcConn, host_id: HostId) -> Result<(), Error> {
+async fn request_to_host(
+    rpc: RpcConn,
+    host_id: HostId,
+    port: u16,
+    relay: bool,
+    mapped: Option<SocketAddr>,
+) -> Result<(), Error> {
     rpc.client
-        .request_to_host(context::current(), rpc.session, host_id)
+        .request_to_host(
+            context::current(),
+            rpc.session,
+            host_id,
+            port,
+            rpc.lan_ip,
+            relay,
+            mapped,
+        )
         .await?
         .map_err(|e| e.into())
 }
 
+#[instrument(err)]
+async fn request_takeover(
+    rpc: RpcConn,
+    host_id: HostId,
+    port: u16,
+    relay: bool,
+    mapped: Option<SocketAddr>,
+) -> Result<(), join::Error> {
+    rpc.client
+        .request_takeover(
+            context::current(),
+            rpc.session,
+            host_id,
+            port,
+            rpc.lan_ip,
+            relay,
+            mapped,
+        )
+        .await?
+        .map_err(join::Error::Takeover)
+}
+
+/// we never loaded the world, there is nothing to save
+#[instrument(err)]
+async fn release_host(rpc: RpcConn, host_id: HostId) -> Result<(), Error> {
+    rpc.client
+        .release_host(context::current(), rpc.session, host_id, false)
+        .await?
+        .map_err(Error::Release)
+}
+
+/// Listens on port while the server tries to reach us at the external
+/// port, we answer with a token so the server knows it reached us and not
+/// some other device
+#[instrument(err)]
+async fn check_port(rpc: RpcConn, port: u16, external: u16) -> Result<PortCheck, Error> {
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))
+        .await
+        .map_err(|e| Error::PortCheck(e.kind()))?;
+    let token = Uuid::new_v4();
+    let answer = async {
+        loop {
+            if let Ok((mut conn, _)) = listener.accept().await {
+                let _irrelevant = conn.write_all(token.as_bytes()).await;
+            }
+        }
+    };
+
+    let check = rpc
+        .client
+        .check_port(context::current(), rpc.session, external, token);
+    tokio::select! {
+        res = check => res?.map_err(|e| e.into()),
+        _ = answer => unreachable!("answer loops forever"),
+    }
+}
+
+/// removes a mapping we no longer need, it expires by itself if the
+/// router does not answer
+pub fn remove_mapping(mapping: port_map::Mapping) -> Command<Msg> {
+    let task = async move {
+        if let Err(e) = mapping.remove().await {
+            warn!("could not remove port mapping: {}", e);
+        }
+    };
+    Command::perform(task, |_| Msg::Empty)
+}
+
 impl Page {
-    pub fn request_to_host(&mut self) -> Command<Msg> {
+    /// Players connect through the relay if we use it. Otherwise we ask
+    /// the router to forward our port before announcing ourselves.
+    pub fn prepare(&mut self, goal: Goal) -> Command<Msg> {
+        let port = mc::pick_port(self.preferred_port);
+        self.port = Some(port);
+        if self.relay {
+            return self.announce(goal);
+        }
+
+        let mut commands = Vec::new();
+        match self.mapping.take() {
+            Some(mapping) if mapping.internal_port == port => {
+                self.mapping = Some(mapping);
+                return self.mapped(goal);
+            }
+            Some(outdated) => commands.push(remove_mapping(outdated)),
+            None => (),
+        }
+        let task = port_map::map(port, self.rpc.lan_ip);
+        commands.push(Command::perform(task, move |res| {
+            Msg::HostPage(Event::PortMapped(goal, res))
+        }));
+        Command::batch(commands)
+    }
+
+    /// we know if the router forwards our port, if we have to host make
+    /// sure it is reachable
+    pub fn mapped(&mut self, goal: Goal) -> Command<Msg> {
+        match goal {
+            Goal::Host => self.check_port(),
+            Goal::Takeover => self.announce(goal),
+        }
+    }
+
+    pub fn announce(&mut self, goal: Goal) -> Command<Msg> {
+        match goal {
+            Goal::Host => self.request_to_host(),
+            Goal::Takeover => self.request_takeover(),
+        }
+    }
+
+    fn check_port(&mut self) -> Command<Msg> {
+        let port = self.port.expect("port is picked before checking it");
+        let task = check_port(self.rpc.clone(), port, self.external_port());
+        Command::perform(task, |res| Msg::HostPage(Event::PortChecked(res)))
+    }
+
+    /// the port players outside our network connect to
+    pub fn external_port(&self) -> u16 {
+        match &self.mapping {
+            Some(mapping) => mapping.external.port(),
+            None => self.port.expect("port is picked before mapping it"),
+        }
+    }
+
+    fn request_to_host(&mut self) -> Command<Msg> {
         let host_id = HostId::new_v4();
         self.host_id = Some(host_id);
-        let task = request_to_host(self.rpc.clone(), host_id);
+        let port = self.port.expect("port is picked before requesting to host");
+        let mapped = self.mapping.as_ref().map(|m| m.external);
+        let task = request_to_host(self.rpc.clone(), host_id, port, self.relay, mapped);
 
         Command::perform(task, move |res| match res {
             // if we became host we will get the msg via
@@ -29,6 +174,34 @@ impl Page {
         })
     }
 
+    fn request_takeover(&mut self) -> Command<Msg> {
+        let host_id = HostId::new_v4();
+        self.host_id = Some(host_id);
+        let port = self.port.expect("port is picked before taking over");
+        let mapped = self.mapping.as_ref().map(|m| m.external);
+        let task = request_takeover(self.rpc.clone(), host_id, port, self.relay, mapped);
+
+        Command::perform(task, move |res| match res {
+            // the current host hands over by announcing us
+            // as new host via the server event subscription
+            Ok(_) => Msg::JoinPage(join::Event::TakeoverRequested),
+            Err(err) => Msg::JoinPage(join::Event::Error(err)),
+        })
+    }
+
+    pub fn release(&mut self) -> Command<Msg> {
+        let host_id = self
+            .host_id
+            .expect("only the host gets minecraft server lines");
+        let task = release_host(self.rpc.clone(), host_id);
+        Command::perform(task, |res| match res {
+            // the server announces the shutdown through the event
+            // subscription
+            Ok(_) => Msg::Empty,
+            Err(err) => Msg::HostPage(Event::Error(err)),
+        })
+    }
+
     pub fn is_us(&self, host: &protocol::HostDetails) -> bool {
         self.host_id == Some(host.id)
     }
@@ -0,0 +1,78 @@
+use iced::{scrollable, text_input, Color, Column, Element, Length, Scrollable, Text, TextInput};
+use std::collections::VecDeque;
+use wrapper::parser::{Level, Line};
+use wrapper::CrashReport;
+
+use super::{Event, Msg};
+
+/// lines kept, older ones are dropped
+const KEPT: usize = 500;
+
+/// replies to commands the host ran
+const REPLY: Color = Color::from_rgb(0.1, 0.3, 0.7);
+
+/// what the minecraft server printed and a prompt to run commands
+#[derive(Default)]
+pub struct Console {
+    /// newest first
+    lines: VecDeque<(String, Color)>,
+    pub draft: String,
+    input: text_input::State,
+    scroll: scrollable::State,
+}
+
+impl Console {
+    pub fn push(&mut self, line: Line) {
+        self.add(line.to_string(), color(&line.level));
+    }
+
+    pub fn push_reply(&mut self, reply: &str) {
+        for line in reply.lines() {
+            self.add(line.to_owned(), REPLY);
+        }
+    }
+
+    pub fn push_crash_report(&mut self, report: &CrashReport) {
+        let color = color(&Level::Error);
+        self.add(format!("crash report {}:", report.file_name), color);
+        for line in report.text.lines() {
+            self.add(line.to_owned(), color);
+        }
+    }
+
+    fn add(&mut self, text: String, color: Color) {
+        self.lines.push_front((text, color));
+        self.lines.truncate(KEPT);
+    }
+
+    pub fn view(&mut self) -> Element<'_, Msg> {
+        let lines = self
+            .lines
+            .iter()
+            .fold(Scrollable::new(&mut self.scroll), |list, (line, color)| {
+                list.push(Text::new(line.as_str()).size(14).color(*color))
+            })
+            .height(Length::Fill);
+
+        let input = TextInput::new(&mut self.input, "/command", &self.draft, |s| {
+            Msg::HostingPage(Event::ConsoleDraft(s))
+        })
+        .on_submit(Msg::HostingPage(Event::ConsoleSubmit))
+        .padding(4);
+
+        Column::new()
+            .width(Length::FillPortion(4))
+            .padding(8)
+            .push(lines)
+            .push(input)
+            .into()
+    }
+}
+
+fn color(level: &Level) -> Color {
+    match level {
+        Level::Info => Color::BLACK,
+        Level::Warn => Color::from_rgb(0.8, 0.5, 0.0),
+        Level::Error => Color::from_rgb(0.8, 0.0, 0.0),
+    }
+}
@@ -0,0 +1,207 @@
+//! Stops hosting once no one has played for a while, so the world is free
+//! for someone else. Players are tracked from the join and leave lines of
+//! the minecraft server.
+
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+use wrapper::Message;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    /// time without players after which we stop hosting
+    pub shutdown_after: Duration,
+    /// how long before stopping we warn in chat
+    pub warn_before: Duration,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    None,
+    /// tell everyone how long is left
+    Warn(Duration),
+    Stop,
+}
+
+#[derive(Debug)]
+pub struct Idle {
+    policy: Option<Policy>,
+    players: BTreeSet<String>,
+    /// since when no one is playing, none while loading
+    since: Option<Instant>,
+    warned: bool,
+}
+
+impl Idle {
+    /// the timer starts once the server is done loading
+    pub fn new(policy: Option<Policy>) -> Self {
+        Self {
+            policy,
+            players: BTreeSet::new(),
+            since: None,
+            warned: false,
+        }
+    }
+
+    /// no one is playing yet when the server is done loading
+    pub fn loaded(&mut self, now: Instant) {
+        if self.players.is_empty() {
+            self.since.get_or_insert(now);
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.policy.is_some()
+    }
+
+    pub fn update(&mut self, msg: &Message, now: Instant) {
+        match msg {
+            Message::Joined { user, .. } => {
+                self.players.insert(user.clone());
+                self.since = None;
+                self.warned = false;
+            }
+            Message::DoneLoading(_) => self.loaded(now),
+            Message::Left(user) | Message::Kicked(user) => {
+                self.players.remove(user);
+                if self.players.is_empty() && self.since.is_none() {
+                    self.since = Some(now);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// the minecraft server restarted, everyone playing lost their
+    /// connection without a leave line
+    pub fn restarted(&mut self, now: Instant) {
+        self.players.clear();
+        self.since.get_or_insert(now);
+    }
+
+    /// time left before we stop, none if someone is playing or there is
+    /// no policy
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        let policy = self.policy?;
+        let idle = now.saturating_duration_since(self.since?);
+        Some(policy.shutdown_after.saturating_sub(idle))
+    }
+
+    pub fn check(&mut self, now: Instant) -> Action {
+        let (policy, remaining) = match (self.policy, self.remaining(now)) {
+            (Some(policy), Some(remaining)) => (policy, remaining),
+            _ => return Action::None,
+        };
+        if remaining.is_zero() {
+            return Action::Stop;
+        }
+        if remaining <= policy.warn_before && !self.warned {
+            self.warned = true;
+            return Action::Warn(remaining);
+        }
+        Action::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wrapper::parser::Coords;
+
+    const POLICY: Policy = Policy {
+        shutdown_after: Duration::from_secs(30 * 60),
+        warn_before: Duration::from_secs(5 * 60),
+    };
+
+    fn mins(start: Instant, mins: u64) -> Instant {
+        start + Duration::from_secs(mins * 60)
+    }
+
+    fn joined(user: &str) -> Message {
+        Message::Joined {
+            user: user.to_owned(),
+            address: "10.0.0.2:52788".parse().unwrap(),
+            entity_id: 179,
+            coords: Coords {
+                x: 0.,
+                y: 0.,
+                z: 0.,
+            },
+        }
+    }
+
+    #[test]
+    fn stops_when_empty() {
+        let start = Instant::now();
+        let mut idle = Idle::new(Some(POLICY));
+        idle.loaded(start);
+        assert_eq!(idle.check(mins(start, 24)), Action::None);
+        assert_eq!(
+            idle.check(mins(start, 25)),
+            Action::Warn(Duration::from_secs(5 * 60))
+        );
+        assert_eq!(idle.check(mins(start, 26)), Action::None);
+        assert_eq!(idle.check(mins(start, 30)), Action::Stop);
+    }
+
+    #[test]
+    fn starts_once_loaded() {
+        let start = Instant::now();
+        let mut idle = Idle::new(Some(POLICY));
+        assert_eq!(idle.check(mins(start, 100)), Action::None);
+        idle.update(
+            &Message::DoneLoading(Duration::from_secs(10)),
+            mins(start, 100),
+        );
+        assert_eq!(
+            idle.remaining(mins(start, 110)),
+            Some(Duration::from_secs(20 * 60))
+        );
+    }
+
+    #[test]
+    fn players_reset_timer() {
+        let start = Instant::now();
+        let mut idle = Idle::new(Some(POLICY));
+        idle.loaded(start);
+        idle.update(&joined("Alice"), mins(start, 10));
+        idle.update(&joined("Bob"), mins(start, 11));
+        assert_eq!(idle.remaining(mins(start, 50)), None);
+        assert_eq!(idle.check(mins(start, 50)), Action::None);
+
+        idle.update(&Message::Left("Alice".to_owned()), mins(start, 60));
+        assert_eq!(idle.remaining(mins(start, 70)), None);
+        idle.update(&Message::Kicked("Bob".to_owned()), mins(start, 70));
+        assert_eq!(
+            idle.remaining(mins(start, 80)),
+            Some(Duration::from_secs(20 * 60))
+        );
+        assert_eq!(idle.check(mins(start, 100)), Action::Stop);
+    }
+
+    #[test]
+    fn restart_drops_players() {
+        let start = Instant::now();
+        let mut idle = Idle::new(Some(POLICY));
+        idle.loaded(start);
+        idle.update(&joined("Alice"), mins(start, 10));
+        idle.restarted(mins(start, 20));
+        assert_eq!(
+            idle.remaining(mins(start, 30)),
+            Some(Duration::from_secs(20 * 60))
+        );
+        idle.update(&Message::Left("Alice".to_owned()), mins(start, 40));
+        assert_eq!(
+            idle.remaining(mins(start, 40)),
+            Some(Duration::from_secs(10 * 60))
+        );
+    }
+
+    #[test]
+    fn no_policy() {
+        let start = Instant::now();
+        let mut idle = Idle::new(None);
+        idle.loaded(start);
+        assert_eq!(idle.remaining(mins(start, 100)), None);
+        assert_eq!(idle.check(mins(start, 100)), Action::None);
+    }
+}
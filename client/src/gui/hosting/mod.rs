@@ -1,15 +1,22 @@
 use super::parts::{ClearError, ErrorBar, Loading};
+use super::style;
 use super::tasks::SubStatus;
 use super::{RpcConn, SubsList};
 pub use crate::Event as Msg;
 use crate::{mc, world_upload};
-use iced::{Column, Command, Element, HorizontalAlignment, Length, Row, Space, Text};
-use protocol::HostId;
+use iced::{button, Button, Column, Command, Element, HorizontalAlignment, Length, Row, Space, Text};
+use protocol::{ChatMessage, CommandId, HostId};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+mod console;
+mod idle;
 mod tasks;
 
+use console::Console;
+use idle::Idle;
+pub use idle::Policy as IdlePolicy;
+
 #[derive(thiserror::Error, Debug, Clone, Eq, PartialEq, Hash)]
 pub enum Error {
     #[error("Ran into problem running minecraft server: {0}")]
@@ -24,6 +31,20 @@ pub enum Error {
     Upload(#[from] crate::world_upload::Error),
     #[error("Lost connection to metadata server")]
     LostConn,
+    #[error("Could not hand over hosting: {0}")]
+    HandOver(protocol::Error),
+    #[error("Could not stop hosting: {0}")]
+    Release(protocol::Error),
+    #[error("Could not return console output to the server: {0}")]
+    Console(protocol::Error),
+    #[error("Could not send command to minecraft server: {0}")]
+    Command(wrapper::HandleError),
+    #[error("Could not upload crash report: {0}")]
+    CrashReport(protocol::Error),
+    #[error("Stopped hosting: {0}")]
+    Refused(protocol::Error),
+    #[error("Could not report minecraft server output: {0}")]
+    PubLine(protocol::Error),
 }
 
 impl From<protocol::Error> for Error {
@@ -37,12 +58,32 @@ pub enum Event {
     ClearError(Error),
     Mc(Result<wrapper::parser::Line, wrapper::Error>),
     PeriodicSave,
+    /// the world is saved and copied for upload
+    Saved,
     Error(Error),
     UploadStarting(usize),
     Uploading(usize),
     UploadDone,
     SaveRegisterd,
     Tick,
+    TakeoverRequested(String),
+    TakeoverFailed,
+    Stop,
+    McStopped,
+    /// a message from a worldsync client to pass on to the players
+    Chat(ChatMessage),
+    /// an admin wants this console command run
+    ConsoleCommand(CommandId, String),
+    /// the console command ran, return its reply
+    ConsoleDone(CommandId, Vec<String>),
+    /// the host is typing a command in the console pane
+    ConsoleDraft(String),
+    ConsoleSubmit,
+    /// reply to a command the host ran from the console pane
+    ConsoleReply(String),
+    /// the server refused the minecraft version we run after a restart,
+    /// the minecraft server is stopped
+    Refused(protocol::Error),
 }
 
 impl ClearError for Event {
@@ -52,6 +93,28 @@ impl ClearError for Event {
     }
 }
 
+/// progress of handing hosting over to someone who asked to take over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HandOver {
+    Idle,
+    /// waiting for an upload that was already running to finish
+    Requested,
+    /// the final save is being made and uploaded
+    FinalSave,
+}
+
+/// progress of stopping the minecraft server and releasing the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stopping {
+    No,
+    /// waiting for the minecraft server to exit
+    AwaitExit,
+    /// waiting for an upload that was already running to finish
+    AwaitUpload,
+    /// the save made by the minecraft server on exit is being uploaded
+    FinalUpload,
+}
+
 pub struct Page {
     errorbar: ErrorBar<Error>,
     mc_handle: wrapper::Handle,
@@ -61,11 +124,28 @@ pub struct Page {
     uploading_sub: SubStatus,
     refresh_time: SubStatus,
     save_periodically: SubStatus,
+    handover: HandOver,
+    stopping: Stopping,
+    stop: button::State,
     rpc: RpcConn,
+    console_pane: Console,
+    idle: Idle,
+    /// the minecraft server printed it ran into an error it can not
+    /// recover from
+    crashing: bool,
+    /// times the minecraft server was restarted after crashing
+    restarts: u8,
+    max_restarts: u8,
 }
 
 impl Page {
-    pub fn from(server: Arc<wrapper::Handle>, host_id: HostId, rpc: RpcConn) -> Self {
+    pub fn from(
+        server: Arc<wrapper::Handle>,
+        host_id: HostId,
+        rpc: RpcConn,
+        idle_policy: Option<IdlePolicy>,
+        max_restarts: u8,
+    ) -> Self {
         let mut save_periodically = SubStatus::default();
         save_periodically.start();
 
@@ -79,27 +159,61 @@ impl Page {
             uploading_sub: SubStatus::default(),
             refresh_time: SubStatus::default(),
             save_periodically,
+            handover: HandOver::Idle,
+            stopping: Stopping::No,
+            stop: button::State::default(),
             rpc,
+            console_pane: Console::default(),
+            idle: Idle::new(idle_policy),
+            crashing: false,
+            restarts: 0,
+            max_restarts,
         }
     }
 
+    /// the minecraft server is done loading, players can join
+    pub fn loaded(&mut self) {
+        self.idle.loaded(Instant::now());
+    }
+
+    /// the minecraft server restarted, commands go to the new one
+    pub fn set_handle(&mut self, server: Arc<wrapper::Handle>) {
+        self.mc_handle =
+            Arc::try_unwrap(server).expect("server handle should only have one reference");
+    }
+
     pub fn update(&mut self, event: Event) -> Command<Msg> {
         match event {
             Event::Error(Error::LostConn) => {
                 self.errorbar.add(Error::LostConn);
                 return self.notify_conn_lost();
             }
+            Event::Error(Error::Upload(e)) => {
+                self.uploading_sub.stop();
+                self.errorbar.add(Error::Upload(e));
+                match self.stopping {
+                    Stopping::No | Stopping::AwaitExit => (),
+                    Stopping::AwaitUpload => return self.upload_final_save(),
+                    Stopping::FinalUpload => return self.release(false),
+                }
+            }
             Event::Error(e) => self.errorbar.add(e),
             Event::ClearError(e) => self.errorbar.clear(e),
             Event::PeriodicSave => {
-                if self.uploading_sub.active().is_none() {
+                if self.uploading_sub.active().is_none() && self.stopping == Stopping::No {
                     return self.save_world();
                 }
             }
             Event::Mc(event) => match event {
-                Ok(line) => return self.handle_server_line(line, self.rpc.clone()),
+                Ok(line) => {
+                    self.console_pane.push(line.clone());
+                    self.idle.update(&line.msg, Instant::now());
+                    return self.handle_server_line(line, self.rpc.clone());
+                }
+                Err(e @ wrapper::Error::Crashed { .. }) => return self.crashed(e),
                 Err(e) => self.errorbar.add(e.into()),
             },
+            Event::Saved => self.uploading_sub.start(),
             Event::UploadStarting(num_obj) => self.uploading.start(num_obj as f32, 0.0),
             Event::Uploading(p) => self.uploading.set_progress(p as f32),
             Event::UploadDone => self.uploading.finished(),
@@ -107,8 +221,35 @@ impl Page {
                 self.last_save = Some(Instant::now());
                 self.uploading_sub.stop();
                 self.refresh_time.start();
+                match self.stopping {
+                    Stopping::No | Stopping::AwaitExit => (),
+                    Stopping::AwaitUpload => return self.upload_final_save(),
+                    Stopping::FinalUpload => return self.release(true),
+                }
+                match self.handover {
+                    HandOver::Idle => (),
+                    HandOver::Requested => {
+                        self.handover = HandOver::FinalSave;
+                        return self.save_world();
+                    }
+                    HandOver::FinalSave => return self.hand_over(),
+                }
+            }
+            Event::Tick => return self.check_idle(),
+            Event::TakeoverRequested(by) => return self.start_handover(by),
+            Event::TakeoverFailed => self.handover = HandOver::Idle,
+            Event::Stop => return self.stop_server(),
+            Event::McStopped => return self.upload_final_save(),
+            Event::Chat(msg) => return self.relay_chat(msg),
+            Event::ConsoleCommand(id, command) => return self.run_command(id, command),
+            Event::ConsoleDone(id, reply) => return self.return_output(id, reply),
+            Event::ConsoleDraft(text) => self.console_pane.draft = text,
+            Event::ConsoleSubmit => return self.send_command(),
+            Event::ConsoleReply(reply) => self.console_pane.push_reply(&reply),
+            Event::Refused(e) => {
+                self.errorbar.add(Error::Refused(e));
+                return self.release(false);
             }
-            Event::Tick => (),
         }
         Command::none()
     }
@@ -118,7 +259,7 @@ impl Page {
             let host_id = self.host_id;
             subs.push(world_upload::sub(self.rpc.clone(), id, host_id))
         }
-        if let Some(_) = self.refresh_time.active() {
+        if self.refresh_time.active().is_some() || self.idle.enabled() {
             let tick_event = |_| Msg::HostingPage(Event::Tick);
             let ticker = iced::time::every(Duration::from_secs(1)).map(tick_event);
             subs.push(ticker)
@@ -131,7 +272,7 @@ impl Page {
     }
 
     pub fn view(&mut self) -> Element<Msg> {
-        let sidebar = Space::with_width(Length::FillPortion(4));
+        let sidebar = self.console_pane.view();
         let left_spacer = Space::with_width(Length::FillPortion(1));
         let top_spacer = Space::with_height(Length::FillPortion(1));
         let bottom_spacer = Space::with_height(Length::FillPortion(1));
@@ -139,7 +280,9 @@ impl Page {
             .push(top_spacer)
             .push(title())
             .push(last_save(self.last_save))
+            .push(idle_timer(&self.idle, self.stopping))
             .push(self.uploading.view())
+            .push(stop_button(&mut self.stop, self.stopping))
             .push(bottom_spacer);
 
         let ui = Row::new()
@@ -162,6 +305,21 @@ fn title() -> Text {
         .horizontal_alignment(HorizontalAlignment::Center)
 }
 
+fn stop_button(state: &mut button::State, stopping: Stopping) -> Button<Msg> {
+    let (text, style) = match stopping {
+        Stopping::No => ("Stop hosting", style::Button::Clickable),
+        Stopping::AwaitExit => ("Stopping server", style::Button::Blocked),
+        Stopping::AwaitUpload | Stopping::FinalUpload => {
+            ("Uploading final save", style::Button::Blocked)
+        }
+    };
+    let button = Button::new(state, Text::new(text)).style(style);
+    match stopping {
+        Stopping::No => button.on_press(Msg::HostingPage(Event::Stop)),
+        _ => button,
+    }
+}
+
 fn elapsed(at: Instant) -> String {
     let elapsed = at.elapsed().as_secs();
     let min = elapsed / 60;
@@ -172,6 +330,24 @@ fn elapsed(at: Instant) -> String {
     }
 }
 
+/// when we stop hosting if no one joins
+fn idle_timer(idle: &Idle, stopping: Stopping) -> Text {
+    let text = match (idle.remaining(Instant::now()), stopping) {
+        (Some(left), Stopping::No) => {
+            let secs = left.as_secs();
+            format!(
+                "no one is playing, stopping in {}:{:02} minutes",
+                secs / 60,
+                secs % 60
+            )
+        }
+        _ => String::new(),
+    };
+    Text::new(text)
+        .width(Length::FillPortion(1))
+        .horizontal_alignment(HorizontalAlignment::Center)
+}
+
 fn last_save(at: Option<Instant>) -> Text {
     let text = match at {
         Some(instant) =>  format!("last save {}", elapsed(instant)),
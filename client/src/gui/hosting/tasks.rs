@@ -1,10 +1,17 @@
+use futures::future;
 use iced::Command;
-use tracing::{info, instrument};
-use wrapper::Line;
+use protocol::{CommandId, HostId, MAX_CRASH_REPORT_LEN};
+use shared::tarpc::context;
+use std::time::Instant;
+use tracing::{info, instrument, warn};
+use wrapper::{CrashReport, Line};
 
 use crate::gui::{hosting, RpcConn};
+use crate::world_upload::{self, snapshot};
+use crate::{server_path, snapshot_path};
 
-use super::{elapsed, Msg, Page};
+use super::idle::Action;
+use super::{elapsed, Error, HandOver, Msg, Page, Stopping};
 
 #[instrument(err)]
 pub async fn notify_conn_lost(
@@ -21,12 +28,269 @@ pub async fn notify_conn_lost(
     handle.say(msg).await
 }
 
+/// saves the world and copies it while the server can not write to it,
+/// uploads are made from the copy
 #[instrument(err)]
-pub async fn save(mut handle: wrapper::Handle) -> Result<(), wrapper::HandleError> {
-    handle.save().await
+pub async fn snapshot(mut handle: wrapper::Handle) -> Result<(), Error> {
+    let paused = Instant::now();
+    let res = copy_paused(&mut handle).await;
+    // also if turning saving off failed, it might have been turned off
+    // anyway
+    let resumed = handle.save_on().await;
+    info!("world writes were paused for {:?}", paused.elapsed());
+    res?;
+    resumed?;
+    Ok(())
+}
+
+async fn copy_paused(handle: &mut wrapper::Handle) -> Result<(), Error> {
+    handle.save_off().await?;
+    handle.save().await?;
+    copy_world().await
+}
+
+/// copy the world without pausing saving, only when the server is not
+/// running
+#[instrument(err)]
+pub async fn copy_world() -> Result<(), Error> {
+    let copied = snapshot::update(server_path().into(), snapshot_path().into())
+        .await
+        .map_err(world_upload::Error::from)?;
+    info!("copied {} changed files for upload", copied);
+    Ok(())
+}
+
+#[instrument(err)]
+pub async fn announce_handover(
+    mut handle: wrapper::Handle,
+    new_host: String,
+) -> Result<(), wrapper::HandleError> {
+    let msg = format!(
+        "{} is taking over hosting, saving the world before handing over",
+        new_host
+    );
+    handle.say(msg).await
+}
+
+#[instrument(err)]
+pub async fn say(mut handle: wrapper::Handle, msg: String) -> Result<(), wrapper::HandleError> {
+    handle.say(msg).await
+}
+
+/// the output of a command an admin ran, the rcon reply or the lines
+/// the server printed in response
+#[instrument]
+pub async fn console_command(mut handle: wrapper::Handle, command: String) -> Vec<String> {
+    match handle.command_output(command).await {
+        Ok(lines) => lines,
+        Err(e) => vec![format!("Could not run command: {}", e)],
+    }
+}
+
+#[instrument(err)]
+pub async fn command_from_host(
+    mut handle: wrapper::Handle,
+    command: String,
+) -> Result<Option<String>, wrapper::HandleError> {
+    handle.command(command.trim()).await
+}
+
+#[instrument(err)]
+pub async fn stop(mut handle: wrapper::Handle) -> Result<(), wrapper::HandleError> {
+    handle.stop().await
+}
+
+#[instrument(err)]
+pub async fn release_host(rpc: RpcConn, host_id: HostId, saved: bool) -> Result<(), Error> {
+    match rpc
+        .client
+        .release_host(context::current(), rpc.session, host_id, saved)
+        .await
+    {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(protocol::Error::NotHost)) => Err(Error::NotHost),
+        Ok(Err(e)) => Err(Error::Release(e)),
+        Err(_) => Err(Error::LostConn),
+    }
+}
+
+#[instrument(err, skip(lines))]
+pub async fn console_output(
+    rpc: RpcConn,
+    host_id: HostId,
+    command: CommandId,
+    lines: Vec<String>,
+) -> Result<(), Error> {
+    match rpc
+        .client
+        .console_output(context::current(), rpc.session, host_id, command, lines)
+        .await
+    {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(protocol::Error::NotHost)) => Err(Error::NotHost),
+        Ok(Err(e)) => Err(Error::Console(e)),
+        Err(_) => Err(Error::LostConn),
+    }
+}
+
+/// nothing to upload if the server crashed without writing a report
+#[instrument(err, skip(report))]
+pub async fn crash_report(
+    rpc: RpcConn,
+    host_id: HostId,
+    report: Option<CrashReport>,
+) -> Result<(), Error> {
+    let mut report = match report {
+        Some(report) => report,
+        None => return Ok(()),
+    };
+    // the stacktrace is at the start, keep that
+    if report.text.len() > MAX_CRASH_REPORT_LEN {
+        let mut end = MAX_CRASH_REPORT_LEN;
+        while !report.text.is_char_boundary(end) {
+            end -= 1;
+        }
+        report.text.truncate(end);
+    }
+    match rpc
+        .client
+        .crash_report(context::current(), rpc.session, host_id, report)
+        .await
+    {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(protocol::Error::NotHost)) => Err(Error::NotHost),
+        Ok(Err(e)) => Err(Error::CrashReport(e)),
+        Err(_) => Err(Error::LostConn),
+    }
+}
+
+#[instrument(err)]
+pub async fn hand_over(rpc: RpcConn, host_id: HostId) -> Result<(), Error> {
+    match rpc
+        .client
+        .hand_over(context::current(), rpc.session, host_id)
+        .await
+    {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(protocol::Error::NotHost)) => Err(Error::NotHost),
+        Ok(Err(e)) => Err(Error::HandOver(e)),
+        Err(_) => Err(Error::LostConn),
+    }
 }
 
 impl Page {
+    pub fn start_handover(&mut self, new_host: String) -> Command<Msg> {
+        info!(
+            "{} wants to take over, handing over after a final save",
+            new_host
+        );
+        let handle = self.mc_handle.clone();
+        let announce = Command::perform(announce_handover(handle, new_host), |e| match e {
+            Ok(_) => Msg::Empty,
+            Err(e) => Msg::HostingPage(hosting::Event::Error(e.into())),
+        });
+
+        if self.uploading_sub.active().is_some() {
+            self.handover = HandOver::Requested;
+            return announce;
+        }
+        self.handover = HandOver::FinalSave;
+        Command::batch([announce, self.save_world()])
+    }
+
+    pub fn hand_over(&mut self) -> Command<Msg> {
+        info!("final save uploaded, handing over");
+        // the server announces the new host through the event
+        // subscription, that ends our hosting session
+        Command::perform(hand_over(self.rpc.clone(), self.host_id), |e| match e {
+            Ok(_) => Msg::Empty,
+            Err(e) => Msg::HostingPage(hosting::Event::Error(e)),
+        })
+    }
+
+    pub fn stop_server(&mut self) -> Command<Msg> {
+        info!("stopping minecraft server");
+        self.stopping = Stopping::AwaitExit;
+        let handle = self.mc_handle.clone();
+        Command::perform(stop(handle), |e| match e {
+            Ok(_) => Msg::Empty,
+            Err(e) => Msg::HostingPage(hosting::Event::Error(e.into())),
+        })
+    }
+
+    /// the minecraft server saved the world while stopping, upload it
+    /// once any running upload is done
+    pub fn upload_final_save(&mut self) -> Command<Msg> {
+        if self.uploading_sub.active().is_some() {
+            self.stopping = Stopping::AwaitUpload;
+            Command::none()
+        } else {
+            self.stopping = Stopping::FinalUpload;
+            Command::perform(copy_world(), |e| match e {
+                Ok(_) => Msg::HostingPage(hosting::Event::Saved),
+                Err(e) => Msg::HostingPage(hosting::Event::Error(e)),
+            })
+        }
+    }
+
+    pub fn release(&mut self, saved: bool) -> Command<Msg> {
+        info!(
+            "minecraft server stopped, releasing host (saved: {})",
+            saved
+        );
+        // the server announces the shutdown through the event
+        // subscription, that ends our hosting session
+        let release = release_host(self.rpc.clone(), self.host_id, saved);
+        Command::perform(release, |e| match e {
+            Ok(_) => Msg::Empty,
+            Err(e) => Msg::HostingPage(hosting::Event::Error(e)),
+        })
+    }
+
+    /// shows and uploads the crash report, then restarts the minecraft
+    /// server or, if we are out of restarts, stops hosting
+    pub fn crashed(&mut self, error: wrapper::Error) -> Command<Msg> {
+        self.crashing = false;
+        let report = match &error {
+            wrapper::Error::Crashed { report, .. } => report.clone(),
+            _ => None,
+        };
+        if let Some(report) = &report {
+            self.console_pane.push_crash_report(report);
+        }
+        self.errorbar.add(error.into());
+        let upload = crash_report(self.rpc.clone(), self.host_id, report);
+
+        let can_restart = self.stopping == Stopping::No && self.handover == HandOver::Idle;
+        if can_restart && self.restarts < self.max_restarts {
+            self.restarts += 1;
+            warn!(
+                "minecraft server crashed, restarting it ({} of {})",
+                self.restarts, self.max_restarts
+            );
+            self.idle.restarted(Instant::now());
+            let upload = Command::perform(upload, |e| match e {
+                Ok(_) => Msg::Empty,
+                Err(e) => Msg::HostingPage(hosting::Event::Error(e)),
+            });
+            let restart = Command::perform(future::ready(()), |_| Msg::McRestart);
+            return Command::batch([upload, restart]);
+        }
+
+        // whatever it saved since the last upload may be corrupt
+        warn!("minecraft server crashed, releasing host without saving");
+        let (rpc, host_id) = (self.rpc.clone(), self.host_id);
+        // the report can only be uploaded while we are the host
+        let release = async move {
+            let uploaded = upload.await;
+            release_host(rpc, host_id, false).await.and(uploaded)
+        };
+        Command::perform(release, |e| match e {
+            Ok(_) => Msg::Empty,
+            Err(e) => Msg::HostingPage(hosting::Event::Error(e)),
+        })
+    }
+
     pub fn notify_conn_lost(&mut self) -> Command<Msg> {
         info!("saving world on schedual");
         let last_save = self.last_save;
@@ -37,24 +301,104 @@ impl Page {
         })
     }
 
-    pub fn save_world(&mut self) -> Command<Msg> {
-        info!("saving world on schedual");
+    pub fn relay_chat(&mut self, msg: protocol::ChatMessage) -> Command<Msg> {
         let handle = self.mc_handle.clone();
-        Command::perform(save(handle), |e| match e {
+        Command::perform(say(handle, msg.in_game_text()), |e| match e {
             Ok(_) => Msg::Empty,
             Err(e) => Msg::HostingPage(hosting::Event::Error(e.into())),
         })
     }
 
+    pub fn run_command(&mut self, id: CommandId, command: String) -> Command<Msg> {
+        info!("running console command for admin: {}", command);
+        let handle = self.mc_handle.clone();
+        Command::perform(console_command(handle, command), move |lines| {
+            Msg::HostingPage(hosting::Event::ConsoleDone(id, lines))
+        })
+    }
+
+    /// runs what the host typed in the console pane
+    pub fn send_command(&mut self) -> Command<Msg> {
+        let command = std::mem::take(&mut self.console_pane.draft);
+        if command.trim().is_empty() {
+            return Command::none();
+        }
+        let handle = self.mc_handle.clone();
+        Command::perform(command_from_host(handle, command), |e| match e {
+            Ok(Some(reply)) => Msg::HostingPage(hosting::Event::ConsoleReply(reply)),
+            Ok(None) => Msg::Empty,
+            Err(e) => Msg::HostingPage(hosting::Event::Error(Error::Command(e))),
+        })
+    }
+
+    pub fn return_output(&mut self, id: CommandId, lines: Vec<String>) -> Command<Msg> {
+        let returned = console_output(self.rpc.clone(), self.host_id, id, lines);
+        Command::perform(returned, |e| match e {
+            Ok(_) => Msg::Empty,
+            Err(e) => Msg::HostingPage(hosting::Event::Error(e)),
+        })
+    }
+
+    /// warns the players or stops hosting if no one played for too long
+    pub fn check_idle(&mut self) -> Command<Msg> {
+        if self.stopping != Stopping::No || self.handover != HandOver::Idle {
+            return Command::none();
+        }
+        let msg = match self.idle.check(Instant::now()) {
+            Action::None => return Command::none(),
+            Action::Warn(left) => format!(
+                "No one is playing, hosting stops in {} minutes unless someone joins",
+                (left.as_secs() + 59) / 60
+            ),
+            Action::Stop => {
+                info!("no one played for too long, stopping");
+                return self.stop_server();
+            }
+        };
+        let handle = self.mc_handle.clone();
+        Command::perform(say(handle, msg), |e| match e {
+            Ok(_) => Msg::Empty,
+            Err(e) => Msg::HostingPage(hosting::Event::Error(e.into())),
+        })
+    }
+
+    pub fn save_world(&mut self) -> Command<Msg> {
+        info!("saving world on schedual");
+        let handle = self.mc_handle.clone();
+        Command::perform(snapshot(handle), |e| match e {
+            Ok(_) => Msg::HostingPage(hosting::Event::Saved),
+            Err(e) => Msg::HostingPage(hosting::Event::Error(e)),
+        })
+    }
+
     pub fn handle_server_line(&mut self, line: Line, rpc: RpcConn) -> Command<Msg> {
         match line {
             Line {
-                msg: wrapper::Message::Saved,
+                msg: wrapper::Message::Crashing,
                 ..
             } => {
-                self.uploading_sub.start();
-                Command::none()
+                self.crashing = true;
+                super::mc::send_line(line, rpc, self.host_id)
             }
+            // a crashing server stops itself, once it exited we restart it
+            // or stop hosting
+            Line {
+                msg: wrapper::Message::Stopping,
+                ..
+            } if self.crashing => Command::none(),
+            Line {
+                msg: wrapper::Message::Stopping,
+                ..
+            } => {
+                // someone may have stopped the server from within the game
+                self.stopping = Stopping::AwaitExit;
+                super::mc::send_line(line, rpc, self.host_id)
+            }
+            // after a restart the server still sees us as up
+            Line {
+                msg: wrapper::Message::Loading(_) | wrapper::Message::DoneLoading(_),
+                ..
+            } if self.restarts > 0 => Command::none(),
             _ => super::mc::send_line(line, rpc, self.host_id),
         }
     }
@@ -1,15 +1,26 @@
 pub use crate::Event as Msg;
 use iced::{
-    button, Align, Button, Column, Element, HorizontalAlignment, Length, Row, Space, Text,
+    button, Align, Button, Column, Command, Element, HorizontalAlignment, Length, Row, Space,
+    Text,
 };
-use protocol::HostDetails;
+use protocol::{HostDetails, HostStatus, HostTimings};
+use std::time::Duration;
+use shared::tarpc::client::RpcError;
 
-use super::parts::Loading;
+use super::parts::{ClearError, ErrorBar, Loading};
 
 mod tasks;
+pub use tasks::fetch_status;
 
 #[derive(thiserror::Error, Debug, Clone, Eq, PartialEq, Hash)]
-pub enum Error {}
+pub enum Error {
+    #[error("Lost connection to worldsync server: {0:?}")]
+    NoMetaConn(#[from] RpcError),
+    #[error("Could not take over hosting: {0}")]
+    Takeover(protocol::Error),
+    #[error("The host did not hand over in time and is still hosting")]
+    TakeoverFailed,
+}
 
 impl From<protocol::Error> for Error {
     fn from(e: protocol::Error) -> Self {
@@ -19,6 +30,21 @@ impl From<protocol::Error> for Error {
 
 #[derive(Debug, Clone)]
 pub enum Event {
+    TakeoverRequested,
+    TakeoverFailed,
+    Status(Option<HostStatus>),
+    Players(Vec<String>),
+    PlayerJoined(String),
+    PlayerLeft(String),
+    Error(Error),
+    ClearError(Error),
+}
+
+impl ClearError for Event {
+    type Error = Error;
+    fn clear(e: Error) -> Self {
+        Self::ClearError(e)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -56,22 +82,61 @@ impl From<protocol::Event> for HostState {
 }
 
 pub struct Page {
+    errorbar: ErrorBar<Error>,
     pub host: HostDetails,
     pub host_state: HostState,
+    timings: HostTimings,
+    status: Option<HostStatus>,
+    /// minecraft names of the players on the host, sorted
+    players: Vec<String>,
     loading: Loading,
     copy: button::State,
+    take_over: button::State,
+    awaiting_handover: bool,
 }
 
 impl Page {
-    pub fn from(host: HostDetails, host_state: HostState) -> Self {
+    pub fn from(host: HostDetails, host_state: HostState, timings: HostTimings) -> Self {
         Self {
+            errorbar: ErrorBar::default(),
             host,
             host_state,
+            timings,
+            status: None,
+            players: Vec::new(),
             loading: Loading::default(),
             copy: button::State::default(),
+            take_over: button::State::default(),
+            awaiting_handover: false,
         }
     }
 
+    pub fn update(&mut self, event: Event) -> Command<Msg> {
+        match event {
+            Event::TakeoverRequested => self.awaiting_handover = true,
+            Event::TakeoverFailed => {
+                if self.awaiting_handover {
+                    self.errorbar.add(Error::TakeoverFailed);
+                }
+                self.awaiting_handover = false;
+            }
+            Event::Status(status) => self.status = status,
+            Event::Players(players) => self.players = players,
+            Event::PlayerJoined(name) => {
+                if let Err(i) = self.players.binary_search(&name) {
+                    self.players.insert(i, name)
+                }
+            }
+            Event::PlayerLeft(name) => self.players.retain(|p| *p != name),
+            Event::Error(e) => {
+                self.awaiting_handover = false;
+                self.errorbar.add(e);
+            }
+            Event::ClearError(e) => self.errorbar.clear(e),
+        }
+        Command::none()
+    }
+
     pub fn view(&mut self) -> Element<Msg> {
         let sidebar = Space::with_width(Length::FillPortion(4));
         let left_spacer = Space::with_width(Length::FillPortion(1));
@@ -82,7 +147,16 @@ impl Page {
             .width(Length::FillPortion(8))
             .push(top_spacer)
             .push(self.title())
+            .push(self.deadline())
+            .push(self.status())
+            .push(self.players())
             .push(copy_button(&mut self.copy))
+            .push(take_over_button(
+                &mut self.take_over,
+                &self.host_state,
+                self.awaiting_handover,
+                &self.host.name,
+            ))
             .push(self.loading.view())
             .push(bottom_spacer);
 
@@ -91,7 +165,12 @@ impl Page {
             .push(center_column)
             .push(sidebar);
 
-        Column::new().width(Length::Fill).push(ui).into()
+        let errorbar = self.errorbar.view().map(Msg::JoinPage);
+        Column::new()
+            .width(Length::Fill)
+            .push(errorbar)
+            .push(ui)
+            .into()
     }
 }
 
@@ -109,6 +188,99 @@ impl Page {
             .width(Length::FillPortion(1))
             .horizontal_alignment(HorizontalAlignment::Center)
     }
+
+    /// how long the server keeps waiting on the host in its current state
+    fn deadline(&self) -> Text {
+        use HostState::*;
+        let t = &self.timings;
+        let label = match self.host_state {
+            Loading(_) => format!(
+                "hosting is canceld if loading stalls for {}",
+                fmt_duration(t.loading)
+            ),
+            Running => String::new(),
+            ShuttingDown => format!(
+                "waiting up to {} for the final save",
+                fmt_duration(t.shutdown)
+            ),
+            Unreachable => format!(
+                "{} is dropped after being unreachable for {}",
+                self.host.name,
+                fmt_duration(t.unreachable)
+            ),
+        };
+
+        Text::new(label)
+            .width(Length::FillPortion(1))
+            .horizontal_alignment(HorizontalAlignment::Center)
+    }
+}
+
+impl Page {
+    /// what the hosts minecraft server last reported
+    fn status(&self) -> Text {
+        let label = match (&self.host_state, &self.status) {
+            (HostState::Running, Some(status)) => {
+                format!(
+                    "{}, {}/{} players, {} ms",
+                    status.version,
+                    status.online,
+                    status.max_players,
+                    status.latency.as_millis()
+                )
+            }
+            _ => String::new(),
+        };
+
+        Text::new(label)
+            .width(Length::FillPortion(1))
+            .horizontal_alignment(HorizontalAlignment::Center)
+    }
+
+    /// who is playing on the host right now
+    fn players(&self) -> Text {
+        let label = match (&self.host_state, self.players.as_slice()) {
+            (HostState::Loading(_), _) => String::new(),
+            (_, []) => "no one is playing".to_owned(),
+            (_, players) => format!("playing: {}", players.join(", ")),
+        };
+
+        Text::new(label)
+            .width(Length::FillPortion(1))
+            .horizontal_alignment(HorizontalAlignment::Center)
+    }
+}
+
+fn fmt_duration(dur: Duration) -> String {
+    let secs = dur.as_secs();
+    match (secs / 60, secs % 60) {
+        (0, s) => format!("{} seconds", s),
+        (m, 0) => format!("{} minutes", m),
+        (m, s) => format!("{}:{:02} minutes", m, s),
+    }
+}
+
+fn take_over_button<'a>(
+    state: &'a mut button::State,
+    host_state: &HostState,
+    awaiting_handover: bool,
+    host_name: &str,
+) -> Element<'a, Msg> {
+    if awaiting_handover {
+        let label = format!("Waiting for {} to hand over", host_name);
+        return Text::new(label)
+            .horizontal_alignment(HorizontalAlignment::Center)
+            .into();
+    }
+    let button = Button::new(
+        state,
+        Text::new("Take over").horizontal_alignment(HorizontalAlignment::Center),
+    );
+    match host_state {
+        HostState::Running => button.on_press(Msg::TakeOver),
+        _ => button,
+    }
+    .into()
 }
 
 fn copy_button(state: &mut button::State) -> Button<Msg> {
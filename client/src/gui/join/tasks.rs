@@ -0,0 +1,37 @@
+use iced::Command;
+use protocol::HostStatus;
+use shared::tarpc::context;
+use tracing::instrument;
+
+use super::{Error, Event, Msg};
+use crate::gui::RpcConn;
+
+#[instrument(err)]
+async fn host_status(rpc: RpcConn) -> Result<Option<HostStatus>, Error> {
+    rpc.client
+        .host_status(context::current(), rpc.session)
+        .await?
+        .map_err(|e| e.into())
+}
+
+#[instrument(err)]
+async fn players(rpc: RpcConn) -> Result<Vec<String>, Error> {
+    rpc.client
+        .players(context::current(), rpc.session)
+        .await?
+        .map_err(|e| e.into())
+}
+
+/// status updates are only send when the host is pinged and we only
+/// hear of players joining or leaving, get the current ones right away
+pub fn fetch_status(rpc: RpcConn) -> Command<Msg> {
+    let status = Command::perform(host_status(rpc.clone()), |res| match res {
+        Ok(status) => Msg::JoinPage(Event::Status(status)),
+        Err(err) => Msg::JoinPage(Event::Error(err)),
+    });
+    let players = Command::perform(players(rpc), |res| match res {
+        Ok(players) => Msg::JoinPage(Event::Players(players)),
+        Err(err) => Msg::JoinPage(Event::Error(err)),
+    });
+    Command::batch([status, players])
+}
@@ -1,5 +1,5 @@
 use crate::gui::{style, RpcConn};
-use protocol::HostState;
+use protocol::{HostState, HostTimings};
 pub use protocol::ServiceClient;
 use serde::{Deserialize, Serialize};
 use shared::tarpc;
@@ -8,6 +8,8 @@ use tracing::instrument;
 
 use super::{Error, Event, Msg, Page};
 use futures::future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use tokio::net::UdpSocket;
 use iced::Command;
 
 #[instrument(err)]
@@ -64,13 +66,33 @@ impl super::Inputs {
     }
 }
 
+/// the address of the interface we use to reach the worldsync server,
+/// this is our address on the local network
+async fn lan_ip(domain: &str, port: u16) -> Option<IpAddr> {
+    let unspecified: [IpAddr; 2] = [Ipv4Addr::UNSPECIFIED.into(), Ipv6Addr::UNSPECIFIED.into()];
+    for ip in unspecified {
+        let socket = match UdpSocket::bind((ip, 0)).await {
+            Ok(socket) => socket,
+            Err(_) => continue,
+        };
+        // connecting an udp socket sends nothing, it only picks a route
+        if socket.connect((domain, port)).await.is_err() {
+            continue;
+        }
+        if let Ok(addr) = socket.local_addr() {
+            return Some(addr.ip());
+        }
+    }
+    None
+}
+
 #[instrument(err, skip(password))]
 pub async fn login(
     domain: String,
     port: u16,
     username: String,
     password: String,
-) -> Result<(RpcConn, HostState), Error> {
+) -> Result<(RpcConn, HostState, HostTimings), Error> {
     let client = protocol::connect(&domain, port)
         .await
         .map_err(|e| e.kind())
@@ -87,8 +109,19 @@ pub async fn login(
     let session = client
         .log_in(context::current(), username, password)
         .await??;
-    let host = client.host(context::current(), session).await??;
-    Ok((RpcConn { client, session }, host))
+    let (host, timings) = client.host(context::current(), session).await??;
+    let lan_ip = lan_ip(&domain, port).await;
+    let tunnel = client
+        .tunnel_port(context::current())
+        .await?
+        .map(|tunnel_port| (domain, tunnel_port));
+    let rpc = RpcConn {
+        client,
+        session,
+        lan_ip,
+        tunnel,
+    };
+    Ok((rpc, host, timings))
 }
 
 impl Page {
@@ -103,7 +136,7 @@ impl Page {
                     self.inputs.password.value.clone(),
                 );
                 Command::perform(task, move |res| match res {
-                    Ok((rpc, host)) => Msg::LoggedIn(rpc, host),
+                    Ok((rpc, host, timings)) => Msg::LoggedIn(rpc, host, timings),
                     Err(err) => Msg::LoginPage(Event::Error(err)),
                 })
             }
@@ -1,9 +1,14 @@
-use crate::{events, mc, world_dl, Event};
+use crate::{events, mc, port_map, relay, world_dl, Event};
 use derivative::Derivative;
-use iced::{executor, Application, Clipboard, Command, Element, Subscription};
-use protocol::{HostState, ServiceClient, Uuid};
+use iced::{
+    executor, Application, Clipboard, Command, Container, Element, Length, Row, Subscription,
+};
+use protocol::{HostState, HostTimings, ServiceClient, Uuid};
+use std::net::IpAddr;
+use std::path::PathBuf;
 use tracing::{debug, info};
 
+pub mod chat;
 pub mod host;
 pub mod hosting;
 pub mod join;
@@ -20,6 +25,25 @@ pub struct RpcConn {
     #[derivative(Debug = "ignore")]
     pub client: ServiceClient,
     pub session: Uuid,
+    /// our address on the local network, if we could find it
+    pub lan_ip: Option<IpAddr>,
+    /// domain and port to open a relay tunnel to, if the server has a relay
+    pub tunnel: Option<(String, u16)>,
+}
+
+#[derive(Debug, Default)]
+pub struct Flags {
+    /// preferred port for the minecraft server
+    pub mc_port: Option<u16>,
+    /// have players connect through the relay of the server when hosting
+    pub relay: bool,
+    /// stop hosting when no one played for a while, never if none
+    pub idle: Option<hosting::IdlePolicy>,
+    /// times the minecraft server is restarted after crashing before we
+    /// stop hosting
+    pub restarts: u8,
+    /// java to prefer for the minecraft server
+    pub java: Option<PathBuf>,
 }
 
 pub struct State {
@@ -27,28 +51,45 @@ pub struct State {
     hosting: Option<hosting::Page>,
     can_host: Option<host::Page>,
     can_join: Option<join::Page>,
+    chat: Option<chat::Pane>,
     page: Page,
 
     rpc: Option<RpcConn>,
+    /// how long the server waits on a host, as of logging in
+    host_timings: HostTimings,
     server_events: bool,
     downloading_world: SubStatus,
     mc_server: SubStatus,
+    tunnel: SubStatus,
+    mc_port: Option<u16>,
+    relay: bool,
+    idle: Option<hosting::IdlePolicy>,
+    restarts: u8,
+    java: Option<PathBuf>,
 }
 
 impl State {
-    fn new() -> Self {
+    fn new(flags: Flags) -> Self {
         let db = tasks::open_settings();
         Self {
             login: login::Page::new(db.clone()),
             hosting: None,
             can_host: None,
             can_join: None,
+            chat: None,
             page: Page::Login,
 
             rpc: None,
+            host_timings: HostTimings::default(),
             server_events: false,
             downloading_world: SubStatus::default(),
             mc_server: SubStatus::default(),
+            tunnel: SubStatus::default(),
+            mc_port: flags.mc_port,
+            relay: flags.relay,
+            idle: flags.idle,
+            restarts: flags.restarts,
+            java: flags.java,
         }
     }
 }
@@ -74,10 +115,10 @@ type SubsList = Vec<Subscription<events::Event>>;
 impl Application for State {
     type Executor = executor::Default;
     type Message = Event;
-    type Flags = ();
+    type Flags = Flags;
 
-    fn new(_flags: ()) -> (State, Command<Event>) {
-        (State::new(), Command::none())
+    fn new(flags: Flags) -> (State, Command<Event>) {
+        (State::new(flags), Command::none())
     }
 
     fn title(&self) -> String {
@@ -98,15 +139,22 @@ impl Application for State {
             HostingPage(event) => {
                 return self.hosting.as_mut().unwrap().update(event);
             }
-            LoggedIn(rpc, host_state) => {
+            JoinPage(event) => return self.can_join().update(event),
+            Chat(event) => return self.chat.as_mut().unwrap().update(event),
+            LoggedIn(rpc, host_state, timings) => {
                 use HostState::*;
                 self.server_events = true;
-                self.can_host = Some(host::Page::from(rpc.clone()));
+                self.host_timings = timings;
+                self.can_host = Some(host::Page::from(rpc.clone(), self.mc_port, self.relay));
+                let chat = chat::Pane::from(rpc.clone());
+                let history = chat.fetch_history();
+                self.chat = Some(chat);
                 match host_state.clone() {
                     NoHost => {
                         info!("logged in, no one is hosting");
                         self.rpc = Some(rpc);
                         self.page = Page::Host;
+                        return history;
                     }
                     Loading(details)
                     | Up(details)
@@ -114,25 +162,46 @@ impl Application for State {
                     | ShuttingDown(details) => {
                         info!("logged in, can join {:?}", host_state);
                         self.rpc = Some(rpc);
-                        self.can_join = Some(join::Page::from(details, host_state.into()));
+                        self.can_join = Some(join::Page::from(details, host_state.into(), timings));
                         self.page = Page::Join;
+                        let status = join::fetch_status(self.unwrap_rpc());
+                        return Command::batch([status, history]);
                     }
                 }
             }
-            ClipHost => clipboard.write(self.can_join.as_ref().unwrap().host.addr.to_string()),
+            ClipHost => clipboard.write(self.can_join.as_ref().unwrap().host.server_address()),
+            TakeOver => return self.can_host().prepare(host::Goal::Takeover),
             WorldUpdated => {
+                self.downloading_world.stop();
                 self.mc_server.start();
                 return self
                     .can_host()
                     .update(host::Event::WorldUpdated);
             }
+            // the server restarted after a crash, keep hosting
+            McHandle(handle) if self.page == Page::Hosting => self.hosting().set_handle(handle),
             McHandle(handle) => {
                 self.hosting = Some(hosting::Page::from(
                     handle,
                     self.can_host().host_id.unwrap(),
                     self.unwrap_rpc().clone(),
+                    self.idle,
+                    self.restarts,
                 ))
             }
+            McRestart => {
+                self.mc_server.stop();
+                self.mc_server.start();
+            }
+            // ending the subscription stops the minecraft server
+            McRefused(e) => {
+                self.mc_server.stop();
+                match self.page {
+                    Page::Host => return self.can_host().update(host::Event::Refused(e)),
+                    Page::Hosting => return self.hosting().update(hosting::Event::Refused(e)),
+                    _ => panic!("should not recieve server events on other page"),
+                }
+            }
             Mc(event) => match self.page {
                 Page::Host => {
                     return self
@@ -148,6 +217,15 @@ impl Application for State {
                 }
                 _ => panic!("should not recieve server events on other page"),
             },
+            McStopped => match self.page {
+                Page::Host => {
+                    return self
+                        .can_host()
+                        .update(host::Event::Error(host::Error::McStopped))
+                }
+                Page::Hosting => return self.hosting().update(hosting::Event::McStopped),
+                _ => panic!("should not recieve server events on other page"),
+            },
             Server(event) => return self.handle_server_event(event),
             Error(crate::Error::NoMetaConn(e)) => match self.page {
                 Page::Hosting => {
@@ -182,19 +260,38 @@ impl Application for State {
             let rpc = self.unwrap_rpc().clone();
             subs.push(world_dl::sub(rpc, id))
         }
-        if let Some(_id) = self.mc_server.active() {
-            subs.push(mc::sub())
+        if let Some(id) = self.mc_server.active() {
+            let port = self.can_host.as_ref().unwrap().port.unwrap();
+            subs.push(mc::sub(port, id, self.java.clone()))
+        }
+        if let Some(_id) = self.tunnel.active() {
+            let can_host = self.can_host.as_ref().unwrap();
+            let rpc = self.unwrap_rpc();
+            subs.push(relay::sub(
+                rpc,
+                can_host.host_id.unwrap(),
+                can_host.port.unwrap(),
+            ))
+        }
+        let mapping = self.can_host.as_ref().and_then(|p| p.mapping.clone());
+        if let Some(mapping) = mapping {
+            subs.push(port_map::sub(mapping))
         }
 
         Subscription::batch(subs)
     }
 
     fn view(&mut self) -> Element<Event> {
-        match self.page {
-            Page::Login => self.login.view(),
-            Page::Join => self.can_join().view(),
-            Page::Host => self.can_host().view(),
-            Page::Hosting => self.hosting().view(),
-        }
+        let page = match self.page {
+            Page::Login => return self.login.view(),
+            Page::Join => self.can_join.as_mut().unwrap().view(),
+            Page::Host => self.can_host.as_mut().unwrap().view(),
+            Page::Hosting => self.hosting.as_mut().unwrap().view(),
+        };
+        let chat = self.chat.as_mut().unwrap().view();
+        Row::new()
+            .push(Container::new(page).width(Length::FillPortion(2)))
+            .push(chat)
+            .into()
     }
 }
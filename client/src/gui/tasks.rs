@@ -68,8 +68,29 @@ impl State {
         self.rpc.as_ref().unwrap().clone()
     }
 
+    /// tear down what is left of our hosting session, if any
+    fn stop_hosting(&mut self) -> Command<Event> {
+        let mapping = self.can_host().mapping.take();
+        if self.tunnel.active().is_some() {
+            self.tunnel.stop();
+        }
+        if self.hosting.take().is_some() {
+            info!("no longer hosting, stopping minecraft server");
+            self.mc_server.stop();
+            self.can_host = Some(super::host::Page::from(
+                self.unwrap_rpc(),
+                self.mc_port,
+                self.relay,
+            ));
+        }
+        match mapping {
+            Some(mapping) => gui::host::remove_mapping(mapping),
+            None => Command::none(),
+        }
+    }
+
     pub fn handle_server_event(&mut self, event: protocol::Event) -> Command<Event> {
-        use super::{host, join, Page};
+        use super::{chat, host, hosting, join, Page};
         use protocol::Event::*;
 
         match event {
@@ -80,19 +101,75 @@ impl State {
             }
             HostLoaded if self.page == Page::Host => {
                 self.page = Page::Hosting;
+                self.hosting().loaded();
             }
             NewHost(host) => match self.can_host().is_us(&host) {
                 true => {
                     info!("attempting to host");
                     self.downloading_world.start();
+                    if self.can_host().relay {
+                        self.tunnel.start();
+                    }
+                    // we might have taken over from the join page
+                    self.page = Page::Host;
                 }
                 false => {
                     info!("got new host: {:?}", host);
-                    self.can_join = Some(join::Page::from(host, join::HostState::Loading(0)));
+                    let stopped = self.stop_hosting();
+                    let state = join::HostState::Loading(0);
+                    self.can_join = Some(join::Page::from(host, state, self.host_timings));
                     self.page = Page::Join;
+                    return stopped;
                 }
             },
-            HostDropped | HostCanceld | HostShutdown => self.page = dbg!(Page::Host),
+            TakeoverRequested(by) if self.page == Page::Hosting => {
+                return self.hosting().update(hosting::Event::TakeoverRequested(by))
+            }
+            TakeoverRequested(by) => info!("{} wants to take over hosting", by),
+            TakeoverFailed if self.page == Page::Hosting => {
+                return self.hosting().update(hosting::Event::TakeoverFailed)
+            }
+            TakeoverFailed => {
+                if let Some(p) = self.can_join.as_mut() {
+                    return p.update(join::Event::TakeoverFailed);
+                }
+            }
+            HostStatus(status) => {
+                if let Some(p) = self.can_join.as_mut() {
+                    return p.update(join::Event::Status(Some(status)));
+                }
+            }
+            PlayerJoined(name) => {
+                if let Some(p) = self.can_join.as_mut() {
+                    return p.update(join::Event::PlayerJoined(name));
+                }
+            }
+            PlayerLeft(name) => {
+                if let Some(p) = self.can_join.as_mut() {
+                    return p.update(join::Event::PlayerLeft(name));
+                }
+            }
+            Chat(msg) => {
+                let mut relay = Command::none();
+                if let (false, Some(p)) = (msg.in_game, self.hosting.as_mut()) {
+                    relay = p.update(hosting::Event::Chat(msg.clone()));
+                }
+                let shown = self
+                    .chat
+                    .as_mut()
+                    .unwrap()
+                    .update(chat::Event::Received(msg));
+                return Command::batch([relay, shown]);
+            }
+            ConsoleCommand { id, command } => {
+                if let Some(p) = self.hosting.as_mut() {
+                    return p.update(hosting::Event::ConsoleCommand(id, command));
+                }
+            }
+            HostDropped | HostCanceld | HostShutdown => {
+                self.page = Page::Host;
+                return self.stop_hosting();
+            }
             #[cfg(not(feature = "deployed"))]
             TestHB(n) => info!("recieved hb {}", n),
             _e => if let Some(p) = self.can_join.as_mut() {
@@ -2,6 +2,9 @@ mod error;
 mod events;
 pub mod gui;
 pub mod mc;
+mod port_map;
+mod relay;
+mod server_settings;
 mod world_dl;
 mod world_upload;
 use std::path::Path;
@@ -13,6 +16,10 @@ pub use world_dl::WorldDl;
 pub fn server_path() -> &'static Path {
     Path::new("worldsync/mc_server")
 }
+/// copy of the server folder that uploads are made from
+pub fn snapshot_path() -> &'static Path {
+    Path::new("worldsync/snapshot")
+}
 pub fn db_path() -> &'static Path {
     Path::new("worldsync/db")
 }
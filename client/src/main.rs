@@ -1,5 +1,7 @@
 use client::{gui, log_path};
 use iced::Application;
+use std::path::PathBuf;
+use std::time::Duration;
 #[cfg(not(feature = "deployed"))]
 use tracing::warn;
 use structopt::StructOpt;
@@ -10,6 +12,30 @@ struct Opt {
     /// Verbosity of the logging, options: TRACE, DEBUG, INFO, WARN or ERROR
     #[structopt(name = "log", default_value = "INFO")]
     log_level: shared::LogLevel,
+    /// Port to run the minecraft server on when hosting, if it is not
+    /// set or not free another free port is used
+    #[structopt(long)]
+    mc_port: Option<u16>,
+    /// Have players connect through the relay of the worldsync server
+    /// when hosting, for when you can not forward ports
+    #[structopt(long)]
+    relay: bool,
+    /// Minutes without players after which we stop hosting, by default
+    /// 0: keep hosting until stopped by hand
+    #[structopt(long, default_value = "0")]
+    idle_shutdown: u64,
+    /// Minutes before stopping an idle server that we warn in chat
+    #[structopt(long, default_value = "5")]
+    idle_warning: u64,
+    /// Times to restart the minecraft server when it crashes before we
+    /// stop hosting, 0 to stop hosting on the first crash
+    #[structopt(long, default_value = "2")]
+    restarts: u8,
+    /// Java to run the minecraft server with, the java binary or the
+    /// folder it is installed in. If it is not set or too old for the
+    /// server another installed java is used
+    #[structopt(long)]
+    java: Option<PathBuf>,
 }
 
 pub fn main() -> iced::Result {
@@ -21,7 +47,20 @@ pub fn main() -> iced::Result {
 
     println!("{}", protocol::current_version());
 
-    let mut settings = iced::Settings::default();
+    let idle = match opt.idle_shutdown {
+        0 => None,
+        mins => Some(gui::hosting::IdlePolicy {
+            shutdown_after: Duration::from_secs(mins * 60),
+            warn_before: Duration::from_secs(opt.idle_warning * 60),
+        }),
+    };
+    let mut settings = iced::Settings::with_flags(gui::Flags {
+        mc_port: opt.mc_port,
+        relay: opt.relay,
+        idle,
+        restarts: opt.restarts,
+        java: opt.java,
+    });
     settings.window.size = (500, 400);
     gui::State::run(settings)
 }
@@ -1,33 +1,45 @@
 use std::hash::{Hash, Hasher};
-use std::path::Path;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use futures::stream::{self, BoxStream};
 use iced::Command;
 use protocol::HostId;
 use shared::tarpc::context::Context;
-use tracing::info;
-use wrapper::Instance;
+use tracing::{info, warn};
+use wrapper::{Config, Instance, Line, Message};
 
 use crate::gui::RpcConn;
 use crate::{Event, server_path};
 
 // pub mod server;
-pub fn sub() -> iced::Subscription<Event> {
-    iced::Subscription::from_recipe(McServer {})
+pub fn sub(port: u16, count: usize, java: Option<PathBuf>) -> iced::Subscription<Event> {
+    iced::Subscription::from_recipe(McServer { port, count, java })
 }
 
-pub struct McServer {}
+pub struct McServer {
+    port: u16,
+    java: Option<PathBuf>,
+    /// a new count starts a new server, for example after a crash
+    count: usize,
+}
 
 #[derive(Debug)]
 enum Phase {
     Start,
     Running,
+    /// the server printed it is stopping, wait for it to exit
+    Stopping,
     Error,
+    Stopped,
 }
 
 struct State {
     phase: Phase,
+    port: u16,
+    java: Option<PathBuf>,
     instance: Option<Instance>,
 }
 
@@ -39,6 +51,7 @@ where
 
     fn hash(&self, state: &mut H) {
         struct Marker;
+        self.count.hash(state);
         std::any::TypeId::of::<Marker>().hash(state);
     }
 
@@ -46,6 +59,8 @@ where
         Box::pin(stream::unfold(
             State {
                 phase: Phase::Start,
+                port: self.port,
+                java: self.java,
                 instance: None,
             },
             move |state| async move { state_machine(state).await },
@@ -53,11 +68,47 @@ where
     }
 }
 
+/// the preferred port, or the default minecraft port if none is preferred.
+/// Falls back to any free port if that port is taken
+pub fn pick_port(preferred: Option<u16>) -> u16 {
+    let port = preferred.unwrap_or(25565);
+    if TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).is_ok() {
+        return port;
+    }
+    let free = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|l| l.local_addr())
+        .expect("could not find free port")
+        .port();
+    warn!("port {} is taken, using port {} instead", port, free);
+    free
+}
+
+/// sets the port and enables rcon in the `server.properties` file,
+/// properties missing from it get our defaults
+async fn configure(port: u16) -> Result<(), std::io::Error> {
+    let dir = server_path();
+    let mut config = Config::default();
+    match Config::read(dir).await {
+        Ok(existing) => config.merge(&existing),
+        Err(e) if e.kind() == ErrorKind::NotFound => (),
+        Err(e) => return Err(e),
+    }
+    // run commands over rcon so they get a reply
+    let rcon_port = pick_port(Some(25575));
+    config.set_port(port).set_rcon(rcon_port).write(dir).await
+}
+
 async fn start(mut state: State) -> (Event, State) {
-    info!("starting minecraft server");
-    match Instance::start(Path::new(server_path()), 2).await {
+    use crate::gui::host::{Error as hError, Event as hEvent};
+    info!("starting minecraft server on port: {}", state.port);
+    if let Err(e) = configure(state.port).await {
+        let event = Event::HostPage(hEvent::Error(hError::Config(e.kind())));
+        state.phase = Phase::Error;
+        return (event, state);
+    }
+
+    match Instance::start(Path::new(server_path()), 2, state.java.as_deref()).await {
         Err(e) => {
-            use crate::gui::host::Event as hEvent;
             let event = Event::HostPage(hEvent::Error(e.into()));
             state.phase = Phase::Error;
             (event, state)
@@ -74,14 +125,42 @@ async fn start(mut state: State) -> (Event, State) {
 
 async fn forward_events(mut state: State) -> (Event, State) {
     let res = state.instance.as_mut().unwrap().next_event().await;
+    match res {
+        Ok(Line {
+            msg: Message::Stopping,
+            ..
+        }) => state.phase = Phase::Stopping,
+        Err(wrapper::Error::Stopped { code }) => {
+            info!("minecraft server exited, exit code: {:?}", code);
+            state.phase = Phase::Stopped;
+            return (Event::McStopped, state);
+        }
+        Err(wrapper::Error::Crashed { .. }) => state.phase = Phase::Stopped,
+        _ => (),
+    }
     (Event::Mc(res), state)
 }
 
+async fn wait_for_exit(mut state: State) -> (Event, State) {
+    let instance = state.instance.as_mut().unwrap();
+    match instance.stop(wrapper::STOP_TIMEOUT).await {
+        Ok(status) => info!("minecraft server exited, status: {}", status),
+        Err(e @ wrapper::Error::Crashed { .. }) => {
+            state.phase = Phase::Stopped;
+            return (Event::Mc(Err(e)), state);
+        }
+        Err(e) => warn!("minecraft server did not stop cleanly: {:?}", e),
+    }
+    state.phase = Phase::Stopped;
+    (Event::McStopped, state)
+}
+
 async fn state_machine(state: State) -> Option<(Event, State)> {
     match &state.phase {
         Phase::Start => Some(start(state).await),
         Phase::Running => Some(forward_events(state).await),
-        Phase::Error => None,
+        Phase::Stopping => Some(wait_for_exit(state).await),
+        Phase::Error | Phase::Stopped => None,
     }
 }
 
@@ -96,8 +175,9 @@ async fn send(line: wrapper::parser::Line, rpc: RpcConn, host_id: HostId) -> cra
     match res {
         Ok(Ok(_)) => Event::Empty,
         Ok(Err(protocol::Error::NotHost)) => Event::HostingPage(hEvent::Error(hError::NotHost)),
+        Ok(Err(e @ protocol::Error::VersionMismatch { .. })) => Event::McRefused(e),
+        Ok(Err(e)) => Event::HostingPage(hEvent::Error(hError::PubLine(e))),
         Err(e) => Event::Error(e.into()),
-        e_ => panic!("unexpected error: {:?}", e_),
     }
 }
 
@@ -0,0 +1,201 @@
+//! Asks the router of the local network to forward the minecraft port to
+//! us. We try NAT-PMP first as it is simple and quick to fail, then UPnP.
+//! Routers supporting neither need the port forwarded by hand, or the
+//! relay of the worldsync server.
+
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use futures::stream::{self, BoxStream};
+use tracing::{debug, info, warn};
+
+use crate::Event;
+
+mod natpmp;
+mod upnp;
+
+pub use natpmp::Refusal;
+
+/// how long we ask the router to keep a mapping, we renew it halfway
+pub const LIFETIME: Duration = Duration::from_secs(2 * 60 * 60);
+
+#[derive(thiserror::Error, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Error {
+    #[error("Do not know our address on the local network")]
+    NoLanIp,
+    #[error("Router did not answer, it may not support NAT-PMP or UPnP")]
+    NoAnswer,
+    #[error("Io error talking to the router: {0:?}")]
+    Io(io::ErrorKind),
+    #[error("Router refused the port mapping: {0}")]
+    NatPmp(Refusal),
+    #[error("Router refused the port mapping: {description} (UPnP error {code})")]
+    Upnp { code: u16, description: String },
+    #[error("Router send an answer we do not understand")]
+    Malformed,
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e.kind())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Gateway {
+    NatPmp(SocketAddr),
+    Upnp(upnp::Service),
+}
+
+/// a port the router forwards to us
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    /// where players outside the local network can reach us
+    pub external: SocketAddr,
+    pub internal_port: u16,
+    /// zero if the router only does permanent mappings
+    lifetime: Duration,
+    gateway: Gateway,
+}
+
+impl Mapping {
+    pub async fn renew(&mut self) -> Result<(), Error> {
+        match &self.gateway {
+            Gateway::NatPmp(gateway) => {
+                let (port, lifetime) = natpmp::request_mapping(
+                    *gateway,
+                    self.internal_port,
+                    self.external.port(),
+                    LIFETIME,
+                )
+                .await?;
+                self.external.set_port(port);
+                self.lifetime = lifetime;
+            }
+            Gateway::Upnp(service) => {
+                self.lifetime = service
+                    .add_mapping(self.external.port(), self.internal_port, LIFETIME)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn remove(self) -> Result<(), Error> {
+        match self.gateway {
+            Gateway::NatPmp(gateway) => {
+                natpmp::request_mapping(gateway, self.internal_port, 0, Duration::ZERO).await?;
+            }
+            Gateway::Upnp(service) => service.delete_mapping(self.external.port()).await?,
+        }
+        Ok(())
+    }
+}
+
+/// the default route of the system, or the first address on the lan as
+/// most home routers use that
+fn gateway(lan_ip: Ipv4Addr) -> Ipv4Addr {
+    fs::read_to_string("/proc/net/route")
+        .ok()
+        .and_then(|table| default_route(&table))
+        .unwrap_or_else(|| {
+            let [a, b, c, _] = lan_ip.octets();
+            Ipv4Addr::new(a, b, c, 1)
+        })
+}
+
+fn default_route(table: &str) -> Option<Ipv4Addr> {
+    table.lines().skip(1).find_map(|line| {
+        let mut fields = line.split_whitespace().skip(1);
+        let destination = fields.next()?;
+        let gateway = fields.next()?;
+        if destination != "00000000" {
+            return None;
+        }
+        // the kernel lists addresses as little endian hex
+        let gateway = u32::from_str_radix(gateway, 16).ok()?;
+        Some(Ipv4Addr::from(gateway.swap_bytes()))
+    })
+}
+
+/// Asks the router to forward port to us, lan_ip is our address on the
+/// local network.
+pub async fn map(port: u16, lan_ip: Option<IpAddr>) -> Result<Mapping, Error> {
+    let lan_ip = match lan_ip {
+        Some(IpAddr::V4(ip)) => ip,
+        _ => return Err(Error::NoLanIp),
+    };
+
+    let gateway = SocketAddr::from((gateway(lan_ip), natpmp::PORT));
+    let natpmp_err = match natpmp::map(gateway, port, LIFETIME).await {
+        Ok(mapping) => return Ok(mapping),
+        Err(e) => {
+            debug!("NAT-PMP mapping failed: {}, trying UPnP", e);
+            e
+        }
+    };
+    match upnp::map(upnp::SSDP, lan_ip, port, LIFETIME).await {
+        // the router only speaks NAT-PMP, why it refused is the real reason
+        Err(Error::NoAnswer) if matches!(natpmp_err, Error::NatPmp(_)) => Err(natpmp_err),
+        res => res,
+    }
+}
+
+/// keeps the router from forgetting the mapping while we host
+pub fn sub(mapping: Mapping) -> iced::Subscription<Event> {
+    iced::Subscription::from_recipe(Renewal { mapping })
+}
+
+pub struct Renewal {
+    mapping: Mapping,
+}
+
+/// renews the mapping halfway through its lifetime, never produces an
+/// event
+async fn keep_mapped(mut mapping: Mapping) -> Event {
+    loop {
+        if mapping.lifetime.is_zero() {
+            info!("router mapped port permanently, no need to renew");
+            futures::future::pending::<()>().await;
+        }
+        tokio::time::sleep(mapping.lifetime / 2).await;
+        match mapping.renew().await {
+            Ok(_) => debug!("renewed port mapping: {:?}", mapping.external),
+            Err(e) => warn!("could not renew port mapping: {}", e),
+        }
+    }
+}
+
+impl<H, I> iced_native::subscription::Recipe<H, I> for Renewal
+where
+    H: Hasher,
+{
+    type Output = Event;
+
+    fn hash(&self, state: &mut H) {
+        struct Marker;
+        std::any::TypeId::of::<Marker>().hash(state);
+        self.mapping.external.hash(state);
+    }
+
+    fn stream(self: Box<Self>, _input: BoxStream<'static, I>) -> BoxStream<'static, Self::Output> {
+        Box::pin(stream::once(keep_mapped(self.mapping)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_default_route() {
+        let table = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+                     eth0\t0002A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\n\
+                     eth0\t00000000\t0102A8C0\t0003\t0\t0\t100\t00000000\n";
+        assert_eq!(default_route(table), Some(Ipv4Addr::new(192, 168, 2, 1)));
+        assert_eq!(default_route("Iface\tDestination\tGateway\n"), None);
+    }
+}
@@ -0,0 +1,209 @@
+//! NAT Port Mapping Protocol, RFC 6886
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+use super::{Error, Gateway, Mapping};
+
+pub const PORT: u16 = 5351;
+const OP_EXTERNAL_ADDR: u8 = 0;
+const OP_MAP_TCP: u8 = 2;
+/// the rfc starts at 250 ms and doubles each try, we give up sooner than
+/// it suggests as UPnP is still left to try
+const TRIES: usize = 4;
+const FIRST_WAIT: Duration = Duration::from_millis(250);
+
+/// why the router refused a request, the result codes of section 3.5
+#[derive(thiserror::Error, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Refusal {
+    #[error("it does not support this version of NAT-PMP")]
+    UnsupportedVersion,
+    #[error("port mapping is disabled on it")]
+    NotAuthorized,
+    #[error("it is not connected to the internet")]
+    NetworkFailure,
+    #[error("it has no ports left to map")]
+    OutOfResources,
+    #[error("it does not support the request")]
+    UnsupportedOpcode,
+    #[error("NAT-PMP result code {0}")]
+    Other(u16),
+}
+
+impl From<u16> for Refusal {
+    fn from(code: u16) -> Self {
+        match code {
+            1 => Refusal::UnsupportedVersion,
+            2 => Refusal::NotAuthorized,
+            3 => Refusal::NetworkFailure,
+            4 => Refusal::OutOfResources,
+            5 => Refusal::UnsupportedOpcode,
+            code => Refusal::Other(code),
+        }
+    }
+}
+
+/// sends the request until the gateway answers, returns the answer once
+/// it is valid and successful
+async fn request(gateway: SocketAddr, req: &[u8], len: usize) -> Result<Vec<u8>, Error> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect(gateway).await?;
+
+    let mut wait = FIRST_WAIT;
+    let mut buf = [0u8; 16];
+    for _ in 0..TRIES {
+        socket.send(req).await?;
+        match timeout(wait, socket.recv(&mut buf)).await {
+            Ok(res) => return check(&buf[..res?], req[1], len),
+            Err(_) => wait *= 2,
+        }
+    }
+    Err(Error::NoAnswer)
+}
+
+/// answers that refuse the request are cut short after the header
+fn check(resp: &[u8], op: u8, len: usize) -> Result<Vec<u8>, Error> {
+    if resp.len() < 8 || resp[0] != 0 || resp[1] != op + 128 {
+        return Err(Error::Malformed);
+    }
+    match u16::from_be_bytes([resp[2], resp[3]]) {
+        0 if resp.len() == len => Ok(resp.to_vec()),
+        0 => Err(Error::Malformed),
+        code => Err(Error::NatPmp(code.into())),
+    }
+}
+
+async fn external_addr(gateway: SocketAddr) -> Result<Ipv4Addr, Error> {
+    let resp = request(gateway, &[0, OP_EXTERNAL_ADDR], 12).await?;
+    Ok(Ipv4Addr::new(resp[8], resp[9], resp[10], resp[11]))
+}
+
+/// Asks the gateway to forward external to internal for lifetime, a zero
+/// lifetime and external port remove the mapping. Returns the port and
+/// lifetime the gateway granted.
+pub async fn request_mapping(
+    gateway: SocketAddr,
+    internal: u16,
+    external: u16,
+    lifetime: Duration,
+) -> Result<(u16, Duration), Error> {
+    let mut req = vec![0, OP_MAP_TCP, 0, 0];
+    req.extend_from_slice(&internal.to_be_bytes());
+    req.extend_from_slice(&external.to_be_bytes());
+    req.extend_from_slice(&(lifetime.as_secs() as u32).to_be_bytes());
+
+    let resp = request(gateway, &req, 16).await?;
+    let port = u16::from_be_bytes([resp[10], resp[11]]);
+    let lifetime = u32::from_be_bytes([resp[12], resp[13], resp[14], resp[15]]);
+    Ok((port, Duration::from_secs(lifetime as u64)))
+}
+
+pub async fn map(gateway: SocketAddr, port: u16, lifetime: Duration) -> Result<Mapping, Error> {
+    let ip = external_addr(gateway).await?;
+    let (external, lifetime) = request_mapping(gateway, port, port, lifetime).await?;
+    Ok(Mapping {
+        external: (ip, external).into(),
+        internal_port: port,
+        lifetime,
+        gateway: Gateway::NatPmp(gateway),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    const EXTERNAL: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 7);
+
+    /// mapped ports by internal port
+    type Mappings = Arc<Mutex<HashMap<u16, u16>>>;
+
+    /// answers like a router that maps internal ports 1000 higher
+    async fn mock_router() -> (SocketAddr, Mappings) {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let mappings = Mappings::default();
+        let mapped = mappings.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 12];
+            loop {
+                let (n, peer) = socket.recv_from(&mut buf).await.unwrap();
+                let mut resp = vec![0, buf[1] + 128, 0, 0, 0, 0, 0, 1];
+                match (buf[1], n) {
+                    (OP_EXTERNAL_ADDR, 2) => resp.extend_from_slice(&EXTERNAL.octets()),
+                    (OP_MAP_TCP, 12) => {
+                        let internal = u16::from_be_bytes([buf[4], buf[5]]);
+                        let lifetime = &buf[8..12];
+                        let external = match lifetime {
+                            [0, 0, 0, 0] => mapped.lock().unwrap().remove(&internal).unwrap(),
+                            _ => *mapped
+                                .lock()
+                                .unwrap()
+                                .entry(internal)
+                                .or_insert(internal + 1000),
+                        };
+                        resp.extend_from_slice(&buf[4..6]);
+                        resp.extend_from_slice(&external.to_be_bytes());
+                        resp.extend_from_slice(lifetime);
+                    }
+                    // unsupported opcode
+                    _ => resp[3] = 5,
+                }
+                socket.send_to(&resp, peer).await.unwrap();
+            }
+        });
+        (addr, mappings)
+    }
+
+    #[tokio::test]
+    async fn maps_and_removes() {
+        let (router, mappings) = mock_router().await;
+        let mut mapping = map(router, 25565, Duration::from_secs(60)).await.unwrap();
+        assert_eq!(mapping.external, SocketAddr::from((EXTERNAL, 26565)));
+        assert_eq!(mapping.lifetime, Duration::from_secs(60));
+        assert_eq!(mappings.lock().unwrap().get(&25565), Some(&26565));
+
+        mapping.renew().await.unwrap();
+        assert_eq!(mapping.external.port(), 26565);
+        assert_eq!(mapping.lifetime, super::super::LIFETIME);
+
+        mapping.remove().await.unwrap();
+        assert!(mappings.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn no_router() {
+        // nothing listens here, the os may tell us or we time out
+        let socket = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        drop(socket);
+        let err = map(addr, 25565, Duration::from_secs(60)).await.unwrap_err();
+        assert!(matches!(err, Error::NoAnswer | Error::Io(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn result_code() {
+        assert_eq!(
+            check(&[0, 130, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 2, 16),
+            Err(Error::NatPmp(Refusal::NetworkFailure))
+        );
+        assert_eq!(
+            check(&[0, 130, 0, 2, 0, 0, 0, 9], 2, 16),
+            Err(Error::NatPmp(Refusal::NotAuthorized))
+        );
+        assert_eq!(
+            check(&[0, 128, 0, 9, 0, 0, 0, 9], 0, 12),
+            Err(Error::NatPmp(Refusal::Other(9)))
+        );
+        assert_eq!(check(&[0, 2, 0, 0], 2, 16), Err(Error::Malformed));
+        assert_eq!(
+            check(&[0, 130, 0, 0, 0, 0, 0, 9], 2, 16),
+            Err(Error::Malformed)
+        );
+    }
+}
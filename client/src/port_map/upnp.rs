@@ -0,0 +1,405 @@
+//! UPnP Internet Gateway Device port mapping. We find the router using
+//! SSDP, then talk SOAP to its WAN connection service. The documents are
+//! small and regular enough to pick apart without an xml parser.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::timeout;
+use tracing::debug;
+
+use super::{Error, Gateway, Mapping};
+
+pub const SSDP: SocketAddr = SocketAddr::new(
+    std::net::IpAddr::V4(Ipv4Addr::new(239, 255, 255, 250)),
+    1900,
+);
+const SEARCH_TARGET: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+/// services that can map ports, in order of preference
+const SERVICES: [&str; 3] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+const TIMEOUT: Duration = Duration::from_secs(3);
+/// the router only supports permanent mappings
+const ONLY_PERMANENT_LEASE: u16 = 725;
+
+/// a service on the router we can ask to map ports
+#[derive(Debug, Clone)]
+pub struct Service {
+    addr: SocketAddr,
+    control_path: String,
+    service_type: &'static str,
+    lan_ip: Ipv4Addr,
+}
+
+/// the content of the first element called name
+fn tag<'a>(doc: &'a str, name: &str) -> Option<&'a str> {
+    let start = doc.find(&format!("<{}>", name))? + name.len() + 2;
+    let len = doc[start..].find(&format!("</{}>", name))?;
+    Some(doc[start..start + len].trim())
+}
+
+/// splits an url into address and path, routers use ip addresses in
+/// their urls
+fn parse_url(url: &str) -> Option<(SocketAddr, String)> {
+    let url = url.strip_prefix("http://")?;
+    let (host, path) = match url.find('/') {
+        Some(i) => url.split_at(i),
+        None => (url, "/"),
+    };
+    let addr = match host.parse() {
+        Ok(addr) => addr,
+        Err(_) => SocketAddr::new(host.parse().ok()?, 80),
+    };
+    Some((addr, path.to_owned()))
+}
+
+/// the description url of the first gateway to answer our search
+async fn discover(ssdp: SocketAddr) -> Result<(SocketAddr, String), Error> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\n\
+         HOST: 239.255.255.250:1900\r\n\
+         MAN: \"ssdp:discover\"\r\n\
+         MX: 2\r\n\
+         ST: {}\r\n\r\n",
+        SEARCH_TARGET
+    );
+    socket.send_to(search.as_bytes(), ssdp).await?;
+
+    let mut buf = [0u8; 2048];
+    loop {
+        let n = match timeout(TIMEOUT, socket.recv(&mut buf)).await {
+            Ok(res) => res?,
+            Err(_) => return Err(Error::NoAnswer),
+        };
+        let resp = String::from_utf8_lossy(&buf[..n]);
+        let location = resp.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case("location").then(|| value.trim())
+        });
+        match location.and_then(parse_url) {
+            Some(location) => return Ok(location),
+            None => debug!("ignoring ssdp answer without usable location"),
+        }
+    }
+}
+
+/// a minimal http/1.0 exchange, the server closes the connection after
+/// the response so we need not handle chunking or keep-alive
+async fn http(addr: SocketAddr, request: String) -> Result<(u16, String), Error> {
+    let exchange = async {
+        let mut conn = TcpStream::connect(addr).await?;
+        conn.write_all(request.as_bytes()).await?;
+        let mut resp = Vec::new();
+        conn.read_to_end(&mut resp).await?;
+        Ok::<_, std::io::Error>(resp)
+    };
+    let resp = match timeout(TIMEOUT, exchange).await {
+        Ok(resp) => resp?,
+        Err(_) => return Err(Error::NoAnswer),
+    };
+
+    let resp = String::from_utf8_lossy(&resp);
+    let (head, body) = resp.split_once("\r\n\r\n").ok_or(Error::Malformed)?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or(Error::Malformed)?;
+    Ok((status, body.to_owned()))
+}
+
+async fn find_service(ssdp: SocketAddr, lan_ip: Ipv4Addr) -> Result<Service, Error> {
+    let (addr, path) = discover(ssdp).await?;
+    let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\n\r\n", path, addr);
+    let (_, description) = http(addr, request).await?;
+
+    for service_type in SERVICES {
+        let element = format!("<serviceType>{}</serviceType>", service_type);
+        let control = description
+            .find(&element)
+            .and_then(|start| tag(&description[start..], "controlURL"));
+        let control = match control {
+            Some(control) => control,
+            None => continue,
+        };
+        let (addr, control_path) = match parse_url(control) {
+            Some(url) => url,
+            None if control.starts_with('/') => (addr, control.to_owned()),
+            None => (addr, format!("/{}", control)),
+        };
+        return Ok(Service {
+            addr,
+            control_path,
+            service_type,
+            lan_ip,
+        });
+    }
+    Err(Error::Malformed)
+}
+
+impl Service {
+    async fn soap(&self, action: &str, args: &[(&str, String)]) -> Result<String, Error> {
+        let args: String = args
+            .iter()
+            .map(|(name, value)| format!("<{0}>{1}</{0}>", name, value))
+            .collect();
+        let body = format!(
+            "<?xml version=\"1.0\"?>\
+             <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+             s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+             <s:Body><u:{action} xmlns:u=\"{service}\">{args}</u:{action}></s:Body>\
+             </s:Envelope>",
+            action = action,
+            service = self.service_type,
+            args = args,
+        );
+        let request = format!(
+            "POST {} HTTP/1.0\r\n\
+             Host: {}\r\n\
+             Content-Type: text/xml; charset=\"utf-8\"\r\n\
+             Content-Length: {}\r\n\
+             SOAPAction: \"{}#{}\"\r\n\r\n{}",
+            self.control_path,
+            self.addr,
+            body.len(),
+            self.service_type,
+            action,
+            body
+        );
+
+        let (status, body) = http(self.addr, request).await?;
+        if status == 200 {
+            return Ok(body);
+        }
+        let code = tag(&body, "errorCode").and_then(|code| code.parse().ok());
+        let description = tag(&body, "errorDescription").unwrap_or("unknown error");
+        Err(Error::Upnp {
+            code: code.unwrap_or(status),
+            description: description.to_owned(),
+        })
+    }
+
+    async fn external_ip(&self) -> Result<Ipv4Addr, Error> {
+        let resp = self.soap("GetExternalIPAddress", &[]).await?;
+        tag(&resp, "NewExternalIPAddress")
+            .and_then(|ip| ip.parse().ok())
+            .ok_or(Error::Malformed)
+    }
+
+    /// returns the lifetime of the mapping, zero if the router only
+    /// offered a permanent one
+    pub async fn add_mapping(
+        &self,
+        external: u16,
+        internal: u16,
+        lifetime: Duration,
+    ) -> Result<Duration, Error> {
+        let args = |lifetime: Duration| {
+            [
+                ("NewRemoteHost", String::new()),
+                ("NewExternalPort", external.to_string()),
+                ("NewProtocol", "TCP".to_owned()),
+                ("NewInternalPort", internal.to_string()),
+                ("NewInternalClient", self.lan_ip.to_string()),
+                ("NewEnabled", "1".to_owned()),
+                ("NewPortMappingDescription", "WorldSync".to_owned()),
+                ("NewLeaseDuration", lifetime.as_secs().to_string()),
+            ]
+        };
+        match self.soap("AddPortMapping", &args(lifetime)).await {
+            Err(Error::Upnp {
+                code: ONLY_PERMANENT_LEASE,
+                ..
+            }) => {
+                self.soap("AddPortMapping", &args(Duration::ZERO)).await?;
+                Ok(Duration::ZERO)
+            }
+            res => res.map(|_| lifetime),
+        }
+    }
+
+    pub async fn delete_mapping(&self, external: u16) -> Result<(), Error> {
+        let args = [
+            ("NewRemoteHost", String::new()),
+            ("NewExternalPort", external.to_string()),
+            ("NewProtocol", "TCP".to_owned()),
+        ];
+        self.soap("DeletePortMapping", &args).await.map(|_| ())
+    }
+}
+
+/// ssdp is where to search for the gateway, normally the multicast
+/// address of SSDP
+pub async fn map(
+    ssdp: SocketAddr,
+    lan_ip: Ipv4Addr,
+    port: u16,
+    lifetime: Duration,
+) -> Result<Mapping, Error> {
+    let service = find_service(ssdp, lan_ip).await?;
+    let ip = service.external_ip().await?;
+    let lifetime = service.add_mapping(port, port, lifetime).await?;
+    Ok(Mapping {
+        external: (ip, port).into(),
+        internal_port: port,
+        lifetime,
+        gateway: Gateway::Upnp(service),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    const EXTERNAL: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 3);
+
+    /// actions the mock router received
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    fn description() -> String {
+        format!(
+            "<?xml version=\"1.0\"?><root><device><serviceList>\
+             <service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>\
+             <controlURL>/l3f</controlURL></service>\
+             <service><serviceType>{}</serviceType>\
+             <controlURL>/ctl/IPConn</controlURL></service>\
+             </serviceList></device></root>",
+            SERVICES[1]
+        )
+    }
+
+    fn response(action: &str, body: &str, permanent_only: bool) -> String {
+        let fault = |code: u16| {
+            format!(
+                "HTTP/1.0 500 Internal Server Error\r\n\r\n<s:Envelope><s:Body><s:Fault>\
+                 <detail><UPnPError><errorCode>{}</errorCode>\
+                 <errorDescription>OnlyPermanentLeasesSupported</errorDescription>\
+                 </UPnPError></detail></s:Fault></s:Body></s:Envelope>",
+                code
+            )
+        };
+        match action {
+            "GetExternalIPAddress" => format!(
+                "HTTP/1.0 200 OK\r\n\r\n<s:Envelope><s:Body><u:GetExternalIPAddressResponse>\
+                 <NewExternalIPAddress>{}</NewExternalIPAddress>\
+                 </u:GetExternalIPAddressResponse></s:Body></s:Envelope>",
+                EXTERNAL
+            ),
+            "AddPortMapping" if permanent_only && !body.contains("<NewLeaseDuration>0<") => {
+                fault(ONLY_PERMANENT_LEASE)
+            }
+            _ => "HTTP/1.0 200 OK\r\n\r\n<s:Envelope></s:Envelope>".to_owned(),
+        }
+    }
+
+    /// answers ssdp searches and serves the description and control url
+    async fn mock_router(permanent_only: bool) -> (SocketAddr, Log) {
+        let http = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let http_addr = http.local_addr().unwrap();
+        let ssdp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let ssdp_addr = ssdp.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            loop {
+                let (_, peer) = ssdp.recv_from(&mut buf).await.unwrap();
+                let resp = format!(
+                    "HTTP/1.1 200 OK\r\nST: {}\r\nLocation: http://{}/rootDesc.xml\r\n\r\n",
+                    SEARCH_TARGET, http_addr
+                );
+                ssdp.send_to(resp.as_bytes(), peer).await.unwrap();
+            }
+        });
+
+        let log = Log::default();
+        let actions = log.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut conn, _) = http.accept().await.unwrap();
+                let mut req = Vec::new();
+                let mut buf = [0u8; 4096];
+                // read until the request is complete, we get no eof as
+                // the client waits for our response
+                let req = loop {
+                    let n = conn.read(&mut buf).await.unwrap();
+                    req.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&req).into_owned();
+                    if text.starts_with("GET") && text.ends_with("\r\n\r\n")
+                        || text.ends_with("</s:Envelope>")
+                    {
+                        break text;
+                    }
+                };
+                let resp = match req.split_once('#') {
+                    None => format!("HTTP/1.0 200 OK\r\n\r\n{}", description()),
+                    Some((_, rest)) => {
+                        let action = rest.split('"').next().unwrap().to_owned();
+                        let resp = response(&action, &req, permanent_only);
+                        actions.lock().unwrap().push(action);
+                        resp
+                    }
+                };
+                conn.write_all(resp.as_bytes()).await.unwrap();
+            }
+        });
+        (ssdp_addr, log)
+    }
+
+    #[tokio::test]
+    async fn maps_and_removes() {
+        let (ssdp, log) = mock_router(false).await;
+        let lan_ip = Ipv4Addr::new(192, 168, 1, 20);
+        let mapping = map(ssdp, lan_ip, 25565, Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(mapping.external, SocketAddr::from((EXTERNAL, 25565)));
+        assert_eq!(mapping.lifetime, Duration::from_secs(60));
+
+        mapping.remove().await.unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "GetExternalIPAddress",
+                "AddPortMapping",
+                "DeletePortMapping"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn permanent_lease() {
+        let (ssdp, log) = mock_router(true).await;
+        let lan_ip = Ipv4Addr::new(192, 168, 1, 20);
+        let mapping = map(ssdp, lan_ip, 25565, Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(mapping.lifetime, Duration::ZERO);
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["GetExternalIPAddress", "AddPortMapping", "AddPortMapping"]
+        );
+    }
+
+    #[test]
+    fn urls() {
+        let addr = "192.168.1.1:5000".parse().unwrap();
+        assert_eq!(
+            parse_url("http://192.168.1.1:5000/ctl"),
+            Some((addr, "/ctl".to_owned()))
+        );
+        let addr = "192.168.1.1:80".parse().unwrap();
+        assert_eq!(
+            parse_url("http://192.168.1.1"),
+            Some((addr, "/".to_owned()))
+        );
+        assert_eq!(parse_url("https://192.168.1.1/"), None);
+    }
+}
@@ -0,0 +1,59 @@
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use futures::stream::{self, BoxStream};
+use protocol::HostId;
+use tracing::{info, warn};
+
+use crate::gui::RpcConn;
+use crate::Event;
+
+/// keeps a tunnel to the relay of the worldsync server open while we
+/// host, the server then relays players to our minecraft server
+pub fn sub(rpc: RpcConn, host_id: HostId, mc_port: u16) -> iced::Subscription<Event> {
+    iced::Subscription::from_recipe(Tunnel {
+        rpc,
+        host_id,
+        mc_port,
+    })
+}
+
+pub struct Tunnel {
+    rpc: RpcConn,
+    host_id: HostId,
+    mc_port: u16,
+}
+
+/// reopens the tunnel if it fails, never produces an event
+async fn keep_open(tunnel: Tunnel) -> Event {
+    let Tunnel {
+        rpc,
+        host_id,
+        mc_port,
+    } = tunnel;
+    let (domain, port) = rpc.tunnel.expect("server should offer a relay");
+    loop {
+        match protocol::relay::host(&domain, port, rpc.session, host_id, mc_port).await {
+            Ok(_) => info!("relay closed the tunnel"),
+            Err(e) => warn!("tunnel to relay failed: {}", e),
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
+
+impl<H, I> iced_native::subscription::Recipe<H, I> for Tunnel
+where
+    H: Hasher,
+{
+    type Output = Event;
+
+    fn hash(&self, state: &mut H) {
+        struct Marker;
+        std::any::TypeId::of::<Marker>().hash(state);
+        self.host_id.hash(state);
+    }
+
+    fn stream(self: Box<Self>, _input: BoxStream<'static, I>) -> BoxStream<'static, Self::Output> {
+        Box::pin(stream::once(keep_open(*self)))
+    }
+}
@@ -0,0 +1,116 @@
+//! Writes the settings the admin manages centrally to the server folder
+//! before minecraft starts, so every host runs with the same settings.
+
+use std::io;
+use std::path::Path;
+
+use protocol::{ServerSettings, SETTINGS_FILES};
+use serde_json::Value;
+use tokio::fs;
+use tracing::warn;
+use wrapper::Config;
+
+/// server.properties is merged onto the local file so properties each host
+/// sets for itself survive. The player lists are merged too, players an
+/// op added to them while hosting are kept
+pub async fn apply(dir: &Path, settings: &ServerSettings) -> io::Result<()> {
+    for (name, content) in &settings.files {
+        if !SETTINGS_FILES.contains(&name.as_str()) {
+            warn!("ignoring unknown settings file: {}", name);
+            continue;
+        }
+        if name == "server.properties" {
+            let mut config = match Config::read(dir).await {
+                Ok(config) => config,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Config::default(),
+                Err(e) => return Err(e),
+            };
+            config.merge(&Config::parse(content));
+            config.write(dir).await?;
+        } else {
+            let path = dir.join(name);
+            let local = match fs::read_to_string(&path).await {
+                Ok(local) => Some(local),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            };
+            match merge_list(local.as_deref(), content) {
+                Some(merged) => fs::write(path, merged).await?,
+                None => warn!("ignoring settings file that is not a list: {}", name),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// the entries of both lists, an entry of the shared list replaces the
+/// local one for the same player. None if the shared list is invalid
+fn merge_list(local: Option<&str>, shared: &str) -> Option<String> {
+    let shared: Vec<Value> = serde_json::from_str(shared).ok()?;
+    let local: Vec<Value> = match local.map(serde_json::from_str) {
+        None => Vec::new(),
+        Some(Ok(local)) => local,
+        Some(Err(_)) => {
+            warn!("replacing invalid local player list");
+            Vec::new()
+        }
+    };
+
+    let player = |entry: &Value| {
+        let uuid = entry.get("uuid").and_then(Value::as_str);
+        uuid.or_else(|| entry.get("name").and_then(Value::as_str))
+            .map(str::to_lowercase)
+    };
+    let mut merged = shared.clone();
+    for entry in local {
+        let key = player(&entry);
+        if key.is_none() || !shared.iter().any(|s| player(s) == key) {
+            merged.push(entry);
+        }
+    }
+    serde_json::to_string_pretty(&merged).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn applies_settings() {
+        let dir = std::env::temp_dir().join(format!("settings_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).await.unwrap();
+        fs::write(
+            dir.join("server.properties"),
+            "server-port=25570\nmotd=local\n",
+        )
+        .await
+        .unwrap();
+
+        let mut settings = ServerSettings::default();
+        let files = &mut settings.files;
+        files.insert("server.properties".to_owned(), "motd=shared\n".to_owned());
+        files.insert("ops.json".to_owned(), "[]".to_owned());
+        files.insert("../evil.json".to_owned(), "[]".to_owned());
+        let shared = r#"[{"uuid": "a", "name": "Alice", "level": 4}]"#;
+        files.insert("whitelist.json".to_owned(), shared.to_owned());
+        let local = r#"[{"uuid": "a", "name": "Alice"}, {"uuid": "b", "name": "Bob"}]"#;
+        fs::write(dir.join("whitelist.json"), local).await.unwrap();
+        apply(&dir, &settings).await.unwrap();
+
+        let config = Config::read(&dir).await.unwrap();
+        assert_eq!(config.get("server-port"), Some("25570"));
+        assert_eq!(config.get("motd"), Some("shared"));
+        assert_eq!(
+            fs::read_to_string(dir.join("ops.json")).await.unwrap(),
+            "[]"
+        );
+        let read = |name| std::fs::read_to_string(dir.join(name)).unwrap();
+        let whitelist: Value = serde_json::from_str(&read("whitelist.json")).unwrap();
+        let expected =
+            r#"[{"uuid": "a", "name": "Alice", "level": 4}, {"uuid": "b", "name": "Bob"}]"#;
+        assert_eq!(whitelist, serde_json::from_str::<Value>(expected).unwrap());
+        assert!(!std::env::temp_dir().join("evil.json").exists());
+
+        fs::remove_dir_all(dir).await.unwrap();
+    }
+}
@@ -1,4 +1,5 @@
 use std::cell::Cell;
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
 
@@ -6,7 +7,7 @@ use crate::gui::host::Event as hEvent;
 use futures::stream::{self, BoxStream};
 use shared::tarpc::client::RpcError;
 use shared::tarpc::context;
-use sync::{DirContent, DirUpdate, ObjectId, SyncAction};
+use sync::{DirContent, DirUpdate, ObjectId, Save, SyncAction};
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 use tracing::{debug, error, info, instrument};
@@ -38,6 +39,8 @@ struct State {
     conn: RpcConn,
     phase: Phase,
     updates: Option<DirUpdate>,
+    /// expected hash of the server install objects
+    checksums: HashMap<ObjectId, u64>,
 }
 
 #[derive(Clone, Debug, thiserror::Error, Eq, PartialEq, Hash)]
@@ -48,6 +51,8 @@ pub enum Error {
     Fs,
     #[error("{0}")]
     Protocol(#[from] protocol::Error),
+    #[error("Downloaded file is corrupt, its checksum does not match: {0:?}")]
+    Checksum(PathBuf),
 }
 
 impl From<sync::Error> for Error {
@@ -80,6 +85,7 @@ where
                 conn: self.conn.replace(None).unwrap(),
                 phase: Phase::Started,
                 updates: None,
+                checksums: HashMap::new(),
             },
             move |state| async move {
                 match &state.phase {
@@ -102,11 +108,26 @@ impl State {
         }
         let dir_content = DirContent::from_dir(server_path().into()).await?;
         debug!("{:?}", dir_content);
-        let dir_update = self
+        let mut dir_update = self
             .conn
             .client
-            .dir_update(context::current(), self.conn.session, dir_content)
+            .dir_update(context::current(), self.conn.session, dir_content.clone())
             .await??;
+        let install = self
+            .conn
+            .client
+            .server_install(context::current(), self.conn.session)
+            .await??;
+        if let Some(install) = install {
+            info!("server install is minecraft version: {}", install.version);
+            dir_update = with_install(dir_update, &dir_content, &install.files);
+            self.checksums = install
+                .files
+                .objects()
+                .iter()
+                .map(|obj| (obj.id, obj.hash()))
+                .collect();
+        }
         debug!("{:?}", dir_update);
         Ok(dir_update)
     }
@@ -134,10 +155,11 @@ impl State {
             mut conn,
             mut phase,
             mut updates,
+            checksums,
         } = self;
         let list = updates.as_mut().unwrap();
         match list.0.pop() {
-            Some(action) => match apply_action(&mut conn, action).await {
+            Some(action) => match apply_action(&mut conn, action, &checksums).await {
                 Ok(_) => {
                     let left = list.0.len();
                     let progress = hEvent::ObjToSync { left };
@@ -145,6 +167,7 @@ impl State {
                         conn,
                         phase,
                         updates,
+                        checksums,
                     };
                     (Event::HostPage(progress), state)
                 }
@@ -155,34 +178,65 @@ impl State {
                         conn,
                         phase,
                         updates,
+                        checksums,
                     };
                     (Event::HostPage(event), state)
                 }
             },
             None => {
+                let event = match apply_settings(&conn).await {
+                    Ok(()) => Event::WorldUpdated,
+                    Err(e) => Event::HostPage(hEvent::Error(e.into())),
+                };
                 let state = Self {
                     conn,
                     phase: Phase::End,
                     updates,
+                    checksums,
                 };
-                (Event::WorldUpdated, state)
+                (event, state)
             }
         }
     }
 }
 
+/// files of the server install replace those in the save and are never
+/// removed
+fn with_install(mut update: DirUpdate, local: &DirContent, install: &Save) -> DirUpdate {
+    update.0.retain(|action| !install.contains(action.path()));
+    update.0.extend(install.missing(local).0);
+    update
+}
+
+fn verify(
+    checksums: &HashMap<ObjectId, u64>,
+    id: ObjectId,
+    path: &Path,
+    bytes: &[u8],
+) -> Result<(), Error> {
+    match checksums.get(&id) {
+        Some(hash) if *hash != sync::hash(bytes) => Err(Error::Checksum(path.to_owned())),
+        _ => Ok(()),
+    }
+}
+
 fn local_path(remote_path: &Path) -> PathBuf {
     Path::new(server_path()).join(remote_path)
 }
 
-#[instrument(err)]
-async fn apply_action(conn: &mut RpcConn, action: SyncAction) -> Result<(), Error> {
+#[instrument(err, skip(checksums))]
+async fn apply_action(
+    conn: &mut RpcConn,
+    action: SyncAction,
+    checksums: &HashMap<ObjectId, u64>,
+) -> Result<(), Error> {
     match action {
         SyncAction::Remove(path) => {
             fs::remove_file(local_path(&path)).await?;
         }
         SyncAction::Replace(path, id) => {
             let bytes = download_obj(conn, id).await?;
+            verify(checksums, id, &path, &bytes)?;
             let mut file = fs::OpenOptions::new()
                 .write(true)
                 .truncate(true)
@@ -192,6 +246,7 @@ async fn apply_action(conn: &mut RpcConn, action: SyncAction) -> Result<(), Erro
         }
         SyncAction::Add(path, id) => {
             let bytes = download_obj(conn, id).await?;
+            verify(checksums, id, &path, &bytes)?;
             if let Some(dir) = local_path(&path).parent(){
                 fs::create_dir_all(dir).await?;
             }
@@ -211,6 +266,17 @@ async fn apply_action(conn: &mut RpcConn, action: SyncAction) -> Result<(), Erro
     Ok(())
 }
 
+#[instrument(err)]
+async fn apply_settings(conn: &RpcConn) -> Result<(), Error> {
+    let settings = conn
+        .client
+        .server_settings(context::current(), conn.session)
+        .await??;
+    crate::server_settings::apply(server_path(), &settings).await?;
+    info!("applied server settings version: {}", settings.version);
+    Ok(())
+}
+
 #[instrument(err)]
 async fn download_obj(conn: &mut RpcConn, id: ObjectId) -> Result<Vec<u8>, Error> {
     let bytes = conn
@@ -12,7 +12,9 @@ use tokio::fs;
 use tracing::{error, instrument, debug};
 
 use crate::gui::{hosting, RpcConn};
-use crate::{server_path, Event};
+use crate::{snapshot_path, Event};
+
+pub mod snapshot;
 
 pub fn sub(conn: RpcConn, count: usize, host_id: HostId) -> iced::Subscription<Event> {
     iced::Subscription::from_recipe(WorldUpload {
@@ -106,7 +108,7 @@ where
 impl State {
     #[instrument(err)]
     async fn do_build_updatelist(&mut self) -> Result<UpdateList, Error> {
-        let dir = DirContent::from_dir(server_path().into())
+        let dir = DirContent::from_dir(snapshot_path().into())
             .await
             .map_err(|_| Error::SyncError)?;
         assert_ne!(dir.len(), 0, "dircontent should never be empty");
@@ -203,5 +205,5 @@ impl State {
 }
 
 fn local_path(remote_path: &Path) -> PathBuf {
-    Path::new(server_path()).join(remote_path)
+    snapshot_path().join(remote_path)
 }
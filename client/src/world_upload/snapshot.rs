@@ -0,0 +1,115 @@
+//! Copy of the world made while the minecraft server is not writing to it.
+//! Uploading from the copy means no file changes halfway through being
+//! read. The copy is kept between saves so only changed files are copied,
+//! that keeps the time the server can not save short.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// folders in the server folder the worldsync server keeps in a save
+const SYNCED: [&str; 2] = ["world", "logs"];
+
+/// makes `to` a copy of the synced folders in the server folder `from`,
+/// only copying files that changed since the last update. Returns the
+/// number of files copied
+pub async fn update(from: PathBuf, to: PathBuf) -> io::Result<usize> {
+    tokio::task::spawn_blocking(move || update_blocking(&from, &to))
+        .await
+        .expect("error joining snapshot task")
+}
+
+fn update_blocking(from: &Path, to: &Path) -> io::Result<usize> {
+    let mut copied = 0;
+    let mut present = HashSet::new();
+    let mut synced = Vec::new();
+    for dir in SYNCED {
+        synced.extend(files(&from.join(dir))?);
+    }
+    for org in synced {
+        let relative = org.strip_prefix(from).expect("files are in dir");
+        let copy = to.join(relative);
+        present.insert(relative.to_owned());
+        if !changed(&org, &copy)? {
+            continue;
+        }
+        if let Some(parent) = copy.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&org, &copy)?;
+        copied += 1;
+    }
+
+    for copy in files(to)? {
+        let relative = copy.strip_prefix(to).expect("files are in dir");
+        if !present.contains(relative) {
+            fs::remove_file(copy)?;
+        }
+    }
+    Ok(copied)
+}
+
+/// all files in dir and its subdirectories, none if dir does not exist
+fn files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            match entry.file_type()?.is_dir() {
+                true => dirs.push(entry.path()),
+                false => files.push(entry.path()),
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn changed(org: &Path, copy: &Path) -> io::Result<bool> {
+    let copy = match fs::metadata(copy) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(e),
+    };
+    let org = fs::metadata(org)?;
+    // copying sets the modified time, a copy is newer then its original
+    // unless the original changed after it was copied
+    Ok(org.len() != copy.len() || org.modified()? >= copy.modified()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn copies_changes() {
+        let dir = std::env::temp_dir().join(format!("snapshot_test_{}", std::process::id()));
+        let (server, snapshot) = (dir.join("server"), dir.join("snapshot"));
+        let world = server.join("world");
+        fs::create_dir_all(world.join("region")).unwrap();
+        fs::write(world.join("level.dat"), "level").unwrap();
+        fs::write(world.join("region/r.0.0.mca"), "chunks").unwrap();
+        fs::write(server.join("server.jar"), "server").unwrap();
+
+        assert_eq!(update(server.clone(), snapshot.clone()).await.unwrap(), 2);
+        assert_eq!(update(server.clone(), snapshot.clone()).await.unwrap(), 0);
+        assert!(!snapshot.join("server.jar").exists());
+
+        fs::write(world.join("region/r.0.0.mca"), "more chunks").unwrap();
+        fs::remove_file(world.join("level.dat")).unwrap();
+        assert_eq!(update(server.clone(), snapshot.clone()).await.unwrap(), 1);
+        assert_eq!(
+            fs::read_to_string(snapshot.join("world/region/r.0.0.mca")).unwrap(),
+            "more chunks"
+        );
+        assert!(!snapshot.join("world/level.dat").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
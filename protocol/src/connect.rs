@@ -10,7 +10,10 @@ use tracing::instrument;
 use tokio_rustls::{client::TlsStream, rustls, TlsConnector};
 
 #[cfg(feature = "deployed")]
-async fn connect_tcp(domain: &str, port: u16) -> Result<TlsStream<TcpStream>, std::io::Error> {
+pub(crate) async fn connect_tcp(
+    domain: &str,
+    port: u16,
+) -> Result<TlsStream<TcpStream>, std::io::Error> {
     use std::sync::Arc;
 
     let mut roots = rustls::RootCertStore::empty();
@@ -31,7 +34,7 @@ async fn connect_tcp(domain: &str, port: u16) -> Result<TlsStream<TcpStream>, st
 
 #[cfg(not(feature = "deployed"))]
 #[instrument(err)]
-async fn connect_tcp(domain: &str, port: u16) -> Result<TcpStream, std::io::Error> {
+pub(crate) async fn connect_tcp(domain: &str, port: u16) -> Result<TcpStream, std::io::Error> {
     TcpStream::connect(format!("{}:{}", domain, port)).await
 }
 
@@ -1,9 +1,11 @@
+use std::collections::BTreeMap;
 use std::fmt;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
-use std::time::Duration;
-use sync::{DirContent, DirUpdate, ObjectId, UpdateList};
+use std::time::{Duration, SystemTime};
+use sync::{DirContent, DirUpdate, ObjectId, Save, UpdateList};
 use wrapper::parser::Line;
+use wrapper::CrashReport;
 
 use serde::{Deserialize, Serialize};
 use shared::tarpc;
@@ -12,6 +14,7 @@ pub use uuid::Uuid;
 
 mod connect;
 pub use connect::{connect, connect_local};
+pub mod relay;
 
 #[derive(thiserror::Error, Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
 pub enum Error {
@@ -47,6 +50,36 @@ pub enum Error {
     ForbiddenPath(PathBuf),
     #[error("not creating a new save")]
     NotSaving,
+    #[error("can only take over from a host that is up and running")]
+    HostNotUp,
+    #[error("you are already hosting")]
+    AlreadyHost,
+    #[error("someone else is already taking over")]
+    TakeoverPending,
+    #[error("no one is waiting to take over")]
+    NoTakeover,
+    #[error("the ping interval may not be zero")]
+    ZeroPingInterval,
+    #[error("this server does not offer a relay")]
+    NoRelay,
+    #[error("chat messages may not be empty, over 256 characters or contain control characters")]
+    InvalidChat,
+    #[error("console commands may not be empty or contain control characters")]
+    InvalidCommand,
+    #[error("the host did not return the output of the console command in time")]
+    NoConsoleOutput,
+    #[error("crash reports may not be longer then {} bytes", MAX_CRASH_REPORT_LEN)]
+    CrashReportTooLarge,
+    #[error("not a server settings file: {0}")]
+    NotSettingsFile(String),
+    #[error("a server install needs a 'server.jar' in its top folder")]
+    NoServerJar,
+    #[error("could not read the server install: {0}")]
+    ReadInstall(String),
+    #[error("the world was last played on minecraft {world}, your server runs {host}. An admin needs to approve changing the version")]
+    VersionMismatch { world: String, host: String },
+    #[error("the save was made by an older minecraft version then the world (data version {save} < {world})")]
+    DataDowngrade { world: i32, save: i32 },
 }
 
 // governs the maximum time between events, is used to detect connection
@@ -67,11 +100,30 @@ pub enum Event {
     HostUnreachable,
     HostRestored,
     HostCanceld,
+    /// someone (name) asked the current host to hand over
+    TakeoverRequested(String),
+    /// the current host did not hand over in time and keeps hosting
+    TakeoverFailed,
+    /// the hosts minecraft server answered a status ping
+    HostStatus(HostStatus),
+    /// a player (minecraft name) joined the hosts minecraft server
+    PlayerJoined(String),
+    /// a player (minecraft name) left the hosts minecraft server
+    PlayerLeft(String),
+    /// someone chatted in game or from a worldsync client
+    Chat(ChatMessage),
+    /// an admin wants the host to run command on its minecraft server
+    /// and return the output
+    ConsoleCommand {
+        id: CommandId,
+        command: String,
+    },
 }
 
 pub type UserId = u64;
 pub type HostId = Uuid;
 pub type SessionId = Uuid;
+pub type CommandId = Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Addr {
@@ -94,14 +146,67 @@ impl Addr {
     }
 }
 
+/// from where an address of the host can be used
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Reach {
+    /// only from within the network of the host
+    Lan,
+    /// from anywhere
+    Public,
+    /// from anywhere, through the relay of the worldsync server
+    Relay,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostAddr {
+    pub addr: Addr,
+    pub reach: Reach,
+    /// port to use instead of the hosts, as the relay listens elsewhere
+    pub port: Option<u16>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HostDetails {
     pub name: String,
-    pub addr: Addr,
+    /// candidate addresses, the server orders these from most to least
+    /// suitable for each client
+    pub addrs: Vec<HostAddr>,
+    /// address the host connected to the worldsync server from
+    pub peer: IpAddr,
     pub port: u16,
     pub id: HostId,
 }
 
+impl HostDetails {
+    /// the address a client should try first
+    pub fn addr(&self) -> &Addr {
+        &self.addrs[0].addr
+    }
+    /// the address a client should try first as entered in minecraft,
+    /// the port is left out if it is the default. Ipv6 addresses are
+    /// always written as `[addr]:port`
+    pub fn server_address(&self) -> String {
+        let port = self.addrs[0].port.unwrap_or(self.port);
+        match (self.addr(), port) {
+            (Addr::Ip(IpAddr::V6(ip)), port) => format!("[{}]:{}", ip, port),
+            (addr, 25565) => addr.to_string(),
+            (addr, port) => format!("{}:{}", addr.to_string(), port),
+        }
+    }
+}
+
+impl HostState {
+    pub fn details_mut(&mut self) -> Option<&mut HostDetails> {
+        match self {
+            HostState::NoHost => None,
+            HostState::Loading(details)
+            | HostState::Up(details)
+            | HostState::Unreachable(details)
+            | HostState::ShuttingDown(details) => Some(details),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum HostState {
     NoHost,
@@ -111,6 +216,218 @@ pub enum HostState {
     ShuttingDown(HostDetails),
 }
 
+/// what happened when the server tried to reach a port of the client
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum PortCheck {
+    /// the server reached the listener of the client
+    Open,
+    /// something else answered, the port leads to another device or program
+    WrongTarget,
+    /// the connection was refused, the port is not forwarded
+    Refused,
+    /// no answer, a firewall drops the connection or the port is not
+    /// forwarded
+    NoAnswer,
+}
+
+/// what the hosts minecraft server reported when last pinged
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HostStatus {
+    pub motd: String,
+    pub version: String,
+    pub online: u32,
+    pub max_players: u32,
+    /// some of the players online, not every server reports these
+    pub sample: Vec<String>,
+    /// round trip time from the worldsync server to the host
+    pub latency: Duration,
+}
+
+/// time a minecraft player spent on the hosts
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PlayerStats {
+    /// minecraft name
+    pub player: String,
+    pub sessions: u32,
+    pub played: Duration,
+    /// part of played during which the host was unreachable
+    pub unreachable: Duration,
+    /// time played on each host (worldsync name), most played first
+    pub per_host: Vec<(String, Duration)>,
+}
+
+/// longest chat message a worldsync client may send, in characters
+pub const MAX_CHAT_LEN: usize = 256;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatMessage {
+    /// minecraft name if send in game, worldsync name otherwise
+    pub from: String,
+    pub text: String,
+    pub in_game: bool,
+}
+
+impl ChatMessage {
+    /// chat from a worldsync client, the text ends up on the console of
+    /// the host so it may not carry a newline or other control characters
+    pub fn from_client(from: String, text: &str) -> Result<Self, Error> {
+        let text = text.trim();
+        let len = text.chars().count();
+        if len == 0 || len > MAX_CHAT_LEN || text.chars().any(char::is_control) {
+            return Err(Error::InvalidChat);
+        }
+        Ok(Self {
+            from,
+            text: text.to_owned(),
+            in_game: false,
+        })
+    }
+
+    /// the message as the host should say it in game
+    pub fn in_game_text(&self) -> String {
+        format!("<{}> {}", self.from, self.text)
+    }
+}
+
+/// why someone stopped hosting
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum HostingEnd {
+    ShutDown,
+    Dropped,
+    Canceld,
+    HandedOver,
+}
+
+/// a period during which someone hosted
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HostingRecord {
+    /// worldsync name of the host
+    pub host: String,
+    pub start: SystemTime,
+    pub duration: Duration,
+    /// part of duration during which the host was unreachable
+    pub unreachable: Duration,
+    /// saves the host uploaded
+    pub saves: u32,
+    pub end: HostingEnd,
+}
+
+/// longest crash report text the server stores, in bytes
+pub const MAX_CRASH_REPORT_LEN: usize = 512 * 1024;
+
+/// a crash of the minecraft server of a host
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CrashRecord {
+    /// worldsync name of the host
+    pub host: String,
+    pub at: SystemTime,
+    pub report: CrashReport,
+}
+
+/// files in the minecraft server folder the admins manage, every host
+/// gets the same
+pub const SETTINGS_FILES: [&str; 4] = [
+    "server.properties",
+    "whitelist.json",
+    "ops.json",
+    "banned-players.json",
+];
+
+/// properties each host sets for itself, they are not synced
+pub const HOST_PROPERTIES: [&str; 7] = [
+    "server-ip",
+    "server-port",
+    "query.port",
+    "enable-query",
+    "enable-rcon",
+    "rcon.port",
+    "rcon.password",
+];
+
+/// the settings files hosts apply before starting the minecraft server
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ServerSettings {
+    /// goes up with every change, 0 if the admins never set any
+    pub version: u64,
+    /// content by file name, hosts keep their own version of missing files
+    pub files: BTreeMap<String, String>,
+}
+
+/// minecraft version a save was made with, hosting with another version
+/// upgrades the world or corrupts it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WorldVersion {
+    /// as printed by the minecraft server, none for saves set by the admin
+    pub game: Option<String>,
+    /// `DataVersion` in level.dat, goes up with every minecraft release
+    pub data: Option<i32>,
+}
+
+/// the minecraft server every host runs, a server jar or a modloader
+/// installation, its files are in the object store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerInstall {
+    /// minecraft version as given by the admin
+    pub version: String,
+    pub files: Save,
+}
+
+/// how long the server waits on the host before giving up on it
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HostTimings {
+    /// maximum time between loading progress updates
+    pub loading: Duration,
+    /// how long a host may be unreachable before it is dropped
+    pub unreachable: Duration,
+    /// time the host has to upload its final save after stopping
+    pub shutdown: Duration,
+    /// time the host has to hand over after someone asked to take over
+    pub hand_over: Duration,
+    /// time between checks whether the host is (still) reachable
+    pub ping_interval: Duration,
+}
+
+impl Default for HostTimings {
+    fn default() -> Self {
+        Self {
+            loading: Duration::from_secs(5 * 60),
+            unreachable: Duration::from_secs(5 * 60),
+            shutdown: Duration::from_secs(5 * 60),
+            hand_over: Duration::from_secs(5 * 60),
+            ping_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+impl HostTimings {
+    pub fn with(mut self, overrides: &TimingOverrides) -> Self {
+        let TimingOverrides {
+            loading,
+            unreachable,
+            shutdown,
+            hand_over,
+            ping_interval,
+        } = *overrides;
+        self.loading = loading.unwrap_or(self.loading);
+        self.unreachable = unreachable.unwrap_or(self.unreachable);
+        self.shutdown = shutdown.unwrap_or(self.shutdown);
+        self.hand_over = hand_over.unwrap_or(self.hand_over);
+        self.ping_interval = ping_interval.unwrap_or(self.ping_interval);
+        self
+    }
+}
+
+/// per world changes to the servers [`HostTimings`], fields that are
+/// not set use the server wide value
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TimingOverrides {
+    pub loading: Option<Duration>,
+    pub unreachable: Option<Duration>,
+    pub shutdown: Option<Duration>,
+    pub hand_over: Option<Duration>,
+    pub ping_interval: Option<Duration>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
 pub struct User {
     pub username: String,
@@ -180,8 +497,55 @@ pub trait Service {
     async fn update_password(id: SessionId, new: String) -> Result<(), Error>;
     async fn close_account(id: SessionId) -> Result<(), Error>;
     async fn await_event(id: SessionId) -> Result<Event, Error>;
-    async fn host(id: SessionId) -> Result<HostState, Error>;
-    async fn request_to_host(id: SessionId, host_id: HostId) -> Result<(), Error>;
+    /// the current host and the timings the server uses to monitor it
+    async fn host(id: SessionId) -> Result<(HostState, HostTimings), Error>;
+    /// the latest status the hosts minecraft server reported, if it is up
+    async fn host_status(id: SessionId) -> Result<Option<HostStatus>, Error>;
+    /// minecraft names of the players on the current host, sorted
+    async fn players(id: SessionId) -> Result<Vec<String>, Error>;
+    /// play time of a minecraft player, none if they never played
+    async fn player_stats(id: SessionId, player: String) -> Result<Option<PlayerStats>, Error>;
+    /// at most limit players, most played first
+    async fn leaderboard(id: SessionId, limit: u16) -> Result<Vec<PlayerStats>, Error>;
+    /// at most limit past hosting periods, latest first
+    async fn hosting_history(id: SessionId, limit: u16) -> Result<Vec<HostingRecord>, Error>;
+    /// passes text to everyone logged in and to the players on the host
+    async fn send_chat(id: SessionId, text: String) -> Result<(), Error>;
+    /// the latest chat messages, oldest first
+    async fn chat_history(id: SessionId) -> Result<Vec<ChatMessage>, Error>;
+    async fn server_settings(id: SessionId) -> Result<ServerSettings, Error>;
+    /// none if the admins never uploaded one
+    async fn server_install(id: SessionId) -> Result<Option<ServerInstall>, Error>;
+    /// port is where the requesting client will run the minecraft server,
+    /// lan_ip its address on its local network if it knows it. If relay
+    /// is set players are told to connect through the relay, the client
+    /// should then open a tunnel to it. Mapped is the external address
+    /// the router of the client forwards to port, if it mapped one.
+    async fn request_to_host(
+        id: SessionId,
+        host_id: HostId,
+        port: u16,
+        lan_ip: Option<IpAddr>,
+        relay: bool,
+        mapped: Option<SocketAddr>,
+    ) -> Result<(), Error>;
+    async fn request_takeover(
+        id: SessionId,
+        host_id: HostId,
+        port: u16,
+        lan_ip: Option<IpAddr>,
+        relay: bool,
+        mapped: Option<SocketAddr>,
+    ) -> Result<(), Error>;
+    /// port hosts open their relay tunnel to, none if there is no relay
+    async fn tunnel_port() -> Option<u16>;
+    /// the server connects back to port on the address of the caller,
+    /// whoever listens there should answer with the bytes of token
+    async fn check_port(id: SessionId, port: u16, token: Uuid) -> Result<PortCheck, Error>;
+    async fn hand_over(id: SessionId, host_id: HostId) -> Result<(), Error>;
+    /// called by the host once the minecraft server exited and the final
+    /// save is uploaded, saved is false if that upload failed
+    async fn release_host(id: SessionId, host_id: HostId, saved: bool) -> Result<(), Error>;
     async fn dir_update(id: SessionId, dir: DirContent) -> Result<DirUpdate, Error>;
     async fn new_save(
         id: SessionId,
@@ -198,6 +562,17 @@ pub trait Service {
         bytes: Vec<u8>,
     ) -> Result<(), Error>;
     async fn pub_mc_line(id: HostId, line: Line) -> Result<(), Error>;
+    /// the reply of the minecraft server of the host to a console
+    /// command, or if it gave none what it printed after running it
+    async fn console_output(
+        id: SessionId,
+        host_id: HostId,
+        command: CommandId,
+        lines: Vec<String>,
+    ) -> Result<(), Error>;
+    /// the minecraft server of the host crashed and wrote report
+    async fn crash_report(id: SessionId, host_id: HostId, report: CrashReport)
+        -> Result<(), Error>;
 
     async fn add_user(user: User, password: String) -> Result<(), Error>;
     async fn list_users() -> Result<Vec<(UserId, User)>, Error>;
@@ -206,4 +581,24 @@ pub trait Service {
     async fn remove_account(id: UserId) -> Result<(), Error>;
     async fn dump_save(dir: PathBuf) -> Result<(), Error>;
     async fn set_save(dir: PathBuf) -> Result<(), Error>;
+    async fn timing_overrides() -> Result<TimingOverrides, Error>;
+    async fn set_timing_overrides(overrides: TimingOverrides) -> Result<(), Error>;
+    /// has the host run command on its minecraft server, returns what the
+    /// server replied or printed in response
+    async fn console_command(command: String) -> Result<Vec<String>, Error>;
+    /// at most limit crash reports uploaded by hosts, latest first
+    async fn crash_reports(limit: u16) -> Result<Vec<CrashRecord>, Error>;
+    async fn current_settings() -> Result<ServerSettings, Error>;
+    /// replaces the server settings, properties in [`HOST_PROPERTIES`] are
+    /// dropped. Returns the new version
+    async fn set_settings(files: BTreeMap<String, String>) -> Result<u64, Error>;
+    /// version of the server install, none if there is none
+    async fn install_version() -> Result<Option<String>, Error>;
+    /// replaces the server install with the content of dir
+    async fn set_install(version: String, dir: PathBuf) -> Result<(), Error>;
+    /// the versions the latest save was made with and the game version
+    /// hosts may run even if it differs from the one of the save
+    async fn world_version() -> Result<(Option<WorldVersion>, Option<String>), Error>;
+    /// lets hosts run game version, upgrading the world to it
+    async fn approve_version(game: String) -> Result<(), Error>;
 }
@@ -0,0 +1,177 @@
+//! Lets players reach a host that can not forward ports. The host opens
+//! a tunnel to the worldsync server, players connect to the relay port of
+//! the server and their connections are multiplexed over the tunnel.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use shared::tarpc;
+use tarpc::serde_transport::Transport;
+use tarpc::tokio_serde::formats::Bincode;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::{debug, instrument, warn};
+
+use crate::{HostId, SessionId};
+
+pub type StreamId = u32;
+
+/// most bytes send in one data frame
+const CHUNK: usize = 16 * 1024;
+/// chunks buffered for a stream, a player that falls further behind is
+/// disconnected so it can not stall the others sharing the tunnel
+const STREAM_BUFFER: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Frame {
+    /// first frame on a tunnel, the server only accepts the current host
+    Hello {
+        session: SessionId,
+        host_id: HostId,
+    },
+    /// a player connected to the relay
+    Open(StreamId),
+    Data(StreamId, Vec<u8>),
+    /// the stream ended on the side sending this
+    Close(StreamId),
+}
+
+pub type Tunnel<S> = Transport<S, Frame, Frame, Bincode<Frame, Frame>>;
+
+pub fn framed<S: AsyncRead + AsyncWrite>(conn: S) -> Tunnel<S> {
+    use tokio_util::codec::length_delimited::LengthDelimitedCodec;
+    let framed = LengthDelimitedCodec::builder().new_framed(conn);
+    tarpc::serde_transport::new(framed, Bincode::default())
+}
+
+/// connects the stream then copies between it and the tunnel, returns
+/// where to send the bytes that arrive for this stream. Bytes that arrive
+/// while connecting are buffered
+fn spawn_stream<C>(
+    id: StreamId,
+    connect: C,
+    to_tunnel: mpsc::Sender<Frame>,
+) -> mpsc::Sender<Vec<u8>>
+where
+    C: Future<Output = io::Result<TcpStream>> + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::channel::<Vec<u8>>(STREAM_BUFFER);
+    tokio::spawn(async move {
+        let conn = match connect.await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("could not reach minecraft server: {}", e);
+                let _irrelevant = to_tunnel.send(Frame::Close(id)).await;
+                return;
+            }
+        };
+        let (mut read, mut write) = conn.into_split();
+        tokio::spawn(async move {
+            while let Some(bytes) = rx.recv().await {
+                if write.write_all(&bytes).await.is_err() {
+                    break;
+                }
+            }
+            let _irrelevant = write.shutdown().await;
+        });
+
+        let mut buf = vec![0u8; CHUNK];
+        loop {
+            let frame = match read.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => Frame::Data(id, buf[..n].to_vec()),
+            };
+            if to_tunnel.send(frame).await.is_err() {
+                return;
+            }
+        }
+        let _irrelevant = to_tunnel.send(Frame::Close(id)).await;
+    });
+    tx
+}
+
+/// Forwards the streams multiplexed over the tunnel until it closes. The
+/// server opens a stream for each player it gets from `players`, the host
+/// connects the streams the server opens to its minecraft server at
+/// `local`.
+pub async fn forward<T>(
+    mut tunnel: T,
+    mut players: mpsc::Receiver<TcpStream>,
+    local: Option<SocketAddr>,
+) -> io::Result<()>
+where
+    T: Stream<Item = io::Result<Frame>> + Sink<Frame, Error = io::Error> + Unpin,
+{
+    let (to_tunnel, mut outgoing) = mpsc::channel(32);
+    let mut streams: HashMap<StreamId, mpsc::Sender<Vec<u8>>> = HashMap::new();
+    let mut next_id = 0;
+
+    loop {
+        tokio::select! {
+            frame = tunnel.next() => match frame.transpose()? {
+                None => return Ok(()),
+                Some(Frame::Open(id)) => match local {
+                    Some(addr) => {
+                        debug!("relaying new stream {}", id);
+                        let connect = TcpStream::connect(addr);
+                        streams.insert(id, spawn_stream(id, connect, to_tunnel.clone()));
+                    }
+                    None => warn!("only the server may open streams"),
+                },
+                Some(Frame::Data(id, bytes)) => {
+                    // the stream may have ended, then its close frame is
+                    // on its way to the other side. Never wait on a
+                    // stream, that would hold up all others
+                    let sent = streams.get(&id).map(|s| s.try_send(bytes));
+                    if let Some(Err(TrySendError::Full(_))) = sent {
+                        warn!("stream {} can not keep up, closing it", id);
+                        streams.remove(&id);
+                        tunnel.send(Frame::Close(id)).await?;
+                    }
+                }
+                Some(Frame::Close(id)) => {
+                    streams.remove(&id);
+                }
+                Some(Frame::Hello { .. }) => warn!("hello on an established tunnel"),
+            },
+            Some(conn) = players.recv() => {
+                let id = next_id;
+                next_id += 1;
+                tunnel.send(Frame::Open(id)).await?;
+                let connected = async { Ok(conn) };
+                streams.insert(id, spawn_stream(id, connected, to_tunnel.clone()));
+            }
+            Some(frame) = outgoing.recv() => {
+                if let Frame::Close(id) = frame {
+                    streams.remove(&id);
+                }
+                tunnel.send(frame).await?;
+            }
+        }
+    }
+}
+
+/// Opens a tunnel to the relay of the worldsync server and forwards the
+/// players it relays to our minecraft server at `mc_port`. Returns once
+/// the server closes the tunnel.
+#[instrument(err)]
+pub async fn host(
+    domain: &str,
+    port: u16,
+    session: SessionId,
+    host_id: HostId,
+    mc_port: u16,
+) -> io::Result<()> {
+    let conn = crate::connect::connect_tcp(domain, port).await?;
+    let mut tunnel = framed(conn);
+    tunnel.send(Frame::Hello { session, host_id }).await?;
+
+    let (_, no_players) = mpsc::channel(1);
+    let local = SocketAddr::from((Ipv4Addr::LOCALHOST, mc_port));
+    forward(tunnel, no_players, Some(local)).await
+}
@@ -0,0 +1,295 @@
+use protocol::{Addr, HostAddr, HostDetails, PortCheck, Reach, Uuid};
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Where an address is valid, until `ip.is_global()` is stabilized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Loopback,
+    /// private, link local or shared (carrier grade nat) address space
+    Private,
+    Public,
+}
+
+fn scope_v4(addr: Ipv4Addr) -> Scope {
+    let oct = addr.octets();
+    if addr.is_loopback() {
+        Scope::Loopback
+    } else if addr.is_private() || addr.is_link_local() {
+        Scope::Private
+    } else if oct[0] == 100 && (oct[1] & 0b1100_0000) == 64 {
+        // 100.64.0.0/10 is used for carrier grade nat
+        Scope::Private
+    } else {
+        Scope::Public
+    }
+}
+
+fn scope_v6(addr: Ipv6Addr) -> Scope {
+    if addr.is_loopback() {
+        return Scope::Loopback;
+    }
+    if let Some(v4) = addr.to_ipv4_mapped() {
+        return scope_v4(v4);
+    }
+    let first = addr.segments()[0];
+    if (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80 {
+        // unique local (fc00::/7) or link local (fe80::/10)
+        Scope::Private
+    } else {
+        Scope::Public
+    }
+}
+
+pub fn scope(ip: IpAddr) -> Scope {
+    match ip {
+        IpAddr::V4(addr) => scope_v4(addr),
+        IpAddr::V6(addr) => scope_v6(addr),
+    }
+}
+
+/// Addresses at which a host that connected from peer can be reached.
+/// A host on the same network as us can be reached using the domain
+/// (given the router forwards the port). Clients on the same network as
+/// the host can use its lan address. The router of the host may forward
+/// another port, or be reachable at another address, than the host
+/// knows if it mapped the port. A host using the relay can be reached at
+/// the relay port on our domain.
+pub fn candidates(
+    peer: IpAddr,
+    lan_ip: Option<IpAddr>,
+    mapped: Option<SocketAddr>,
+    domain: &str,
+    relay: Option<u16>,
+) -> Vec<HostAddr> {
+    let public_port = mapped.map(|addr| addr.port());
+    let peer_scope = scope(peer);
+    let mut addrs = vec![match peer_scope {
+        Scope::Public => HostAddr {
+            addr: Addr::Ip(peer),
+            reach: Reach::Public,
+            port: public_port,
+        },
+        _ => HostAddr {
+            addr: Addr::Ip(peer),
+            reach: Reach::Lan,
+            port: None,
+        },
+    }];
+
+    // a host on our network shares its router with us, the domain below
+    // points there already
+    if let Some(mapped) = mapped {
+        let other_public = mapped.ip() != peer && scope(mapped.ip()) == Scope::Public;
+        if peer_scope == Scope::Public && other_public {
+            addrs.push(HostAddr {
+                addr: Addr::Ip(mapped.ip()),
+                reach: Reach::Public,
+                port: Some(mapped.port()),
+            });
+        }
+    }
+
+    if let Some(ip) = lan_ip {
+        if ip != peer && scope(ip) == Scope::Private {
+            addrs.push(HostAddr {
+                addr: Addr::Ip(ip),
+                reach: Reach::Lan,
+                port: None,
+            });
+        }
+    }
+
+    if peer_scope != Scope::Public && !domain.is_empty() {
+        addrs.push(HostAddr {
+            addr: Addr::Domain(domain.to_owned()),
+            reach: Reach::Public,
+            port: public_port,
+        });
+    }
+
+    // without a domain clients can not find the relay
+    if let Some(port) = relay.filter(|_| !domain.is_empty()) {
+        addrs.push(HostAddr {
+            addr: Addr::Domain(domain.to_owned()),
+            reach: Reach::Relay,
+            port: Some(port),
+        });
+    }
+    addrs
+}
+
+/// Clients behind the same public address as the host, or that like the
+/// host connected from our network, share a network with it
+fn same_network(host: IpAddr, client: IpAddr) -> bool {
+    host == client || (scope(host) != Scope::Public && scope(client) != Scope::Public)
+}
+
+fn rank(candidate: &HostAddr, host: IpAddr, client: IpAddr) -> u8 {
+    let loopback = matches!(candidate.addr, Addr::Ip(ip) if ip.is_loopback());
+    match candidate.reach {
+        Reach::Lan if loopback && client.is_loopback() => 0,
+        Reach::Lan if !loopback && same_network(host, client) => 1,
+        // hosts only use the relay if they are not reachable otherwise
+        Reach::Relay => 2,
+        Reach::Public => 3,
+        Reach::Lan => 4,
+    }
+}
+
+/// order the hosts addresses from most to least suitable for client
+pub fn order_for(details: &mut HostDetails, client: IpAddr) {
+    let host = details.peer;
+    details
+        .addrs
+        .sort_by_key(|candidate| rank(candidate, host, client));
+}
+
+/// try to reach the listener a client opened on port, it proves it is
+/// the clients by answering with token
+pub async fn check_port(peer: IpAddr, port: u16, token: Uuid) -> PortCheck {
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    let mut conn = match timeout(TIMEOUT, TcpStream::connect((peer, port))).await {
+        Ok(Ok(conn)) => conn,
+        Ok(Err(e)) if e.kind() == ErrorKind::ConnectionRefused => return PortCheck::Refused,
+        Ok(Err(_)) | Err(_) => return PortCheck::NoAnswer,
+    };
+
+    let mut answer = [0u8; 16];
+    match timeout(TIMEOUT, conn.read_exact(&mut answer)).await {
+        Ok(Ok(_)) if answer == *token.as_bytes() => PortCheck::Open,
+        _ => PortCheck::WrongTarget,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::HostId;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn classify() {
+        let cases = [
+            ("127.0.0.1", Scope::Loopback),
+            ("10.1.2.3", Scope::Private),
+            ("172.16.0.1", Scope::Private),
+            ("172.31.255.255", Scope::Private),
+            ("172.32.0.1", Scope::Public),
+            ("192.168.1.10", Scope::Private),
+            ("169.254.0.1", Scope::Private),
+            ("100.64.0.1", Scope::Private),
+            ("100.128.0.1", Scope::Public),
+            ("8.8.8.8", Scope::Public),
+            ("::1", Scope::Loopback),
+            ("fd12:3456::1", Scope::Private),
+            ("fe80::1", Scope::Private),
+            ("::ffff:192.168.1.10", Scope::Private),
+            ("2001:db8::1", Scope::Public),
+        ];
+        for (addr, expected) in cases {
+            assert_eq!(scope(ip(addr)), expected, "{}", addr);
+        }
+    }
+
+    fn details(peer: &str, lan_ip: Option<&str>) -> HostDetails {
+        relayed(peer, lan_ip, None)
+    }
+
+    fn relayed(peer: &str, lan_ip: Option<&str>, relay: Option<u16>) -> HostDetails {
+        mapped(peer, lan_ip, None, relay)
+    }
+
+    fn mapped(
+        peer: &str,
+        lan_ip: Option<&str>,
+        mapped: Option<&str>,
+        relay: Option<u16>,
+    ) -> HostDetails {
+        let peer = ip(peer);
+        let mapped = mapped.map(|addr| addr.parse().unwrap());
+        HostDetails {
+            name: "host".into(),
+            addrs: candidates(peer, lan_ip.map(ip), mapped, "example.org", relay),
+            peer,
+            port: 25565,
+            id: HostId::new_v4(),
+        }
+    }
+
+    fn best_for(mut details: HostDetails, client: &str) -> String {
+        order_for(&mut details, ip(client));
+        details.server_address()
+    }
+
+    #[test]
+    fn host_on_our_network() {
+        let host = details("192.168.1.10", None);
+        assert_eq!(best_for(host.clone(), "192.168.1.20"), "192.168.1.10");
+        assert_eq!(best_for(host, "8.8.8.8"), "example.org");
+    }
+
+    #[test]
+    fn remote_host() {
+        let host = details("203.0.113.5", Some("192.168.2.7"));
+        assert_eq!(best_for(host.clone(), "203.0.113.5"), "192.168.2.7");
+        assert_eq!(best_for(host.clone(), "198.51.100.1"), "203.0.113.5");
+        assert_eq!(best_for(host, "2001:db8::1"), "203.0.113.5");
+    }
+
+    #[test]
+    fn ipv6_host() {
+        let host = details("2001:db8::7", Some("fd00::7"));
+        assert_eq!(best_for(host.clone(), "2001:db8::7"), "[fd00::7]:25565");
+        assert_eq!(best_for(host, "2001:db8::9"), "[2001:db8::7]:25565");
+    }
+
+    #[test]
+    fn host_on_server_machine() {
+        let host = details("127.0.0.1", Some("192.168.1.5"));
+        assert_eq!(best_for(host.clone(), "127.0.0.1"), "127.0.0.1");
+        assert_eq!(best_for(host.clone(), "192.168.1.20"), "192.168.1.5");
+        assert_eq!(best_for(host, "8.8.8.8"), "example.org");
+    }
+
+    #[test]
+    fn relayed_host() {
+        let host = relayed("203.0.113.5", Some("192.168.2.7"), Some(25600));
+        assert_eq!(best_for(host.clone(), "203.0.113.5"), "192.168.2.7");
+        assert_eq!(best_for(host, "198.51.100.1"), "example.org:25600");
+
+        let peer = ip("203.0.113.5");
+        let addrs = candidates(peer, None, None, "", Some(25600));
+        assert!(addrs.iter().all(|addr| addr.reach != Reach::Relay));
+    }
+
+    #[test]
+    fn mapped_host() {
+        let host = mapped(
+            "203.0.113.5",
+            Some("192.168.2.7"),
+            Some("203.0.113.5:25570"),
+            None,
+        );
+        assert_eq!(best_for(host.clone(), "203.0.113.5"), "192.168.2.7");
+        assert_eq!(best_for(host, "198.51.100.1"), "203.0.113.5:25570");
+
+        // behind a second nat the router maps on another public address
+        let host = mapped("203.0.113.5", None, Some("198.51.100.9:25565"), None);
+        let mut addrs: Vec<_> = host.addrs.iter().map(|a| a.addr.to_string()).collect();
+        addrs.sort();
+        assert_eq!(addrs, ["198.51.100.9", "203.0.113.5"]);
+
+        let host = mapped("192.168.1.10", None, Some("203.0.113.1:25570"), None);
+        assert_eq!(best_for(host.clone(), "192.168.1.20"), "192.168.1.10");
+        assert_eq!(best_for(host, "8.8.8.8"), "example.org:25570");
+    }
+}
@@ -1,10 +1,11 @@
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::time::{Duration, SystemTime};
 
 use shared::tarpc::context::Context;
 use shared::tarpc;
 use tarpc::context;
-use protocol::{User, UserId};
+use protocol::{ServerSettings, User, UserId, HOST_PROPERTIES, SETTINGS_FILES};
 
 use super::ServiceClient;
 use dialoguer::{Confirm, Input, Password, Select};
@@ -33,6 +34,12 @@ pub async fn main_menu(client: ServiceClient) {
             .item("Remove user")
             .item("Dump save")
             .item("Set save")
+            .item("Host timings")
+            .item("Console")
+            .item("Crash reports")
+            .item("Server settings")
+            .item("Server install")
+            .item("Minecraft version")
             .interact()
             .unwrap();
 
@@ -43,6 +50,12 @@ pub async fn main_menu(client: ServiceClient) {
             3 => ui.remove_user().await,
             4 => ui.dump_save().await,
             5 => ui.set_save().await,
+            6 => ui.host_timings().await,
+            7 => ui.console().await,
+            8 => ui.crash_reports().await,
+            9 => ui.server_settings().await,
+            10 => ui.set_install().await,
+            11 => ui.world_version().await,
             _ => unreachable!(),
         }
     }
@@ -233,6 +246,324 @@ impl Tui {
     }
 }
 
+impl Tui {
+    async fn host_timings(&self) {
+        let mut overrides = self
+            .client
+            .timing_overrides(context::current())
+            .await
+            .expect("rpc failure")
+            .unwrap();
+
+        loop {
+            let o = &overrides;
+            let fields = [
+                ("loading timeout", o.loading),
+                ("unreachable timeout", o.unreachable),
+                ("shutdown timeout", o.shutdown),
+                ("hand over timeout", o.hand_over),
+                ("ping interval", o.ping_interval),
+            ];
+            let items: Vec<String> = fields
+                .iter()
+                .map(|(name, value)| match value {
+                    Some(dur) => format!("{}: {}s", name, dur.as_secs()),
+                    None => format!("{}: [server default]", name),
+                })
+                .collect();
+
+            let selection = Select::new()
+                .with_prompt("override timings for this world")
+                .items(&items)
+                .item("abort")
+                .item("save and exit")
+                .interact()
+                .unwrap();
+
+            let o = &mut overrides;
+            match selection {
+                0 => change_duration(&mut o.loading, 0),
+                1 => change_duration(&mut o.unreachable, 0),
+                2 => change_duration(&mut o.shutdown, 0),
+                3 => change_duration(&mut o.hand_over, 0),
+                4 => change_duration(&mut o.ping_interval, 1),
+                5 => return,
+                6 => break,
+                _i => unimplemented!("{}", _i),
+            }
+        }
+
+        self.client
+            .set_timing_overrides(context::current(), overrides)
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        println!("saved timings, they apply from the next change in host state");
+    }
+}
+
+impl Tui {
+    async fn console(&self) {
+        loop {
+            let command: String = Input::new()
+                .with_prompt("command to run on the host, leave empty to go back")
+                .allow_empty(true)
+                .interact()
+                .unwrap();
+            if command.is_empty() {
+                return;
+            }
+
+            let lines = self
+                .client
+                .console_command(context::current(), command)
+                .await
+                .expect("rpc failure");
+            match lines {
+                Ok(lines) if lines.is_empty() => println!("[no output]"),
+                Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+                Err(e) => println!("could not run command: {}", e),
+            }
+        }
+    }
+}
+
+impl Tui {
+    async fn crash_reports(&self) {
+        let crashes = self
+            .client
+            .crash_reports(context::current(), 20)
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        if crashes.is_empty() {
+            println!("no host crashed yet");
+            return;
+        }
+
+        let list: Vec<_> = crashes
+            .iter()
+            .map(|c| format!("{}: {}", c.host, c.report.file_name))
+            .collect();
+        let selection = Select::new()
+            .with_prompt("pick a crash report to view")
+            .items(&list)
+            .item("cancel")
+            .interact()
+            .unwrap();
+        if let Some(crash) = crashes.get(selection) {
+            println!("{}", crash.report.text);
+        }
+    }
+}
+
+impl Tui {
+    async fn server_settings(&self) {
+        let settings = self
+            .client
+            .current_settings(context::current())
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        let files: Vec<_> = settings.files.keys().map(String::as_str).collect();
+        println!(
+            "server settings version {}, files: {}",
+            settings.version,
+            files.join(", ")
+        );
+
+        let selection = Select::new()
+            .item("Back")
+            .item("Set property")
+            .item(format!("Import files from: {:?}", settings_path()))
+            .item(format!("Export files to: {:?}", settings_path()))
+            .interact()
+            .unwrap();
+        match selection {
+            0 => (),
+            1 => self.set_property(settings).await,
+            2 => self.import_settings().await,
+            3 => export_settings(settings).await,
+            _ => unreachable!(),
+        }
+    }
+
+    async fn set_property(&self, mut settings: ServerSettings) {
+        let validate_key = |input: &String| {
+            if HOST_PROPERTIES.contains(&input.as_str()) {
+                Err("every host sets this property for itself")
+            } else {
+                Ok(())
+            }
+        };
+        let key: String = Input::new()
+            .with_prompt("property, for example motd")
+            .validate_with(validate_key)
+            .interact()
+            .unwrap();
+
+        let properties = settings
+            .files
+            .entry("server.properties".to_owned())
+            .or_default();
+        let mut config = wrapper::Config::parse(properties);
+        let value: String = Input::new()
+            .with_prompt("value, escaped as in server.properties")
+            .with_initial_text(config.get(&key).unwrap_or_default())
+            .allow_empty(true)
+            .interact()
+            .unwrap();
+        config.set(&key, &value);
+        *properties = config.to_string();
+        self.set_settings(settings.files).await;
+    }
+
+    async fn import_settings(&self) {
+        let mut files = BTreeMap::new();
+        for name in SETTINGS_FILES {
+            match tokio::fs::read_to_string(settings_path().join(name)).await {
+                Ok(content) => {
+                    files.insert(name.to_owned(), content);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => panic!("could not read {}: {:?}", name, e),
+            }
+        }
+        if files.is_empty() {
+            println!("no settings files found in {:?}", settings_path());
+            return;
+        }
+        self.set_settings(files).await;
+    }
+
+    async fn set_settings(&self, files: BTreeMap<String, String>) {
+        let version = self
+            .client
+            .set_settings(context::current(), files)
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        println!(
+            "saved server settings as version {}, hosts apply them when they start",
+            version
+        );
+    }
+}
+
+impl Tui {
+    async fn set_install(&self) {
+        let version = self
+            .client
+            .install_version(context::current())
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        match version {
+            Some(version) => println!("hosts run minecraft version: {}", version),
+            None => println!("no server install uploaded yet"),
+        }
+
+        let path = install_path();
+        if !path.join("server.jar").is_file() {
+            println!(
+                "to upload a server install put it in {:?}, with server.jar in the top folder",
+                path
+            );
+            return;
+        }
+        let version: String = Input::new()
+            .with_prompt(format!("minecraft version of the server in {:?}", path))
+            .interact()
+            .unwrap();
+        let path = tokio::fs::canonicalize(path).await.unwrap();
+
+        let mut context = Context::current();
+        context.deadline = SystemTime::now() + Duration::from_secs(60 * 20);
+        println!("started uploading server install, will be aborted after 20 minutes");
+        self.client
+            .set_install(context, version, path.clone())
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        println!("hosts will now run the server in: {:?}", path);
+    }
+}
+
+impl Tui {
+    async fn world_version(&self) {
+        let (world, approved) = self
+            .client
+            .world_version(context::current())
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        let world = world.unwrap_or_default();
+        println!(
+            "world was last saved by minecraft: {}",
+            world.game.as_deref().unwrap_or("unknown")
+        );
+        if let Some(data) = world.data {
+            println!("level.dat data version: {}", data);
+        }
+        if let Some(approved) = approved {
+            println!("hosts may also run approved version: {}", approved);
+        }
+
+        let approve = Confirm::new()
+            .with_prompt("approve hosting with another version? This upgrades the world")
+            .default(false)
+            .interact()
+            .unwrap();
+        if !approve {
+            return;
+        }
+        let game: String = Input::new()
+            .with_prompt("version as the minecraft server prints it, for example 1.18.2")
+            .interact()
+            .unwrap();
+        self.client
+            .approve_version(context::current(), game.clone())
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        println!("hosts may now run minecraft {}", game);
+    }
+}
+
+async fn export_settings(settings: ServerSettings) {
+    tokio::fs::create_dir_all(settings_path())
+        .await
+        .expect("could not create directory for settings");
+    for (name, content) in settings.files {
+        tokio::fs::write(settings_path().join(&name), content)
+            .await
+            .expect("could not write settings file");
+    }
+    println!("exported server settings to {:?}", settings_path());
+}
+
+/// min is the least number of seconds accepted
+fn change_duration(value: &mut Option<Duration>, min: u64) {
+    let validate = |input: &String| {
+        if input.is_empty() {
+            return Ok(());
+        }
+        match input.parse::<u64>() {
+            Ok(secs) if secs >= min => Ok(()),
+            Ok(_) => Err(format!("must be at least {} seconds", min)),
+            Err(_) => Err("not a number of seconds".to_owned()),
+        }
+    };
+
+    let input: String = Input::new()
+        .with_prompt("seconds, leave empty for the server default")
+        .allow_empty(true)
+        .validate_with(validate)
+        .interact()
+        .unwrap();
+    *value = input.parse().ok().map(Duration::from_secs);
+}
+
 fn change_username(user: &mut User) {
     let validate_username = |input: &String| {
         if input.len() > 2 || input.is_empty() {
@@ -283,3 +614,11 @@ fn change_password(pass: &mut Option<String>) {
 fn dump_path() -> &'static Path {
     Path::new("save_dump")
 }
+
+fn settings_path() -> &'static Path {
+    Path::new("settings")
+}
+
+fn install_path() -> &'static Path {
+    Path::new("server_install")
+}
@@ -0,0 +1,80 @@
+//! Bridges chat between the players on the host and everyone logged in.
+//! Messages from worldsync clients reach the game as the host relays
+//! every chat event that did not come from the game using `/say`.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use protocol::{ChatMessage, Event};
+use tokio::sync::broadcast;
+use wrapper::parser::{Line, Message};
+
+/// messages kept for those that log in later
+pub const HISTORY_LEN: usize = 100;
+
+/// senders of `/say` run from the console or over rcon
+const CONSOLE: &[&str] = &["Server", "Rcon"];
+
+/// chat said in game, lines from the server console or rcon are left
+/// out. That includes the messages the host relays from worldsync clients
+pub fn from_line(line: Line) -> Option<ChatMessage> {
+    match line.msg {
+        Message::Chat { from, .. } if CONSOLE.contains(&from.as_str()) => None,
+        Message::Chat { from, msg } => Some(ChatMessage {
+            from,
+            text: msg,
+            in_game: true,
+        }),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Chat {
+    /// oldest first
+    history: Arc<Mutex<VecDeque<ChatMessage>>>,
+}
+
+impl Chat {
+    /// remembers the message and passes it to everyone logged in
+    pub fn push(&self, msg: ChatMessage, broadcast: &broadcast::Sender<Event>) {
+        let mut history = self.history.lock().unwrap();
+        if history.len() == HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(msg.clone());
+        let _irrelevant = broadcast.send(Event::Chat(msg));
+    }
+
+    pub fn history(&self) -> Vec<ChatMessage> {
+        self.history.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(n: usize) -> ChatMessage {
+        ChatMessage {
+            from: "Alice".to_owned(),
+            text: n.to_string(),
+            in_game: true,
+        }
+    }
+
+    #[test]
+    fn bounded_history() {
+        let (broadcast, mut events) = broadcast::channel(2 * HISTORY_LEN);
+        let chat = Chat::default();
+        for n in 0..HISTORY_LEN + 5 {
+            chat.push(msg(n), &broadcast);
+        }
+
+        let history = chat.history();
+        assert_eq!(history.len(), HISTORY_LEN);
+        assert_eq!(history[0], msg(5));
+        assert_eq!(history[HISTORY_LEN - 1], msg(HISTORY_LEN + 4));
+        assert!(matches!(events.try_recv(), Ok(Event::Chat(m)) if m == msg(0)));
+    }
+}
@@ -0,0 +1,61 @@
+//! Runs admin commands on the minecraft server of the host. Commands reach
+//! the host as an event, it returns the output through an rpc call which
+//! we pass on to the admin waiting for it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use protocol::{CommandId, Error, Event};
+use tokio::sync::{broadcast, oneshot};
+use tracing::warn;
+
+/// how long we wait on the host to return the output of a command. The
+/// host may wait [`wrapper::COMMAND_TIMEOUT`] on the output, the rest is
+/// for the round trip. Well within the default deadline of the admins rpc
+/// call
+pub const TIMEOUT: Duration = Duration::from_secs(wrapper::COMMAND_TIMEOUT.as_secs() + 3);
+
+#[derive(Clone, Debug, Default)]
+pub struct Console {
+    /// admins waiting on output, by command
+    pending: Arc<Mutex<HashMap<CommandId, oneshot::Sender<Vec<String>>>>>,
+}
+
+impl Console {
+    /// passes the command to the host and waits for its output
+    pub async fn run(
+        &self,
+        command: String,
+        broadcast: &broadcast::Sender<Event>,
+    ) -> Result<Vec<String>, Error> {
+        let command = command.trim().trim_start_matches('/').to_owned();
+        // the command ends up on the console of the host, a newline would
+        // let it run a second command
+        if command.is_empty() || command.chars().any(char::is_control) {
+            return Err(Error::InvalidCommand);
+        }
+
+        let id = CommandId::new_v4();
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+        let _irrelevant = broadcast.send(Event::ConsoleCommand { id, command });
+
+        let res = tokio::time::timeout(TIMEOUT, rx).await;
+        self.pending.lock().unwrap().remove(&id);
+        match res {
+            Ok(Ok(lines)) => Ok(lines),
+            _ => Err(Error::NoConsoleOutput),
+        }
+    }
+
+    /// hands the output of a command to the admin that send it
+    pub fn output(&self, id: CommandId, lines: Vec<String>) {
+        match self.pending.lock().unwrap().remove(&id) {
+            Some(tx) => {
+                let _admin_gave_up = tx.send(lines);
+            }
+            None => warn!("got output for unknown console command: {}", id),
+        }
+    }
+}
@@ -1,3 +1,5 @@
+pub mod settings;
+pub mod stats;
 pub mod user;
 pub mod world;
 
@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+
+use protocol::ServerSettings;
+use typed_sled::{sled, Tree};
+
+/// every change to the server settings is kept as a new version
+#[derive(Clone, Debug)]
+pub struct SettingsDb {
+    /// files by version, big endian so the latest is last
+    versions: Tree<[u8; 8], BTreeMap<String, String>>,
+}
+
+impl SettingsDb {
+    pub fn from(db: sled::Db) -> Self {
+        let versions = Tree::open(&db, "server_settings");
+        Self { versions }
+    }
+
+    pub fn current(&self) -> Result<ServerSettings, sled::Error> {
+        Ok(match self.versions.last()? {
+            Some((key, files)) => ServerSettings {
+                version: u64::from_be_bytes(key),
+                files,
+            },
+            None => ServerSettings::default(),
+        })
+    }
+
+    /// returns the new version
+    pub fn set(&self, files: &BTreeMap<String, String>) -> Result<u64, sled::Error> {
+        let version = self.current()?.version + 1;
+        self.versions.insert(&version.to_be_bytes(), files)?;
+        Ok(version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        let db = SettingsDb::from(crate::db::test_db());
+        assert_eq!(db.current().unwrap(), ServerSettings::default());
+
+        let mut files = BTreeMap::new();
+        files.insert("ops.json".to_owned(), "[]".to_owned());
+        assert_eq!(db.set(&files).unwrap(), 1);
+        files.insert("whitelist.json".to_owned(), "[]".to_owned());
+        assert_eq!(db.set(&files).unwrap(), 2);
+
+        let current = db.current().unwrap();
+        assert_eq!(current.version, 2);
+        assert_eq!(current.files, files);
+    }
+}
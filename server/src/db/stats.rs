@@ -0,0 +1,184 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use protocol::{CrashRecord, HostId, HostingRecord, PlayerStats};
+use serde::{Deserialize, Serialize};
+use typed_sled::{sled, Tree};
+
+/// time a minecraft player spent on one host
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlaySession {
+    pub player: String,
+    /// worldsync name of the host
+    pub host: String,
+    pub host_id: HostId,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    /// part of the session during which the host was unreachable
+    pub unreachable: Duration,
+}
+
+impl PlaySession {
+    fn duration(&self) -> Duration {
+        self.end.duration_since(self.start).unwrap_or_default()
+    }
+}
+
+#[derive(Clone)]
+pub struct StatsDb {
+    db: sled::Db,
+    /// by an increasing id
+    sessions: Tree<u64, PlaySession>,
+    /// by an increasing id
+    hosting: Tree<u64, HostingRecord>,
+    /// by an increasing id
+    crashes: Tree<u64, CrashRecord>,
+}
+
+impl StatsDb {
+    pub fn from(db: sled::Db) -> Self {
+        let sessions = Tree::open(&db, "play_sessions");
+        let hosting = Tree::open(&db, "hosting_history");
+        let crashes = Tree::open(&db, "crash_reports");
+        Self {
+            db,
+            sessions,
+            hosting,
+            crashes,
+        }
+    }
+
+    pub fn add_session(&self, session: &PlaySession) -> Result<(), sled::Error> {
+        let id = self.db.generate_id()?;
+        self.sessions.insert(&id, session)?;
+        Ok(())
+    }
+
+    pub fn add_hosting(&self, record: &HostingRecord) -> Result<(), sled::Error> {
+        let id = self.db.generate_id()?;
+        self.hosting.insert(&id, record)?;
+        Ok(())
+    }
+
+    pub fn add_crash(&self, record: &CrashRecord) -> Result<(), sled::Error> {
+        let id = self.db.generate_id()?;
+        self.crashes.insert(&id, record)?;
+        Ok(())
+    }
+
+    fn totals(&self) -> Result<HashMap<String, PlayerStats>, sled::Error> {
+        let mut totals = HashMap::new();
+        for session in self.sessions.iter().values() {
+            let session = session?;
+            let played = session.duration();
+            let stats = totals
+                .entry(session.player.clone())
+                .or_insert_with(|| PlayerStats {
+                    player: session.player.clone(),
+                    sessions: 0,
+                    played: Duration::ZERO,
+                    unreachable: Duration::ZERO,
+                    per_host: Vec::new(),
+                });
+            stats.sessions += 1;
+            stats.played += played;
+            stats.unreachable += session.unreachable;
+            match stats.per_host.iter_mut().find(|(h, _)| *h == session.host) {
+                Some((_, on_host)) => *on_host += played,
+                None => stats.per_host.push((session.host, played)),
+            }
+        }
+        for stats in totals.values_mut() {
+            stats.per_host.sort_by_key(|(_, played)| Reverse(*played));
+        }
+        Ok(totals)
+    }
+
+    pub fn player_stats(&self, player: &str) -> Result<Option<PlayerStats>, sled::Error> {
+        Ok(self.totals()?.remove(player))
+    }
+
+    /// most played first, ties ordered by name
+    pub fn leaderboard(&self, limit: usize) -> Result<Vec<PlayerStats>, sled::Error> {
+        let mut board: Vec<_> = self.totals()?.into_values().collect();
+        board.sort_by(|a, b| {
+            b.played
+                .cmp(&a.played)
+                .then_with(|| a.player.cmp(&b.player))
+        });
+        board.truncate(limit);
+        Ok(board)
+    }
+
+    /// latest first
+    pub fn hosting_history(&self, limit: usize) -> Result<Vec<HostingRecord>, sled::Error> {
+        let mut history = self
+            .hosting
+            .iter()
+            .values()
+            .collect::<Result<Vec<_>, _>>()?;
+        history.sort_by_key(|record| Reverse(record.start));
+        history.truncate(limit);
+        Ok(history)
+    }
+
+    /// latest first
+    pub fn crashes(&self, limit: usize) -> Result<Vec<CrashRecord>, sled::Error> {
+        let mut crashes = self
+            .crashes
+            .iter()
+            .values()
+            .collect::<Result<Vec<_>, _>>()?;
+        crashes.sort_by_key(|record| Reverse(record.at));
+        crashes.truncate(limit);
+        Ok(crashes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(player: &str, host: &str, start: u64, mins: u64) -> PlaySession {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(start * 60);
+        PlaySession {
+            player: player.to_owned(),
+            host: host.to_owned(),
+            host_id: HostId::new_v4(),
+            start,
+            end: start + Duration::from_secs(mins * 60),
+            unreachable: Duration::from_secs(mins),
+        }
+    }
+
+    #[test]
+    fn totals() {
+        let stats = StatsDb::from(super::super::test_db());
+        stats.add_session(&session("Alice", "bob", 0, 30)).unwrap();
+        stats
+            .add_session(&session("Alice", "carol", 60, 10))
+            .unwrap();
+        stats
+            .add_session(&session("Alice", "bob", 120, 20))
+            .unwrap();
+        stats.add_session(&session("Dave", "bob", 0, 90)).unwrap();
+
+        let alice = stats.player_stats("Alice").unwrap().unwrap();
+        assert_eq!(alice.sessions, 3);
+        assert_eq!(alice.played, Duration::from_secs(60 * 60));
+        assert_eq!(alice.unreachable, Duration::from_secs(60));
+        assert_eq!(
+            alice.per_host,
+            [
+                ("bob".to_owned(), Duration::from_secs(50 * 60)),
+                ("carol".to_owned(), Duration::from_secs(10 * 60))
+            ]
+        );
+        assert_eq!(stats.player_stats("Eve").unwrap(), None);
+
+        let board = stats.leaderboard(1).unwrap();
+        assert_eq!(board.len(), 1);
+        assert_eq!(board[0].player, "Dave");
+    }
+}
@@ -1,4 +1,5 @@
 use core::fmt;
+use protocol::{ServerInstall, TimingOverrides, WorldVersion};
 use std::io;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
@@ -32,6 +33,8 @@ pub struct WorldDb {
     db: sled::Db,
     objects: Tree<StoreKey, ObjectId>,
     saves: sled::Tree, // save by a id (saveId)
+    /// version each save was made with, same keys as saves
+    versions: sled::Tree,
 }
 
 impl fmt::Debug for WorldDb {
@@ -84,11 +87,17 @@ impl WorldDb {
     pub async fn from(db: sled::Db) -> Self {
         let objects = Tree::open(&db, "objects");
         let saves = db.open_tree("saves").unwrap();
+        let versions = db.open_tree("save_versions").unwrap();
         if !Self::store_path().exists() {
             fs::create_dir(Self::store_path()).await.unwrap();
         }
 
-        WorldDb { objects, db, saves }
+        WorldDb {
+            objects,
+            db,
+            saves,
+            versions,
+        }
     }
 
     pub fn last_save(&self) -> Save {
@@ -98,7 +107,55 @@ impl WorldDb {
         }
     }
 
-    pub fn push_save(&self, save: Save) {
+    pub fn timing_overrides(&self) -> TimingOverrides {
+        match self.db.get("timing_overrides").unwrap() {
+            Some(bytes) => bincode::deserialize(&bytes).unwrap(),
+            None => TimingOverrides::default(),
+        }
+    }
+
+    pub fn set_timing_overrides(&self, overrides: &TimingOverrides) {
+        let bytes = bincode::serialize(overrides).unwrap();
+        self.db.insert("timing_overrides", bytes).unwrap();
+    }
+
+    pub fn server_install(&self) -> Option<ServerInstall> {
+        self.db
+            .get("server_install")
+            .unwrap()
+            .map(|bytes| bincode::deserialize(&bytes).unwrap())
+    }
+
+    pub fn set_server_install(&self, install: &ServerInstall) {
+        let bytes = bincode::serialize(install).unwrap();
+        self.db.insert("server_install", bytes).unwrap();
+    }
+
+    /// none if the latest save predates recording versions
+    pub fn world_version(&self) -> Option<WorldVersion> {
+        self.versions
+            .last()
+            .unwrap()
+            .map(|(_, bytes)| bincode::deserialize(&bytes).unwrap())
+    }
+
+    pub fn approved_version(&self) -> Option<String> {
+        self.db
+            .get("approved_version")
+            .unwrap()
+            .map(|bytes| bincode::deserialize(&bytes).unwrap())
+    }
+
+    pub fn set_approved_version(&self, game: &str) {
+        let bytes = bincode::serialize(game).unwrap();
+        self.db.insert("approved_version", bytes).unwrap();
+    }
+
+    pub fn clear_approved_version(&self) {
+        self.db.remove("approved_version").unwrap();
+    }
+
+    pub fn push_save(&self, save: Save, version: &WorldVersion) {
         // TODO on what key do we insert saves?
         // for now use time
         let unix_timestamp = SystemTime::now()
@@ -107,6 +164,8 @@ impl WorldDb {
         let key = unix_timestamp.as_secs().to_be_bytes();
         let bytes = bincode::serialize(&save).unwrap();
         self.saves.insert(key, bytes).unwrap();
+        let bytes = bincode::serialize(version).unwrap();
+        self.versions.insert(key, bytes).unwrap();
     }
 
     #[instrument(err)]
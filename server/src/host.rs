@@ -1,36 +1,120 @@
+use std::collections::BTreeSet;
+use std::net::{IpAddr, Ipv4Addr};
 use std::sync::Arc;
+use std::time::SystemTime;
 
-use protocol::{Addr, Event, HostDetails, HostState};
-use tokio::sync::{broadcast, mpsc, RwLock};
+use async_minecraft_ping::ServerError;
+use protocol::TimingOverrides;
+use protocol::{Addr, Event, HostDetails, HostState, HostStatus, HostTimings, Reach};
+use tokio::sync::broadcast::{self, error::SendError};
+use tokio::sync::{mpsc, RwLock};
 use tokio::time::{self, sleep, Duration, Instant};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
-async fn unreachable(addr: &Addr, port: u16) {
-    use async_minecraft_ping::ConnectionConfig;
-    async fn inner(addr: &Addr, port: u16) -> Result<(), ()> {
-        loop {
-            sleep(Duration::from_secs(5)).await;
-            let _status = ConnectionConfig::build(addr.to_string())
-                .with_port(port)
-                .connect()
-                .await
-                .map_err(|_| ())?
-                .status()
-                .await;
+use crate::stats::Stats;
+
+async fn ping(addr: IpAddr, port: u16) -> Result<HostStatus, ServerError> {
+    use async_minecraft_ping::{ConnectionConfig, ServerDescription};
+    let conn = ConnectionConfig::build(addr.to_string())
+        .with_port(port)
+        .connect()
+        .await?
+        .status()
+        .await?;
+
+    let response = &conn.status;
+    let motd = match &response.description {
+        ServerDescription::Plain(text) => text.clone(),
+        ServerDescription::Object { text } => text.clone(),
+    };
+    let sample = response
+        .players
+        .sample
+        .iter()
+        .flatten()
+        .map(|player| player.name.clone())
+        .collect();
+    let version = response.version.name.clone();
+    let online = response.players.online;
+    let max_players = response.players.max;
+
+    let start = Instant::now();
+    conn.ping(0).await?;
+    Ok(HostStatus {
+        motd,
+        version,
+        online,
+        max_players,
+        sample,
+        latency: start.elapsed(),
+    })
+}
+
+/// hosts using the relay are pinged through it, others at the port their
+/// router forwards
+fn ping_target(details: &HostDetails) -> (IpAddr, u16) {
+    let relay = details
+        .addrs
+        .iter()
+        .find(|addr| addr.reach == Reach::Relay)
+        .and_then(|addr| addr.port);
+    if let Some(port) = relay {
+        return (Ipv4Addr::LOCALHOST.into(), port);
+    }
+
+    let port = details
+        .addrs
+        .iter()
+        .find(|addr| matches!(addr.addr, Addr::Ip(ip) if ip == details.peer))
+        .and_then(|addr| addr.port)
+        .unwrap_or(details.port);
+    (details.peer, port)
+}
+
+/// keeps the status, it is only broadcast if it changed. Otherwise every
+/// ping would fill the event queue of those not listening
+async fn record(status: HostStatus, host: &Host, broadcast: &BroadCast) {
+    let mut current = host.status.write().await;
+    // the latency is different every ping
+    let changed = match &*current {
+        Some(old) => {
+            *old != HostStatus {
+                latency: old.latency,
+                ..status.clone()
+            }
         }
+        None => true,
+    };
+    *current = Some(status.clone());
+    if changed {
+        let _irrelevant = broadcast.send(Event::HostStatus(status));
     }
+}
 
-    let _ignore_res = inner(addr, port).await;
+/// pings the host until it stops responding, recording each status
+async fn unreachable(
+    details: &HostDetails,
+    interval: Duration,
+    host: &Host,
+    broadcast: &BroadCast,
+) {
+    loop {
+        sleep(interval).await;
+        let (addr, port) = ping_target(details);
+        match ping(addr, port).await {
+            Ok(status) => record(status, host, broadcast).await,
+            Err(_) => return,
+        }
+    }
 }
 
-async fn reachable(addr: &Addr, port: u16) {
-    use async_minecraft_ping::ConnectionConfig;
-    while let Err(_) = ConnectionConfig::build(addr.to_string())
-        .with_port(port)
-        .connect()
-        .await
-    {
-        sleep(Duration::from_secs(5)).await;
+async fn reachable(details: &HostDetails, interval: Duration, host: &Host, broadcast: &BroadCast) {
+    loop {
+        let (addr, port) = ping_target(details);
+        match ping(addr, port).await {
+            Ok(status) => return record(status, host, broadcast).await,
+            Err(_) => sleep(interval).await,
+        }
     }
 }
 
@@ -41,8 +125,14 @@ pub enum HostEvent {
     Loading(u8),
     Loaded,
     RequestToHost(HostDetails),
+    RequestTakeover(HostDetails),
+    HandOver,
     ShuttingDown,
-    ShutDown,
+    /// the host has stopped and (unless saved is false) uploaded its
+    /// final save
+    ShutDown {
+        saved: bool,
+    },
 }
 
 use wrapper::parser::Line;
@@ -59,15 +149,50 @@ impl TryFrom<Line> for HostEvent {
     }
 }
 
+/// players joining or leaving the minecraft server of the current host,
+/// unlike HostEvents these do not change the host state
+#[derive(Debug)]
+pub enum Presence {
+    Joined(String),
+    Left(String),
+}
+
+impl TryFrom<Line> for Presence {
+    type Error = Line;
+    fn try_from(line: Line) -> Result<Self, Self::Error> {
+        use wrapper::parser::Message::*;
+        match line.msg {
+            Joined { user, .. } => Ok(Presence::Joined(user)),
+            Left(user) | Kicked(user) => Ok(Presence::Left(user)),
+            _ => Err(line),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Host {
     pub state: Arc<RwLock<HostState>>,
+    /// server wide timings, from the configuration
+    defaults: HostTimings,
+    /// the defaults with the worlds overrides applied
+    timings: Arc<RwLock<HostTimings>>,
+    /// latest status reported by the hosts minecraft server
+    status: Arc<RwLock<Option<HostStatus>>>,
+    /// minecraft names of the players on the current host
+    players: Arc<RwLock<BTreeSet<String>>>,
+    /// who is waiting for the current host to hand over
+    takeover: Arc<RwLock<Option<HostDetails>>>,
 }
 
 impl Host {
-    pub fn new() -> Host {
+    pub fn new(defaults: HostTimings) -> Host {
         Self {
             state: Arc::new(RwLock::new(HostState::NoHost)),
+            defaults,
+            timings: Arc::new(RwLock::new(defaults)),
+            status: Arc::new(RwLock::new(None)),
+            players: Arc::new(RwLock::new(BTreeSet::new())),
+            takeover: Arc::new(RwLock::new(None)),
         }
     }
 }
@@ -79,6 +204,33 @@ impl Host {
     pub async fn set_state(&self, new: HostState) {
         *self.state.write().await = new;
     }
+    pub async fn get_status(&self) -> Option<HostStatus> {
+        self.status.read().await.clone()
+    }
+    pub async fn get_players(&self) -> Vec<String> {
+        self.players.read().await.iter().cloned().collect()
+    }
+    /// only broadcasts actual changes, a kicked player is also reported
+    /// as having left
+    pub async fn update_players(&self, presence: Presence, broadcast: &BroadCast) {
+        let mut players = self.players.write().await;
+        let event = match presence {
+            Presence::Joined(name) if players.insert(name.clone()) => Event::PlayerJoined(name),
+            Presence::Left(name) if players.remove(&name) => Event::PlayerLeft(name),
+            _ => return,
+        };
+        let _irrelevant = broadcast.send(event);
+    }
+    pub async fn get_takeover(&self) -> Option<HostDetails> {
+        self.takeover.read().await.clone()
+    }
+    pub async fn get_timings(&self) -> HostTimings {
+        *self.timings.read().await
+    }
+    /// takes effect from the next change in host state
+    pub async fn apply_overrides(&self, overrides: &TimingOverrides) {
+        *self.timings.write().await = self.defaults.with(overrides);
+    }
 }
 
 async fn new_host(broadcast: &mut BroadCast, events: &mut Reciever) -> HostState {
@@ -96,15 +248,16 @@ async fn new_host(broadcast: &mut BroadCast, events: &mut Reciever) -> HostState
 
 async fn loaded_or_timeout(
     host: HostDetails,
+    timings: &HostTimings,
     broadcast: &mut BroadCast,
     events: &mut Reciever,
 ) -> HostState {
-    let mut deadline = Instant::now() + Duration::from_secs(5 * 60);
+    let mut deadline = Instant::now() + timings.loading;
     while let Ok(event) = time::timeout_at(deadline, events.recv()).await {
         match event.unwrap() {
             HostEvent::Loading(p) => {
                 let _irrelevant = broadcast.send(Event::HostLoading(p));
-                deadline = Instant::now() + Duration::from_secs(5 * 60);
+                deadline = Instant::now() + timings.loading;
             }
             HostEvent::Loaded => {
                 let _irrelevant = broadcast.send(Event::HostLoaded);
@@ -120,37 +273,120 @@ async fn loaded_or_timeout(
     HostState::NoHost
 }
 
-async fn got_shutdown_msg(events: &mut Reciever) {
+enum UpMsg {
+    ShuttingDown,
+    ShutDown { saved: bool },
+    Takeover(HostDetails),
+}
+
+async fn got_up_msg(events: &mut Reciever) -> UpMsg {
     loop {
-        if let Some(HostEvent::ShuttingDown) = events.recv().await {
-            break;
+        match events.recv().await {
+            Some(HostEvent::ShuttingDown) => return UpMsg::ShuttingDown,
+            Some(HostEvent::ShutDown { saved }) => return UpMsg::ShutDown { saved },
+            Some(HostEvent::RequestTakeover(new)) => return UpMsg::Takeover(new),
+            _e => error!("should not recieve: {:?} in state Up", _e),
         }
     }
 }
 
+fn shutting_down(host: HostDetails, broadcast: &mut BroadCast) -> HostState {
+    let _irrelevant = broadcast.send(Event::HostShuttingDown);
+    info!("host shutting down, host: {:?}", host);
+    HostState::ShuttingDown(host)
+}
+
 async fn shutdown_or_unreachable(
     host: HostDetails,
+    timings: &HostTimings,
+    record: &Host,
     broadcast: &mut BroadCast,
     events: &mut Reciever,
 ) -> HostState {
+    let pings = broadcast.clone();
     tokio::select! {
-        _ = unreachable(&host.addr, host.port) => {
+        _ = unreachable(&host, timings.ping_interval, record, &pings) => {
             let _irrelevant = broadcast.send(Event::HostUnreachable);
             info!("host unreachable, host: {:?}", host);
             HostState::Unreachable(host)
         }
-        _ = got_shutdown_msg(events) => {
-            let _irrelevant = broadcast.send(Event::HostShuttingDown);
-            info!("host shutting down, host: {:?}", host);
-            HostState::ShuttingDown(host)
+        msg = got_up_msg(events) => match msg {
+            UpMsg::ShuttingDown => shutting_down(host, broadcast),
+            UpMsg::ShutDown { saved } => shut_down(host, saved, broadcast),
+            UpMsg::Takeover(new) => {
+                *record.takeover.write().await = Some(new.clone());
+                let state = handed_over_or_timeout(host, new, timings, broadcast, events).await;
+                *record.takeover.write().await = None;
+                state
+            }
+        }
+    }
+}
+
+/// The current host stays up while it warns its players and uploads a
+/// final save. It keeps hosting if it does not hand over in time. Should
+/// it stop hosting instead the takeover fails.
+async fn handed_over_or_timeout(
+    host: HostDetails,
+    new: HostDetails,
+    timings: &HostTimings,
+    broadcast: &mut BroadCast,
+    events: &mut Reciever,
+) -> HostState {
+    enum HandOverMsg {
+        HandOver,
+        ShuttingDown,
+        ShutDown { saved: bool },
+    }
+
+    async fn got_hand_over(events: &mut Reciever) -> HandOverMsg {
+        loop {
+            match events.recv().await {
+                Some(HostEvent::HandOver) => return HandOverMsg::HandOver,
+                Some(HostEvent::ShuttingDown) => return HandOverMsg::ShuttingDown,
+                Some(HostEvent::ShutDown { saved }) => return HandOverMsg::ShutDown { saved },
+                _e => error!("should not recieve: {:?} while handing over", _e),
+            }
+        }
+    }
+
+    let _irrelevant = broadcast.send(Event::TakeoverRequested(new.name.clone()));
+    info!("takeover requested by: {:?}, host: {:?}", new, host);
+
+    match time::timeout(timings.hand_over, got_hand_over(events)).await {
+        Ok(HandOverMsg::HandOver) => {
+            info!(
+                "host handed over, old host: {:?}, new host: {:?}",
+                host, new
+            );
+            let _irrelevant = broadcast.send(Event::NewHost(new.clone()));
+            HostState::Loading(new)
+        }
+        Ok(HandOverMsg::ShuttingDown) => {
+            let _irrelevant = broadcast.send(Event::TakeoverFailed);
+            shutting_down(host, broadcast)
+        }
+        Ok(HandOverMsg::ShutDown { saved }) => {
+            let _irrelevant = broadcast.send(Event::TakeoverFailed);
+            shut_down(host, saved, broadcast)
+        }
+        Err(_) => {
+            let _irrelevant = broadcast.send(Event::TakeoverFailed);
+            info!("host did not hand over in time, host: {:?}", host);
+            HostState::Up(host)
         }
     }
 }
 
-async fn up_or_timeout(host: HostDetails, broadcast: &mut BroadCast) -> HostState {
+async fn up_or_timeout(
+    host: HostDetails,
+    timings: &HostTimings,
+    record: &Host,
+    broadcast: &mut BroadCast,
+) -> HostState {
     match time::timeout(
-        Duration::from_secs(5 * 60),
-        reachable(&host.addr, host.port),
+        timings.unreachable,
+        reachable(&host, timings.ping_interval, record, broadcast),
     )
     .await
     {
@@ -162,34 +398,43 @@ async fn up_or_timeout(host: HostDetails, broadcast: &mut BroadCast) -> HostStat
         Err(_) => {
             let _irrelevant = broadcast.send(Event::HostDropped);
             info!(
-                "host unreachable for 5 minutes, dropping host, host: {:?}",
-                host
+                "host unreachable for {:?}, dropping host, host: {:?}",
+                timings.unreachable, host
             );
             HostState::NoHost //TODO annotate broken save
         }
     }
 }
 
+fn shut_down(host: HostDetails, saved: bool, broadcast: &mut BroadCast) -> HostState {
+    let _irrelevant = broadcast.send(Event::HostShutdown);
+    if saved {
+        info!("host shut down okay, host: {:?}", host);
+    } else {
+        warn!("host shut down without final save, host: {:?}", host);
+    }
+    HostState::NoHost
+}
+
+/// The host uploads a final save after the minecraft server exits, we keep
+/// it as host until it releases us or the upload failed.
 async fn shut_down_or_timeout(
     host: HostDetails,
+    timings: &HostTimings,
     broadcast: &mut BroadCast,
     events: &mut Reciever,
 ) -> HostState {
-    async fn got_shutdown(events: &mut Reciever) {
+    async fn got_shutdown(events: &mut Reciever) -> bool {
         loop {
             match events.recv().await {
-                Some(HostEvent::ShutDown) => return,
-                _e => error!("should not recieve: {:?} in state Loading", _e),
+                Some(HostEvent::ShutDown { saved }) => return saved,
+                _e => error!("should not recieve: {:?} in state ShuttingDown", _e),
             }
         }
     }
 
-    match time::timeout(Duration::from_secs(5 * 60), got_shutdown(events)).await {
-        Ok(_) => {
-            let _irrelevant = broadcast.send(Event::HostShutdown);
-            info!("host shut down okay, host: {:?}", host);
-            HostState::NoHost
-        }
+    match time::timeout(timings.shutdown, got_shutdown(events)).await {
+        Ok(saved) => shut_down(host, saved, broadcast),
         Err(_) => {
             let _irrelevant = broadcast.send(Event::HostShutdown);
             info!("host shutdown timed out, host: {:?}", host);
@@ -198,22 +443,87 @@ async fn shut_down_or_timeout(
     }
 }
 
-type BroadCast = Arc<broadcast::Sender<protocol::Event>>;
+/// passes events to everyone logged in. The statistics get every event
+/// directly, unlike the clients they can not catch up on missed events
+#[derive(Clone, Debug)]
+pub struct BroadCast {
+    events: Arc<broadcast::Sender<Event>>,
+    stats: Stats,
+}
+
+impl BroadCast {
+    pub fn new(events: Arc<broadcast::Sender<Event>>, stats: Stats) -> Self {
+        Self { events, stats }
+    }
+    pub fn send(&self, event: Event) -> Result<usize, SendError<Event>> {
+        self.stats.handle(&event, SystemTime::now());
+        self.events.send(event)
+    }
+}
+
 type Reciever = mpsc::Receiver<HostEvent>;
 pub async fn monitor(host: Host, mut broadcast: BroadCast, mut events: Reciever) {
     loop {
         // host state may only be changed here
         let current = host.get_state().await;
+        let t = host.get_timings().await;
+        let record = host.clone();
         let new = match current {
             HostState::NoHost => new_host(&mut broadcast, &mut events).await,
-            HostState::Loading(host) => loaded_or_timeout(host, &mut broadcast, &mut events).await,
-            HostState::Up(host) => shutdown_or_unreachable(host, &mut broadcast, &mut events).await,
-            HostState::Unreachable(host) => up_or_timeout(host, &mut broadcast).await,
+            HostState::Loading(host) => {
+                loaded_or_timeout(host, &t, &mut broadcast, &mut events).await
+            }
+            HostState::Up(host) => {
+                shutdown_or_unreachable(host, &t, &record, &mut broadcast, &mut events).await
+            }
+            HostState::Unreachable(host) => up_or_timeout(host, &t, &record, &mut broadcast).await,
             HostState::ShuttingDown(host) => {
-                shut_down_or_timeout(host, &mut broadcast, &mut events).await
+                shut_down_or_timeout(host, &t, &mut broadcast, &mut events).await
             }
         };
 
+        if !matches!(new, HostState::Up(_) | HostState::Unreachable(_)) {
+            *host.status.write().await = None;
+        }
+        // players leave a server that is shutting down one by one
+        if matches!(new, HostState::NoHost | HostState::Loading(_)) {
+            host.players.write().await.clear();
+        }
         host.set_state(new).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::StatsDb;
+
+    fn status(online: u32, latency_ms: u64) -> HostStatus {
+        HostStatus {
+            motd: "A Minecraft Server".to_owned(),
+            version: "1.18.1".to_owned(),
+            online,
+            max_players: 20,
+            sample: Vec::new(),
+            latency: Duration::from_millis(latency_ms),
+        }
+    }
+
+    #[tokio::test]
+    async fn broadcast_changes_only() {
+        let host = Host::new(HostTimings::default());
+        let events = Arc::new(broadcast::channel(16).0);
+        let mut subscribed = events.subscribe();
+        let stats = Stats::from(StatsDb::from(crate::db::test_db()));
+        let broadcast = BroadCast::new(events, stats);
+
+        record(status(0, 20), &host, &broadcast).await;
+        record(status(0, 25), &host, &broadcast).await;
+        record(status(1, 25), &host, &broadcast).await;
+
+        assert!(matches!(subscribed.try_recv(), Ok(Event::HostStatus(s)) if s.online == 0));
+        assert!(matches!(subscribed.try_recv(), Ok(Event::HostStatus(s)) if s.online == 1));
+        assert!(subscribed.try_recv().is_err());
+        assert_eq!(host.get_status().await, Some(status(1, 25)));
+    }
+}
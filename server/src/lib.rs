@@ -1,4 +1,4 @@
-use protocol::{Addr, Event};
+use protocol::Event;
 use shared::tarpc::server::BaseChannel;
 use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr};
@@ -14,15 +14,20 @@ use tokio::sync::Mutex;
 use tokio::time::timeout;
 use tracing::{debug, info, warn};
 
-use protocol::{Service, UserId};
+use protocol::{HostId, Service, UserId};
 use shared::tarpc;
 use tarpc::server::Channel;
 use tarpc::tokio_serde::formats::Bincode;
 use uuid::Uuid;
 
+mod addr;
 pub mod admin_ui;
+mod chat;
+mod console;
 pub mod db;
 pub mod host;
+pub mod relay;
+mod stats;
 #[cfg(feature = "util")]
 pub mod util;
 mod world;
@@ -35,6 +40,8 @@ type SessionId = Uuid;
 pub struct Session {
     user_id: UserId,
     backlog: Arc<Mutex<broadcast::Receiver<Event>>>,
+    /// the id this session last asked to host with
+    host_id: Option<HostId>,
 }
 
 #[derive(Clone, Default)]
@@ -49,6 +56,7 @@ impl Sessions {
         let session = Session {
             user_id,
             backlog: Arc::new(Mutex::new(backlog)),
+            host_id: None,
         };
         sessions.insert(uuid, session);
         uuid
@@ -56,6 +64,14 @@ impl Sessions {
     pub fn get_user_id(&self, id: SessionId) -> Option<UserId> {
         self.by_id.read().unwrap().get(&id).map(|s| s.user_id)
     }
+    pub fn host_id(&self, id: SessionId) -> Option<HostId> {
+        self.by_id.read().unwrap().get(&id).and_then(|s| s.host_id)
+    }
+    fn set_host_id(&self, id: SessionId, host_id: HostId) {
+        if let Some(session) = self.by_id.write().unwrap().get_mut(&id) {
+            session.host_id = Some(host_id);
+        }
+    }
     pub fn clear_user(&self, id: UserId) {
         self.by_id.write().unwrap().retain(|_, v| v.user_id != id)
     }
@@ -134,43 +150,34 @@ pub async fn extract_peer_addr(conn: &mut TcpStream) -> Result<IpAddr, Error> {
     Ok(addr)
 }
 
-/// Temp impl until ip.is_global() is stabalized, this is a bad
-/// solution it will probably break on a lot of networks but it works
-/// on mine.
-fn is_private(ip: IpAddr) -> bool {
-    match ip {
-        IpAddr::V4(addr) => {
-            let oct = addr.octets();
-            match (oct[0], oct[1]) {
-                (10, _) => true,
-                (192, 168) => true,
-                (172, _n) => unimplemented!(),
-                _ => false,
-            }
-        }
-        _ => unimplemented!(),
-    }
-}
-
 pub async fn host(
     sessions: Sessions,
     userdb: UserDb,
     world: World,
     port: u16,
     domain: String,
+    relay: Option<relay::Ports>,
     events: Arc<broadcast::Sender<Event>>,
     host_req: mpsc::Sender<host::HostEvent>,
 ) {
     let server_addr = (IpAddr::V4(Ipv4Addr::LOCALHOST), port);
     info!("starting listener on port {}", port);
 
+    if let Some(ports) = relay {
+        let relay = relay::run(ports, sessions.clone(), world.clone(), events.clone());
+        tokio::spawn(relay);
+    }
+
     let base_state = ConnState {
-        peer_addr: None,
+        peer_ip: None,
+        domain,
+        relay,
         events,
         sessions,
         userdb,
         world,
         host_req,
+        console: console::Console::default(),
     };
 
     use tokio_util::codec::length_delimited::LengthDelimitedCodec;
@@ -179,13 +186,10 @@ pub async fn host(
     loop {
         let (mut conn, _) = listener.accept().await.unwrap();
         let mut conn_state = base_state.clone();
-        let domain_clone = domain.clone();
 
         tokio::spawn(async move {
-            conn_state.peer_addr = match extract_peer_addr(&mut conn).await {
-                // Ok(addr) if !addr.is_private() => domain,
-                Ok(addr) if is_private(addr) => Some(Addr::Domain(domain_clone)),
-                Ok(ip) => Some(Addr::Ip(ip)),
+            conn_state.peer_ip = match extract_peer_addr(&mut conn).await {
+                Ok(ip) => Some(ip),
                 Err(e) => {
                     debug!("could not extract peer address: {}, dropping connection", e);
                     return;
@@ -1,7 +1,11 @@
+use std::num::NonZeroU64;
 use std::path::Path;
+use std::time::Duration;
+
+use protocol::HostTimings;
 
-use server::Sessions;
 use server::{db::user::UserDb, World};
+use server::{relay, Sessions};
 use shared::LogLevel;
 use structopt::StructOpt;
 use tokio::runtime::Builder;
@@ -21,11 +25,51 @@ struct Opt {
     /// current host connects from the local network
     #[structopt(long)]
     domain: String,
+    /// seconds a host may go without making loading progress
+    #[structopt(long, default_value = "300")]
+    loading_timeout: u64,
+    /// seconds a host may be unreachable before it is dropped
+    #[structopt(long, default_value = "300")]
+    unreachable_timeout: u64,
+    /// seconds a host has to upload its final save after stopping
+    #[structopt(long, default_value = "300")]
+    shutdown_timeout: u64,
+    /// seconds a host has to hand over after someone asked to take over
+    #[structopt(long, default_value = "300")]
+    hand_over_timeout: u64,
+    /// seconds between checks whether the host is reachable, may not be 0
+    #[structopt(long, default_value = "5")]
+    ping_interval: NonZeroU64,
+    /// port players connect to when the host uses the relay, the relay
+    /// is only offered if this and the tunnel port are set
+    #[structopt(long)]
+    relay_port: Option<u16>,
+    /// port hosts open their relay tunnel to
+    #[structopt(long)]
+    tunnel_port: Option<u16>,
     /// Verbosity of the logging, options: TRACE, DEBUG, INFO, WARN or ERROR
     #[structopt(name = "log", default_value = "INFO")]
     log_level: LogLevel,
 }
 
+impl Opt {
+    fn host_timings(&self) -> HostTimings {
+        HostTimings {
+            loading: Duration::from_secs(self.loading_timeout),
+            unreachable: Duration::from_secs(self.unreachable_timeout),
+            shutdown: Duration::from_secs(self.shutdown_timeout),
+            hand_over: Duration::from_secs(self.hand_over_timeout),
+            ping_interval: Duration::from_secs(self.ping_interval.get()),
+        }
+    }
+    fn relay(&self) -> Option<relay::Ports> {
+        match (self.relay_port, self.tunnel_port) {
+            (Some(public), Some(tunnel)) => Some(relay::Ports { public, tunnel }),
+            _ => None,
+        }
+    }
+}
+
 fn main() {
     let opt = Opt::from_args();
     let _log_guard =
@@ -49,17 +93,18 @@ fn main() {
         let sessions = Sessions::default();
         let user_db = UserDb::from(db.clone());
         let events = server::events_channel();
-        let host_state = server::host::Host::new();
+        let host_state = server::host::Host::new(opt.host_timings());
         let world = World::from(db, host_state.clone()).await;
 
         let (host_req, host_req_recv) = mpsc::channel(100);
-        let events_clone = events.clone();
+        let broadcast = server::host::BroadCast::new(events.clone(), world.stats.clone());
         tokio::spawn(async move {
-            server::host::monitor(host_state, events_clone, host_req_recv).await;
+            server::host::monitor(host_state, broadcast, host_req_recv).await;
         });
 
+        let relay = opt.relay();
         server::host(
-            sessions, user_db, world, opt.port, opt.domain, events, host_req,
+            sessions, user_db, world, opt.port, opt.domain, relay, events, host_req,
         )
         .await;
     });
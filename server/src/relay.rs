@@ -0,0 +1,164 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::StreamExt;
+use protocol::relay::{self, Frame, Tunnel};
+use protocol::{Event, HostId};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
+
+use crate::{extract_peer_addr, Sessions, World};
+
+/// ports the relay listens on
+#[derive(Debug, Clone, Copy)]
+pub struct Ports {
+    /// players connect here
+    pub public: u16,
+    /// hosts open their tunnel here
+    pub tunnel: u16,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum Error {
+    #[error("tunnel did not start with a hello")]
+    NoHello,
+    #[error("session expired or did not exist")]
+    SessionExpired,
+    #[error("session is not currently hosting")]
+    NotHost,
+}
+
+/// where to send players, only set while a host has a tunnel open
+type Players = Arc<Mutex<Option<mpsc::Sender<TcpStream>>>>;
+
+async fn authenticate(
+    tunnel: &mut Tunnel<TcpStream>,
+    sessions: &Sessions,
+    world: &World,
+) -> Result<HostId, Error> {
+    let hello = timeout(Duration::from_secs(5), tunnel.next()).await;
+    let (session, host_id) = match hello {
+        Ok(Some(Ok(Frame::Hello { session, host_id }))) => (session, host_id),
+        _ => return Err(Error::NoHello),
+    };
+    sessions.get_user_id(session).ok_or(Error::SessionExpired)?;
+    world.is_host(host_id).await.map_err(|_| Error::NotHost)?;
+    Ok(host_id)
+}
+
+/// returns once the host we relay for stops hosting
+async fn host_gone(mut events: broadcast::Receiver<Event>) {
+    loop {
+        match events.recv().await {
+            Ok(Event::NewHost(_))
+            | Ok(Event::HostShutdown)
+            | Ok(Event::HostDropped)
+            | Ok(Event::HostCanceld)
+            | Err(RecvError::Closed) => return,
+            _ => continue,
+        }
+    }
+}
+
+async fn handle_tunnel(
+    mut conn: TcpStream,
+    players: Players,
+    sessions: Sessions,
+    world: World,
+    events: broadcast::Receiver<Event>,
+) {
+    if let Err(e) = extract_peer_addr(&mut conn).await {
+        debug!("could not extract peer address: {}, dropping tunnel", e);
+        return;
+    }
+
+    let mut tunnel = relay::framed(conn);
+    let host_id = match authenticate(&mut tunnel, &sessions, &world).await {
+        Ok(host_id) => host_id,
+        Err(e) => {
+            warn!("refused tunnel: {}", e);
+            return;
+        }
+    };
+
+    info!("relaying players to host: {}", host_id);
+    let (tx, rx) = mpsc::channel(8);
+    *players.lock().unwrap() = Some(tx.clone());
+    tokio::select! {
+        res = relay::forward(tunnel, rx, None) => match res {
+            Ok(_) => info!("host closed tunnel, host: {}", host_id),
+            Err(e) => warn!("tunnel failed: {}, host: {}", e, host_id),
+        },
+        _ = host_gone(events) => info!("closing tunnel, no longer hosting: {}", host_id),
+    }
+
+    // stop sending players here, unless a new tunnel replaced this one
+    let mut players = players.lock().unwrap();
+    if matches!(&*players, Some(current) if current.same_channel(&tx)) {
+        *players = None;
+    }
+}
+
+/// Accepts tunnels from the current host and relays players connecting
+/// to the public port through them.
+pub async fn run(
+    ports: Ports,
+    sessions: Sessions,
+    world: World,
+    events: Arc<broadcast::Sender<Event>>,
+) {
+    let players: Players = Arc::default();
+    // players connect directly, tunnels like rpc connections come in
+    // through the proxy
+    let public = TcpListener::bind((Ipv4Addr::UNSPECIFIED, ports.public))
+        .await
+        .unwrap();
+    let tunnels = TcpListener::bind((IpAddr::V4(Ipv4Addr::LOCALHOST), ports.tunnel))
+        .await
+        .unwrap();
+    info!(
+        "starting relay on port {}, tunnels on port {}",
+        ports.public, ports.tunnel
+    );
+
+    let relay_players = players.clone();
+    tokio::spawn(async move {
+        loop {
+            let (conn, addr) = match public.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("could not accept player: {}", e);
+                    continue;
+                }
+            };
+            let tunnel = relay_players.lock().unwrap().clone();
+            match tunnel {
+                Some(tunnel) if tunnel.send(conn).await.is_ok() => {
+                    debug!("relaying player: {}", addr)
+                }
+                _ => debug!("no tunnel open, dropping player: {}", addr),
+            }
+        }
+    });
+
+    loop {
+        let (conn, _) = match tunnels.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("could not accept tunnel: {}", e);
+                continue;
+            }
+        };
+        // subscribe before checking who hosts, so we can not miss the
+        // host changing
+        let events = events.subscribe();
+        let players = players.clone();
+        let sessions = sessions.clone();
+        let world = world.clone();
+        tokio::spawn(handle_tunnel(conn, players, sessions, world, events));
+    }
+}
@@ -1,13 +1,23 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
+use crate::addr;
+use crate::chat;
 use crate::db::world::WorldDb;
-use crate::host::HostEvent;
-use sync::{DirContent, DirUpdate, ObjectId,UpdateList};
-use wrapper::parser::Line;
+use crate::host::{BroadCast, HostEvent, Presence};
+use sync::{DirContent, DirUpdate, ObjectId, UpdateList};
+use wrapper::parser::{Line, Message};
+use wrapper::CrashReport;
 
 use super::ConnState;
-use protocol::{Error, Event, AWAIT_EVENT_TIMEOUT};
-use protocol::{HostDetails, HostId, HostState, Service, SessionId, User, UserId};
+use protocol::{
+    ChatMessage, CommandId, CrashRecord, HostId, HostState, HostStatus, HostTimings, HostingRecord,
+    PlayerStats, PortCheck, ServerInstall, ServerSettings, Service, SessionId, TimingOverrides,
+    User, UserId, Uuid, WorldVersion,
+};
+use protocol::{Error, Event, AWAIT_EVENT_TIMEOUT, MAX_CRASH_REPORT_LEN};
 use shared::tarpc;
 use tarpc::context;
 use tokio::sync::broadcast::error::RecvError;
@@ -37,15 +47,12 @@ impl Service for ConnState {
             Err(DbError::IncorrectPass) => {
                 warn!(
                     "Incorrect password for user: '{}' from {:?}",
-                    username, self.peer_addr
+                    username, self.peer_ip
                 );
                 Err(Error::IncorrectLogin)
             }
             Err(DbError::IncorrectName) => {
-                warn!(
-                    "Incorrect username ({}) from {:?}",
-                    username, self.peer_addr
-                );
+                warn!("Incorrect username ({}) from {:?}", username, self.peer_ip);
                 Err(Error::IncorrectLogin)
             }
             Err(e) => Err(e.into()),
@@ -89,9 +96,107 @@ impl Service for ConnState {
         Ok(())
     }
 
-    async fn host(self, _: context::Context, id: SessionId) -> Result<HostState, Error> {
+    async fn host(
+        self,
+        _: context::Context,
+        id: SessionId,
+    ) -> Result<(HostState, HostTimings), Error> {
+        let _ = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        let mut state = self.world.host.get_state().await;
+        if let Some(details) = state.details_mut() {
+            addr::order_for(details, self.peer_ip());
+        }
+        let timings = self.world.host.get_timings().await;
+        Ok((state, timings))
+    }
+
+    async fn host_status(
+        self,
+        _: context::Context,
+        id: SessionId,
+    ) -> Result<Option<HostStatus>, Error> {
+        let _ = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        Ok(self.world.host.get_status().await)
+    }
+
+    async fn players(self, _: context::Context, id: SessionId) -> Result<Vec<String>, Error> {
         let _ = self.get_user_id(id).ok_or(Error::SessionExpired)?;
-        Ok(self.world.host.state.read().await.clone())
+        Ok(self.world.host.get_players().await)
+    }
+
+    async fn player_stats(
+        self,
+        _: context::Context,
+        id: SessionId,
+        player: String,
+    ) -> Result<Option<PlayerStats>, Error> {
+        let _ = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        let stats = &self.world.stats.db;
+        stats.player_stats(&player).map_err(|_| Error::Internal)
+    }
+
+    async fn leaderboard(
+        self,
+        _: context::Context,
+        id: SessionId,
+        limit: u16,
+    ) -> Result<Vec<PlayerStats>, Error> {
+        let _ = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        let stats = &self.world.stats.db;
+        stats.leaderboard(limit.into()).map_err(|_| Error::Internal)
+    }
+
+    async fn hosting_history(
+        self,
+        _: context::Context,
+        id: SessionId,
+        limit: u16,
+    ) -> Result<Vec<HostingRecord>, Error> {
+        let _ = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        let stats = &self.world.stats.db;
+        let history = stats.hosting_history(limit.into());
+        history.map_err(|_| Error::Internal)
+    }
+
+    #[instrument(err, skip(self))]
+    async fn send_chat(
+        self,
+        _: context::Context,
+        id: SessionId,
+        text: String,
+    ) -> Result<(), Error> {
+        let user_id = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        let name = self.userdb.get_name(user_id)?.ok_or(Error::UserNotInDb)?;
+        let msg = ChatMessage::from_client(name, &text)?;
+        self.world.chat.push(msg, &self.events);
+        Ok(())
+    }
+
+    async fn chat_history(
+        self,
+        _: context::Context,
+        id: SessionId,
+    ) -> Result<Vec<ChatMessage>, Error> {
+        let _ = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        Ok(self.world.chat.history())
+    }
+
+    async fn server_settings(
+        self,
+        _: context::Context,
+        id: SessionId,
+    ) -> Result<ServerSettings, Error> {
+        let _ = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        self.world.settings()
+    }
+
+    async fn server_install(
+        self,
+        _: context::Context,
+        id: SessionId,
+    ) -> Result<Option<ServerInstall>, Error> {
+        let _ = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        Ok(self.world.server_install())
     }
 
     #[instrument(err, skip(self))]
@@ -100,21 +205,103 @@ impl Service for ConnState {
         _: context::Context,
         id: SessionId,
         host_id: HostId,
+        port: u16,
+        lan_ip: Option<IpAddr>,
+        relay: bool,
+        mapped: Option<SocketAddr>,
     ) -> Result<(), Error> {
         let user_id = self.get_user_id(id).ok_or(Error::SessionExpired)?;
-        let name = self.userdb.get_name(user_id)?.unwrap();
-        let details = HostDetails {
-            name,
-            addr: self.peer_addr(),
-            port: 25565,
-            id: host_id,
-        };
+        let details = self.host_details(user_id, host_id, port, lan_ip, relay, mapped)?;
+        self.world.check_install()?;
+        self.sessions.set_host_id(id, host_id);
         self.host_req
             .send(HostEvent::RequestToHost(details))
             .await
             .unwrap();
         Ok(())
     }
+
+    #[instrument(err, skip(self))]
+    async fn request_takeover(
+        self,
+        _: context::Context,
+        id: SessionId,
+        host_id: HostId,
+        port: u16,
+        lan_ip: Option<IpAddr>,
+        relay: bool,
+        mapped: Option<SocketAddr>,
+    ) -> Result<(), Error> {
+        let user_id = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        let details = self.host_details(user_id, host_id, port, lan_ip, relay, mapped)?;
+        match &*self.world.host.state.read().await {
+            HostState::Up(host) if host.name == details.name => return Err(Error::AlreadyHost),
+            HostState::Up(_) => (),
+            _ => return Err(Error::HostNotUp),
+        }
+        if self.world.host.get_takeover().await.is_some() {
+            return Err(Error::TakeoverPending);
+        }
+        self.world.check_install()?;
+        self.sessions.set_host_id(id, host_id);
+        self.host_req
+            .send(HostEvent::RequestTakeover(details))
+            .await
+            .unwrap();
+        Ok(())
+    }
+
+    async fn tunnel_port(self, _: context::Context) -> Option<u16> {
+        self.relay.map(|ports| ports.tunnel)
+    }
+
+    #[instrument(err, skip(self))]
+    async fn check_port(
+        self,
+        _: context::Context,
+        id: SessionId,
+        port: u16,
+        token: Uuid,
+    ) -> Result<PortCheck, Error> {
+        let _ = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        let check = addr::check_port(self.peer_ip(), port, token).await;
+        info!("checked port {} of {}: {:?}", port, self.peer_ip(), check);
+        Ok(check)
+    }
+
+    #[instrument(err, skip(self))]
+    async fn hand_over(
+        self,
+        _: context::Context,
+        id: SessionId,
+        host_id: HostId,
+    ) -> Result<(), Error> {
+        let user_id = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        let _ = self.is_host(host_id).await?;
+        if self.world.host.get_takeover().await.is_none() {
+            return Err(Error::NoTakeover);
+        }
+        self.host_req.send(HostEvent::HandOver).await.unwrap();
+        info!("user: {}, handed over hosting", user_id);
+        Ok(())
+    }
+    #[instrument(err, skip(self))]
+    async fn release_host(
+        self,
+        _: context::Context,
+        id: SessionId,
+        host_id: HostId,
+        saved: bool,
+    ) -> Result<(), Error> {
+        let user_id = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        let _ = self.is_host(host_id).await?;
+        self.host_req
+            .send(HostEvent::ShutDown { saved })
+            .await
+            .unwrap();
+        info!("user: {}, released hosting", user_id);
+        Ok(())
+    }
     async fn await_event(self, _: context::Context, id: SessionId) -> Result<Event, Error> {
         let backlog = {
             let sessions = self.sessions.by_id.read().unwrap();
@@ -124,14 +311,23 @@ impl Service for ConnState {
 
         let mut backlog = backlog.try_lock_owned().map_err(|_| Error::BackLogLocked)?;
 
-        let timeout_res = tokio::time::timeout(AWAIT_EVENT_TIMEOUT, backlog.recv()).await;
-        match timeout_res {
-            Err(_elapsed) => Ok(Event::AwaitTimeout),
-            Ok(res) => match res {
-                Err(RecvError::Closed) => panic!("events queue got closed"),
-                Err(RecvError::Lagged(_)) => Err(Error::Lagging),
-                Ok(event) => Ok(event),
-            },
+        let deadline = tokio::time::Instant::now() + AWAIT_EVENT_TIMEOUT;
+        loop {
+            let timeout_res = tokio::time::timeout_at(deadline, backlog.recv()).await;
+            return match timeout_res {
+                Err(_elapsed) => Ok(Event::AwaitTimeout),
+                Ok(res) => match res {
+                    Err(RecvError::Closed) => panic!("events queue got closed"),
+                    Err(RecvError::Lagged(_)) => Err(Error::Lagging),
+                    Ok(Event::NewHost(mut details)) => {
+                        addr::order_for(&mut details, self.peer_ip());
+                        Ok(Event::NewHost(details))
+                    }
+                    // admin commands are only for the host
+                    Ok(Event::ConsoleCommand { .. }) if !self.session_hosts(id).await => continue,
+                    Ok(event) => Ok(event),
+                },
+            };
         }
     }
     async fn dir_update(
@@ -166,7 +362,7 @@ impl Service for ConnState {
     ) -> Result<(), Error> {
         let id = self.get_user_id(id).ok_or(Error::SessionExpired)?;
         let _ = self.is_host(host_id).await?;
-        self.world.flush_save()?;
+        self.world.flush_save(host_id).await?;
         info!("user: {}, finished saving", id);
 
         Ok(())
@@ -205,20 +401,80 @@ impl Service for ConnState {
         line: Line,
     ) -> Result<(), Error> {
         let _ = self.is_host(host_id).await?;
-        match HostEvent::try_from(line) {
-            Ok(event) => self.host_req.send(event).await.unwrap(),
-            Err(_) => (), // unprocessed line
+        if let Message::Version(version) = &line.msg {
+            self.world.check_version(host_id, version)?;
+        }
+        let line = match HostEvent::try_from(line) {
+            Ok(event) => {
+                self.host_req.send(event).await.unwrap();
+                return Ok(());
+            }
+            Err(line) => line,
+        };
+        let line = match Presence::try_from(line) {
+            Ok(presence) => {
+                let broadcast = BroadCast::new(self.events.clone(), self.world.stats.clone());
+                self.world.host.update_players(presence, &broadcast).await;
+                return Ok(());
+            }
+            Err(line) => line,
+        };
+        // other lines are not processed
+        if let Some(msg) = chat::from_line(line) {
+            self.world.chat.push(msg, &self.events);
         }
         Ok(())
     }
 
+    #[instrument(err, skip(self, lines))]
+    async fn console_output(
+        self,
+        _: context::Context,
+        id: SessionId,
+        host_id: HostId,
+        command: CommandId,
+        lines: Vec<String>,
+    ) -> Result<(), Error> {
+        let _ = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        let _ = self.is_host(host_id).await?;
+        self.console.output(command, lines);
+        Ok(())
+    }
+
+    #[instrument(err, skip(self, report))]
+    async fn crash_report(
+        self,
+        _: context::Context,
+        id: SessionId,
+        host_id: HostId,
+        report: CrashReport,
+    ) -> Result<(), Error> {
+        let user_id = self.get_user_id(id).ok_or(Error::SessionExpired)?;
+        let _ = self.is_host(host_id).await?;
+        if report.text.len() > MAX_CRASH_REPORT_LEN {
+            return Err(Error::CrashReportTooLarge);
+        }
+        let host = self.userdb.get_name(user_id)?.ok_or(Error::UserNotInDb)?;
+        warn!(
+            "minecraft server of host: {} crashed, report: {}",
+            host, report.file_name
+        );
+        let record = CrashRecord {
+            host,
+            at: SystemTime::now(),
+            report,
+        };
+        let stats = &self.world.stats.db;
+        stats.add_crash(&record).map_err(|_| Error::Internal)
+    }
+
     async fn add_user(
         mut self,
         _: context::Context,
         user: User,
         password: String,
     ) -> Result<(), Error> {
-        if !self.peer_addr().is_loopback() {
+        if !self.peer_ip().is_loopback() {
             return Err(Error::Unauthorized);
         }
         self.userdb.add_user(user.clone(), password).await?;
@@ -227,7 +483,7 @@ impl Service for ConnState {
     }
 
     async fn list_users(self, _: context::Context) -> Result<Vec<(UserId, User)>, Error> {
-        if !self.peer_addr().is_loopback() {
+        if !self.peer_ip().is_loopback() {
             return Err(Error::Unauthorized);
         }
         Ok(self.userdb.get_userlist()?)
@@ -240,7 +496,7 @@ impl Service for ConnState {
         old: User,
         new: User,
     ) -> Result<(), Error> {
-        if !self.peer_addr().is_loopback() {
+        if !self.peer_ip().is_loopback() {
             return Err(Error::Unauthorized);
         }
         self.userdb.update_user(id, old.clone(), new).await?;
@@ -254,7 +510,7 @@ impl Service for ConnState {
         user_id: UserId,
         new_password: String,
     ) -> Result<(), Error> {
-        if !self.peer_addr().is_loopback() {
+        if !self.peer_ip().is_loopback() {
             return Err(Error::Unauthorized);
         }
 
@@ -267,7 +523,7 @@ impl Service for ConnState {
     }
 
     async fn remove_account(mut self, _: context::Context, id: UserId) -> Result<(), Error> {
-        if !self.peer_addr().is_loopback() {
+        if !self.peer_ip().is_loopback() {
             return Err(Error::Unauthorized);
         }
         let name = self.userdb.remove_user(id).await?;
@@ -276,7 +532,7 @@ impl Service for ConnState {
     }
 
     async fn dump_save(self, _: context::Context, dir: PathBuf) -> Result<(), Error> {
-        if !self.peer_addr().is_loopback() {
+        if !self.peer_ip().is_loopback() {
             return Err(Error::Unauthorized);
         }
 
@@ -290,7 +546,7 @@ impl Service for ConnState {
     }
 
     async fn set_save(self, _: context::Context, dir: PathBuf) -> Result<(), Error> {
-        if !self.peer_addr().is_loopback() {
+        if !self.peer_ip().is_loopback() {
             return Err(Error::Unauthorized);
         }
 
@@ -302,4 +558,129 @@ impl Service for ConnState {
         info!("set save to whatever was in: {:?}", dir);
         Ok(())
     }
+
+    async fn timing_overrides(self, _: context::Context) -> Result<TimingOverrides, Error> {
+        if !self.peer_ip().is_loopback() {
+            return Err(Error::Unauthorized);
+        }
+        Ok(self.world.timing_overrides())
+    }
+
+    async fn set_timing_overrides(
+        self,
+        _: context::Context,
+        overrides: TimingOverrides,
+    ) -> Result<(), Error> {
+        if !self.peer_ip().is_loopback() {
+            return Err(Error::Unauthorized);
+        }
+        if overrides.ping_interval == Some(Duration::ZERO) {
+            return Err(Error::ZeroPingInterval);
+        }
+        self.world.set_timing_overrides(overrides).await;
+        info!("set host timing overrides to: {:?}", overrides);
+        Ok(())
+    }
+
+    #[instrument(err, skip(self))]
+    async fn console_command(
+        self,
+        _: context::Context,
+        command: String,
+    ) -> Result<Vec<String>, Error> {
+        if !self.peer_ip().is_loopback() {
+            return Err(Error::Unauthorized);
+        }
+        match *self.world.host.state.read().await {
+            HostState::Up(_) => (),
+            _ => return Err(Error::HostNotUp),
+        }
+        info!("running console command on host: {}", command);
+        self.console.run(command, &self.events).await
+    }
+
+    async fn crash_reports(
+        self,
+        _: context::Context,
+        limit: u16,
+    ) -> Result<Vec<CrashRecord>, Error> {
+        if !self.peer_ip().is_loopback() {
+            return Err(Error::Unauthorized);
+        }
+        let stats = &self.world.stats.db;
+        let crashes = stats.crashes(limit.into());
+        crashes.map_err(|_| Error::Internal)
+    }
+
+    async fn current_settings(self, _: context::Context) -> Result<ServerSettings, Error> {
+        if !self.peer_ip().is_loopback() {
+            return Err(Error::Unauthorized);
+        }
+        self.world.settings()
+    }
+
+    #[instrument(err, skip(self, files))]
+    async fn set_settings(
+        self,
+        _: context::Context,
+        files: BTreeMap<String, String>,
+    ) -> Result<u64, Error> {
+        if !self.peer_ip().is_loopback() {
+            return Err(Error::Unauthorized);
+        }
+        let version = self.world.set_settings(files)?;
+        info!("server settings changed, now at version: {}", version);
+        Ok(version)
+    }
+
+    async fn install_version(self, _: context::Context) -> Result<Option<String>, Error> {
+        if !self.peer_ip().is_loopback() {
+            return Err(Error::Unauthorized);
+        }
+        Ok(self.world.server_install().map(|install| install.version))
+    }
+
+    #[instrument(err, skip(self))]
+    async fn set_install(
+        self,
+        _: context::Context,
+        version: String,
+        dir: PathBuf,
+    ) -> Result<(), Error> {
+        if !self.peer_ip().is_loopback() {
+            return Err(Error::Unauthorized);
+        }
+
+        if !dir.exists() {
+            return Err(Error::DirDoesNotExist);
+        }
+
+        self.world
+            .set_server_install(version.clone(), dir.clone())
+            .await?;
+        info!(
+            "set server install to version: {}, from: {:?}",
+            version, dir
+        );
+        Ok(())
+    }
+
+    async fn world_version(
+        self,
+        _: context::Context,
+    ) -> Result<(Option<WorldVersion>, Option<String>), Error> {
+        if !self.peer_ip().is_loopback() {
+            return Err(Error::Unauthorized);
+        }
+        Ok((self.world.world_version(), self.world.approved_version()))
+    }
+
+    async fn approve_version(self, _: context::Context, game: String) -> Result<(), Error> {
+        if !self.peer_ip().is_loopback() {
+            return Err(Error::Unauthorized);
+        }
+        self.world.approve_version(&game);
+        info!("approved hosting with minecraft version: {}", game);
+        Ok(())
+    }
 }
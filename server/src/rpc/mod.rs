@@ -1,7 +1,11 @@
+use crate::addr;
+use crate::console::Console;
 use crate::db::user::UserDb;
 use crate::host::HostEvent;
+use crate::relay;
 use crate::{Sessions, World};
-use protocol::{Addr, Error, Event, HostId, SessionId, UserId};
+use protocol::{Error, Event, HostDetails, HostId, SessionId, UserId};
+use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::{broadcast, mpsc};
@@ -10,17 +14,22 @@ mod calls;
 
 #[derive(Clone)]
 pub struct ConnState {
-    pub peer_addr: Option<Addr>,
+    pub peer_ip: Option<IpAddr>,
+    /// domain that points to our network, used to reach hosts on it
+    pub domain: String,
+    pub relay: Option<relay::Ports>,
     pub sessions: Sessions,
     pub events: Arc<broadcast::Sender<Event>>,
     pub userdb: UserDb,
     pub world: World,
     pub host_req: mpsc::Sender<HostEvent>,
+    /// admin commands waiting on output from the host
+    pub console: Console,
 }
 
 impl ConnState {
-    pub fn peer_addr(&self) -> Addr {
-        self.peer_addr.clone().unwrap()
+    pub fn peer_ip(&self) -> IpAddr {
+        self.peer_ip.unwrap()
     }
     pub fn get_user_id(&self, id: SessionId) -> Option<UserId> {
         self.sessions.get_user_id(id)
@@ -32,9 +41,40 @@ impl ConnState {
         let backlog = self.events.subscribe();
         self.sessions.add(id, backlog)
     }
+    pub fn host_details(
+        &self,
+        user_id: UserId,
+        host_id: HostId,
+        port: u16,
+        lan_ip: Option<IpAddr>,
+        relay: bool,
+        mapped: Option<SocketAddr>,
+    ) -> Result<HostDetails, Error> {
+        let name = self.userdb.get_name(user_id)?.ok_or(Error::UserNotInDb)?;
+        let relay = match (relay, self.relay) {
+            (false, _) => None,
+            (true, Some(ports)) => Some(ports.public),
+            (true, None) => return Err(Error::NoRelay),
+        };
+        let peer = self.peer_ip();
+        Ok(HostDetails {
+            name,
+            addrs: addr::candidates(peer, lan_ip, mapped, &self.domain, relay),
+            peer,
+            port,
+            id: host_id,
+        })
+    }
     pub async fn is_host(&self, id: HostId) -> Result<(), Error> {
         self.world.is_host(id).await.map_err(|_| Error::NotHost)
     }
+    /// if the session asked to host and is the current host
+    pub async fn session_hosts(&self, id: SessionId) -> bool {
+        match self.sessions.host_id(id) {
+            Some(host_id) => self.world.is_host(host_id).await.is_ok(),
+            None => false,
+        }
+    }
     pub fn path_safe(path: impl AsRef<Path> + Into<PathBuf>) -> Result<(), Error> {
         use crate::db::world::McPaths;
         use sync::PathCheck;
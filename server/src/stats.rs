@@ -0,0 +1,270 @@
+//! Records who played on which host and how long each host lasted. The
+//! host monitor and player updates hand us each event before broadcasting
+//! it, the broadcast may drop events. Saves have no event and are
+//! reported separately.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use protocol::{Event, HostDetails, HostId, HostingEnd, HostingRecord};
+use tracing::{debug, warn};
+
+use crate::db::stats::PlaySession;
+pub use crate::db::stats::StatsDb;
+
+/// a player on the current host
+#[derive(Debug)]
+struct Playing {
+    start: SystemTime,
+    unreachable: Duration,
+}
+
+/// the current host and its players
+#[derive(Debug)]
+struct Hosting {
+    host: String,
+    host_id: HostId,
+    start: SystemTime,
+    saves: u32,
+    unreachable: Duration,
+    unreachable_since: Option<SystemTime>,
+    players: HashMap<String, Playing>,
+}
+
+fn since(earlier: SystemTime, now: SystemTime) -> Duration {
+    now.duration_since(earlier).unwrap_or_default()
+}
+
+impl Hosting {
+    fn new(host: &HostDetails, now: SystemTime) -> Self {
+        Self {
+            host: host.name.clone(),
+            host_id: host.id,
+            start: now,
+            saves: 0,
+            unreachable: Duration::ZERO,
+            unreachable_since: None,
+            players: HashMap::new(),
+        }
+    }
+
+    /// books the time the host has been unreachable up to now, on the host
+    /// and on everyone playing
+    fn unreachable_until(&mut self, now: SystemTime) {
+        let from = match self.unreachable_since {
+            Some(from) => from,
+            None => return,
+        };
+        self.unreachable += since(from, now);
+        for playing in self.players.values_mut() {
+            playing.unreachable += since(from.max(playing.start), now);
+        }
+        self.unreachable_since = Some(now);
+    }
+
+    fn session(&self, player: String, playing: Playing, now: SystemTime) -> PlaySession {
+        PlaySession {
+            player,
+            host: self.host.clone(),
+            host_id: self.host_id,
+            start: playing.start,
+            end: now,
+            unreachable: playing.unreachable,
+        }
+    }
+
+    fn record(self, end: HostingEnd, now: SystemTime) -> HostingRecord {
+        HostingRecord {
+            host: self.host,
+            start: self.start,
+            duration: since(self.start, now),
+            unreachable: self.unreachable,
+            saves: self.saves,
+            end,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Stats {
+    pub db: StatsDb,
+    current: Arc<Mutex<Option<Hosting>>>,
+}
+
+impl std::fmt::Debug for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stats").finish()
+    }
+}
+
+impl Stats {
+    pub fn from(db: StatsDb) -> Self {
+        Self {
+            db,
+            current: Arc::default(),
+        }
+    }
+
+    /// the host uploaded a save
+    pub fn saved(&self) {
+        if let Some(hosting) = self.current.lock().unwrap().as_mut() {
+            hosting.saves += 1;
+        }
+    }
+
+    fn store_session(&self, session: PlaySession) {
+        debug!("recording play session: {:?}", session);
+        if let Err(e) = self.db.add_session(&session) {
+            warn!("could not store play session: {}", e);
+        }
+    }
+
+    /// ends the current hosting period and everyones play session
+    fn end_hosting(&self, hosting: Option<Hosting>, end: HostingEnd, now: SystemTime) {
+        let mut hosting = match hosting {
+            Some(hosting) => hosting,
+            None => return,
+        };
+        hosting.unreachable_until(now);
+        let players: Vec<_> = hosting.players.drain().collect();
+        for (player, playing) in players {
+            self.store_session(hosting.session(player, playing, now));
+        }
+        if let Err(e) = self.db.add_hosting(&hosting.record(end, now)) {
+            warn!("could not store hosting record: {}", e);
+        }
+    }
+
+    pub fn handle(&self, event: &Event, now: SystemTime) {
+        let mut current = self.current.lock().unwrap();
+        let end = match event {
+            Event::NewHost(host) => {
+                // a new host while someone is hosting means they handed over
+                let previous = current.replace(Hosting::new(host, now));
+                return self.end_hosting(previous, HostingEnd::HandedOver, now);
+            }
+            Event::HostShutdown => Some(HostingEnd::ShutDown),
+            Event::HostDropped => Some(HostingEnd::Dropped),
+            Event::HostCanceld => Some(HostingEnd::Canceld),
+            _ => None,
+        };
+        if let Some(end) = end {
+            return self.end_hosting(current.take(), end, now);
+        }
+
+        let hosting = match current.as_mut() {
+            Some(hosting) => hosting,
+            None => return,
+        };
+        match event {
+            // keep the first, the host may be reported unreachable again
+            Event::HostUnreachable => {
+                hosting.unreachable_since.get_or_insert(now);
+            }
+            Event::HostRestored => {
+                hosting.unreachable_until(now);
+                hosting.unreachable_since = None;
+            }
+            Event::PlayerJoined(player) => {
+                let playing = Playing {
+                    start: now,
+                    unreachable: Duration::ZERO,
+                };
+                hosting.players.insert(player.clone(), playing);
+            }
+            Event::PlayerLeft(player) => {
+                hosting.unreachable_until(now);
+                if let Some(playing) = hosting.players.remove(player) {
+                    let session = hosting.session(player.clone(), playing, now);
+                    self.store_session(session);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::Uuid;
+
+    fn at(mins: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(mins * 60)
+    }
+
+    fn mins(mins: u64) -> Duration {
+        Duration::from_secs(mins * 60)
+    }
+
+    fn host(name: &str) -> HostDetails {
+        HostDetails {
+            name: name.to_owned(),
+            addrs: Vec::new(),
+            peer: std::net::Ipv4Addr::LOCALHOST.into(),
+            port: 25565,
+            id: Uuid::new_v4(),
+        }
+    }
+
+    #[test]
+    fn unreachable_time() {
+        let stats = Stats::from(StatsDb::from(crate::db::test_db()));
+        let events = [
+            (0, Event::NewHost(host("bob"))),
+            (1, Event::PlayerJoined("Alice".to_owned())),
+            (10, Event::HostUnreachable),
+            (11, Event::HostUnreachable),
+            (12, Event::PlayerJoined("Dave".to_owned())),
+            (15, Event::HostRestored),
+            (20, Event::PlayerLeft("Alice".to_owned())),
+            (30, Event::HostUnreachable),
+            (40, Event::HostDropped),
+        ];
+        for (time, event) in events {
+            stats.handle(&event, at(time));
+        }
+        stats.saved();
+
+        let alice = stats.db.player_stats("Alice").unwrap().unwrap();
+        assert_eq!(alice.played, mins(19));
+        assert_eq!(alice.unreachable, mins(5));
+        let dave = stats.db.player_stats("Dave").unwrap().unwrap();
+        assert_eq!(dave.played, mins(28));
+        assert_eq!(dave.unreachable, mins(3 + 10));
+
+        let history = stats.db.hosting_history(10).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].duration, mins(40));
+        assert_eq!(history[0].unreachable, mins(15));
+        assert_eq!(history[0].end, HostingEnd::Dropped);
+        // saves after the host is gone do not count
+        assert_eq!(history[0].saves, 0);
+    }
+
+    #[test]
+    fn hand_over() {
+        let stats = Stats::from(StatsDb::from(crate::db::test_db()));
+        stats.handle(&Event::NewHost(host("bob")), at(0));
+        stats.saved();
+        stats.handle(&Event::PlayerJoined("Alice".to_owned()), at(5));
+        stats.handle(&Event::NewHost(host("carol")), at(60));
+        stats.handle(&Event::PlayerJoined("Alice".to_owned()), at(62));
+        stats.handle(&Event::HostShutdown, at(90));
+
+        let alice = stats.db.player_stats("Alice").unwrap().unwrap();
+        assert_eq!(alice.sessions, 2);
+        assert_eq!(
+            alice.per_host,
+            [("bob".to_owned(), mins(55)), ("carol".to_owned(), mins(28))]
+        );
+
+        let history = stats.db.hosting_history(10).unwrap();
+        assert_eq!(history[0].host, "carol");
+        assert_eq!(history[0].end, HostingEnd::ShutDown);
+        assert_eq!(history[1].host, "bob");
+        assert_eq!(history[1].end, HostingEnd::HandedOver);
+        assert_eq!(history[1].saves, 1);
+    }
+}
@@ -1,4 +1,4 @@
-use super::{db, events_channel, host, Sessions, World};
+use super::{db, events_channel, host, relay, Sessions, World};
 use std::path::Path;
 use std::time::Duration;
 use tokio::sync::mpsc;
@@ -6,12 +6,17 @@ use tracing::info;
 
 /// util function meant for testing only, panics if anything goes wrong
 pub async fn spawn_test_server(port: u16) {
-    spawn(port, false).await;
+    spawn(port, false, None).await;
 }
 
 /// also populates the object store
 pub async fn spawn_full_test_server(port: u16) {
-    spawn(port, true).await;
+    spawn(port, true, None).await;
+}
+
+/// also runs a relay on the given ports
+pub async fn spawn_relay_test_server(port: u16, relay: relay::Ports) {
+    spawn(port, false, Some(relay)).await;
 }
 
 /// util function meant for testing only, panics if anything goes wrong
@@ -28,12 +33,16 @@ async fn setup_host_files(world: &mut World) {
     world.set_save(path.to_owned()).await.unwrap();
 }
 
-async fn spawn(port: u16, with_host_files: bool) {
+async fn spawn(port: u16, with_host_files: bool, relay: Option<relay::Ports>) {
     use crate::db::user::UserDb;
 
     let db = db::test_db();
-    let domain = "".to_string();
-    let host_state = host::Host::new();
+    // players reach the relay through our domain
+    let domain = match relay {
+        Some(_) => "localhost".to_string(),
+        None => String::new(),
+    };
+    let host_state = host::Host::new(protocol::HostTimings::default());
     let mut world = World::from(db.clone(), host_state.clone()).await;
     let mut userdb = UserDb::from(db);
 
@@ -57,8 +66,11 @@ async fn spawn(port: u16, with_host_files: bool) {
     let sessions = Sessions::default();
 
     let (host_req, host_req_recv) = mpsc::channel(100);
-    let monitor = host::monitor(host_state, events.clone(), host_req_recv);
-    let host = host(sessions, userdb, world, port, domain, events, host_req);
+    let broadcast = host::BroadCast::new(events.clone(), world.stats.clone());
+    let monitor = host::monitor(host_state, broadcast, host_req_recv);
+    let host = host(
+        sessions, userdb, world, port, domain, relay, events, host_req,
+    );
     tokio::spawn(async move {
         tokio::join!(monitor, host);
     });
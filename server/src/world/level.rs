@@ -0,0 +1,182 @@
+//! Reads the `DataVersion` from a worlds level.dat. The file is gzipped
+//! NBT, a tree of named tags. We only walk it far enough to find the one
+//! number we need.
+
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+const END: u8 = 0;
+const INT: u8 = 3;
+const COMPOUND: u8 = 10;
+
+/// the `DataVersion` minecraft writes in level.dat, none if the file is
+/// corrupt or from before minecraft 1.9
+pub fn data_version(level_dat: &[u8]) -> Option<i32> {
+    let mut decoded = Vec::new();
+    GzDecoder::new(level_dat).read_to_end(&mut decoded).ok()?;
+
+    let mut reader = Reader { bytes: &decoded };
+    // the root is an unnamed compound holding the compound Data
+    if reader.u8()? != COMPOUND {
+        return None;
+    }
+    reader.name()?;
+    if reader.find(b"Data")? != COMPOUND {
+        return None;
+    }
+    if reader.find(b"DataVersion")? != INT {
+        return None;
+    }
+    reader.i32()
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < n {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.take(2)?.try_into().unwrap();
+        Some(u16::from_be_bytes(bytes))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        let bytes = self.take(4)?.try_into().unwrap();
+        Some(i32::from_be_bytes(bytes))
+    }
+
+    /// length of an array or list, negative lengths are corrupt
+    fn len(&mut self) -> Option<usize> {
+        self.i32()?.try_into().ok()
+    }
+
+    fn name(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()?;
+        self.take(len as usize)
+    }
+
+    /// skips the payload of a tag of type tag
+    fn skip(&mut self, tag: u8) -> Option<()> {
+        match tag {
+            1 => drop(self.take(1)?),
+            2 => drop(self.take(2)?),
+            3 | 5 => drop(self.take(4)?),
+            4 | 6 => drop(self.take(8)?),
+            7 => {
+                let len = self.len()?;
+                self.take(len)?;
+            }
+            8 => {
+                let len = self.u16()?;
+                self.take(len as usize)?;
+            }
+            9 => {
+                let tag = self.u8()?;
+                for _ in 0..self.len()? {
+                    self.skip(tag)?;
+                }
+            }
+            COMPOUND => loop {
+                let tag = self.u8()?;
+                if tag == END {
+                    break;
+                }
+                self.name()?;
+                self.skip(tag)?;
+            },
+            11 => {
+                let len = self.len()?;
+                self.take(len.checked_mul(4)?)?;
+            }
+            12 => {
+                let len = self.len()?;
+                self.take(len.checked_mul(8)?)?;
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// moves to the payload of the tag called name in the compound we
+    /// are in, returns its type
+    fn find(&mut self, name: &[u8]) -> Option<u8> {
+        loop {
+            let tag = self.u8()?;
+            if tag == END {
+                return None;
+            }
+            if self.name()? == name {
+                return Some(tag);
+            }
+            self.skip(tag)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn named(tag: u8, name: &str) -> Vec<u8> {
+        let mut bytes = vec![tag];
+        bytes.extend((name.len() as u16).to_be_bytes());
+        bytes.extend(name.as_bytes());
+        bytes
+    }
+
+    fn level_dat(data_version: i32) -> Vec<u8> {
+        let mut nbt = named(COMPOUND, "");
+        nbt.extend(named(COMPOUND, "Data"));
+        nbt.extend(named(8, "LevelName"));
+        nbt.extend(5u16.to_be_bytes());
+        nbt.extend(b"world");
+        // a list of compounds
+        nbt.extend(named(9, "ScheduledEvents"));
+        nbt.push(COMPOUND);
+        nbt.extend(1i32.to_be_bytes());
+        nbt.extend(named(4, "TriggerTime"));
+        nbt.extend(42i64.to_be_bytes());
+        nbt.push(END);
+        nbt.extend(named(11, "WanderingTraderId"));
+        nbt.extend(4i32.to_be_bytes());
+        nbt.extend([0u8; 16]);
+        nbt.extend(named(INT, "DataVersion"));
+        nbt.extend(data_version.to_be_bytes());
+        nbt.push(END);
+        nbt.push(END);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&nbt).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn finds_data_version() {
+        assert_eq!(data_version(&level_dat(2865)), Some(2865));
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut corrupt = level_dat(2865);
+        corrupt.truncate(corrupt.len() / 2);
+        assert_eq!(data_version(&corrupt), None);
+        assert_eq!(data_version(b"not gzip"), None);
+    }
+}
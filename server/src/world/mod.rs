@@ -1,27 +1,116 @@
-use protocol::{HostId, HostState};
-use std::sync::Mutex;
+use protocol::{HostId, HostState, ServerInstall, ServerSettings, TimingOverrides, WorldVersion};
+use protocol::{HOST_PROPERTIES, SETTINGS_FILES};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use sync::{DirContent, DirUpdate, ObjectId, ObjectStore, Save, UpdateList};
-use tracing::{debug, info, instrument};
+use tracing::{debug, info, instrument, warn};
 use typed_sled::sled;
+use wrapper::parser::Version;
 
+use crate::chat::Chat;
+use crate::db::settings::SettingsDb;
 use crate::db::world::WorldDb;
+use crate::stats::{Stats, StatsDb};
+
+mod level;
 
 #[derive(Clone, Debug)]
 pub struct World {
     db: WorldDb,
     new_save: Arc<Mutex<Option<Save>>>,
+    settings: SettingsDb,
+    /// minecraft version a host runs, for the last host that reported it
+    host_version: Arc<Mutex<Option<(HostId, String)>>>,
     pub host: crate::host::Host,
+    pub stats: Stats,
+    pub chat: Chat,
 }
 
 impl World {
     pub async fn from(db: sled::Db, host: crate::host::Host) -> Self {
+        let stats = Stats::from(StatsDb::from(db.clone()));
+        let settings = SettingsDb::from(db.clone());
+        let db = WorldDb::from(db).await;
+        host.apply_overrides(&db.timing_overrides()).await;
         Self {
-            db: WorldDb::from(db).await,
+            db,
             new_save: Arc::new(Mutex::new(None)),
+            settings,
+            host_version: Arc::new(Mutex::new(None)),
             host,
+            stats,
+            chat: Chat::default(),
+        }
+    }
+
+    pub fn timing_overrides(&self) -> TimingOverrides {
+        self.db.timing_overrides()
+    }
+
+    pub async fn set_timing_overrides(&self, overrides: TimingOverrides) {
+        self.db.set_timing_overrides(&overrides);
+        self.host.apply_overrides(&overrides).await;
+    }
+
+    pub fn settings(&self) -> Result<ServerSettings, protocol::Error> {
+        self.settings
+            .current()
+            .map_err(|_| protocol::Error::Internal)
+    }
+
+    /// returns the new version, properties each host sets for itself are
+    /// dropped
+    pub fn set_settings(
+        &self,
+        mut files: BTreeMap<String, String>,
+    ) -> Result<u64, protocol::Error> {
+        if let Some(name) = files
+            .keys()
+            .find(|name| !SETTINGS_FILES.contains(&name.as_str()))
+        {
+            return Err(protocol::Error::NotSettingsFile(name.clone()));
+        }
+        if let Some(properties) = files.get_mut("server.properties") {
+            let mut config = wrapper::Config::parse(properties);
+            for key in HOST_PROPERTIES {
+                config.remove(key);
+            }
+            *properties = config.to_string();
         }
+        let version = self.settings.set(&files);
+        version.map_err(|_| protocol::Error::Internal)
+    }
+
+    pub fn server_install(&self) -> Option<ServerInstall> {
+        self.db.server_install()
+    }
+
+    /// adds the files in source to the object store and makes them the
+    /// server install hosts run
+    pub async fn set_server_install(
+        &self,
+        version: String,
+        source: PathBuf,
+    ) -> Result<(), protocol::Error> {
+        if !source.join("server.jar").is_file() {
+            return Err(protocol::Error::NoServerJar);
+        }
+
+        let content = DirContent::from_dir(source.clone())
+            .await
+            .map_err(|e| protocol::Error::ReadInstall(e.to_string()))?;
+        let (files, update_list) = UpdateList::for_new_save(&self.db, content);
+        for (object_id, path) in update_list.0 {
+            let bytes = tokio::fs::read(source.join(&path))
+                .await
+                .map_err(|e| protocol::Error::ReadInstall(e.to_string()))?;
+            self.add_obj(object_id, path.clone(), &bytes).await?;
+            debug!("added object: {:?}", path);
+        }
+        let install = ServerInstall { version, files };
+        self.db.set_server_install(&install);
+        Ok(())
     }
 
     pub fn get_update(&self, dir: DirContent) -> DirUpdate {
@@ -55,7 +144,10 @@ impl World {
 
     pub async fn is_host(&self, id: HostId) -> Result<(), ()> {
         match &*self.host.state.read().await {
-            HostState::Up(host) | HostState::Loading(host) | HostState::Unreachable(host) => {
+            HostState::Up(host)
+            | HostState::Loading(host)
+            | HostState::Unreachable(host)
+            | HostState::ShuttingDown(host) => {
                 if host.id != id {
                     Err(())
                 } else {
@@ -73,12 +165,95 @@ impl World {
         list
     }
 
-    pub fn flush_save(&mut self) -> Result<(), protocol::Error> {
-        let save = self.new_save.lock().unwrap().take().ok_or(protocol::Error::NotSaving)?;
-        self.db.push_save(save);
+    /// a refused save is kept, it can be registered once an admin approved
+    /// the version
+    pub async fn flush_save(&mut self, host_id: HostId) -> Result<(), protocol::Error> {
+        let save = self.new_save.lock().unwrap().clone();
+        let save = save.ok_or(protocol::Error::NotSaving)?;
+        let world = self.db.world_version().unwrap_or_default();
+        let game = match &*self.host_version.lock().unwrap() {
+            Some((id, game)) if *id == host_id => Some(game.clone()),
+            _ => None,
+        };
+        if let Some(game) = &game {
+            self.allowed(&world, game)?;
+        }
+        let data = data_version(&save).await;
+        if let (Some(world), Some(save)) = (world.data, data) {
+            if save < world {
+                return Err(protocol::Error::DataDowngrade { world, save });
+            }
+        }
+
+        let save = self.new_save.lock().unwrap().take();
+        let save = save.ok_or(protocol::Error::NotSaving)?;
+        let game = game.or(world.game);
+        if game.is_some() && game == self.db.approved_version() {
+            // the world now is on the approved version
+            self.db.clear_approved_version();
+        }
+        self.db.push_save(save, &WorldVersion { game, data });
+        self.stats.saved();
         Ok(())
     }
 
+    /// the minecraft server of the host started, refuses versions other
+    /// then the one the world was last played with unless an admin
+    /// approved it
+    pub fn check_version(&self, host_id: HostId, version: &Version) -> Result<(), protocol::Error> {
+        let game = version.to_string();
+        *self.host_version.lock().unwrap() = Some((host_id, game.clone()));
+        let world = self.db.world_version().unwrap_or_default();
+        self.allowed(&world, &game)
+    }
+
+    /// refuses hosting before the server starts if the server install
+    /// runs a version the world may not be played with
+    pub fn check_install(&self) -> Result<(), protocol::Error> {
+        match self.db.server_install() {
+            Some(install) => {
+                let world = self.db.world_version().unwrap_or_default();
+                self.allowed(&world, &install.version)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn allowed(&self, world: &WorldVersion, game: &str) -> Result<(), protocol::Error> {
+        let world = match &world.game {
+            None => return Ok(()),
+            Some(world) if world == game => return Ok(()),
+            Some(world) => world,
+        };
+        if self.db.approved_version().as_deref() == Some(game) {
+            info!(
+                "upgrading world from minecraft {} to approved {}",
+                world, game
+            );
+            return Ok(());
+        }
+        warn!(
+            "refusing host running minecraft {}, world is {}",
+            game, world
+        );
+        Err(protocol::Error::VersionMismatch {
+            world: world.clone(),
+            host: game.to_owned(),
+        })
+    }
+
+    pub fn world_version(&self) -> Option<WorldVersion> {
+        self.db.world_version()
+    }
+
+    pub fn approved_version(&self) -> Option<String> {
+        self.db.approved_version()
+    }
+
+    pub fn approve_version(&self, game: &str) {
+        self.db.set_approved_version(game)
+    }
+
     pub async fn set_save(&self, source: PathBuf) -> Result<(), protocol::Error> {
         match *self.host.state.read().await {
             HostState::NoHost => (),
@@ -93,7 +268,10 @@ impl World {
             self.add_obj(object_id, path.clone(), &bytes).await?;
             debug!("added object: {:?}", path);
         }
-        self.db.push_save(new_save);
+        // the game version is unknown until someone hosts it
+        let data = data_version(&new_save).await;
+        let version = WorldVersion { game: None, data };
+        self.db.push_save(new_save, &version);
         info!("loaded and set save from: {:?}", source);
 
         Ok(())
@@ -108,3 +286,13 @@ impl World {
         Ok(self.db.add_obj(id, path, bytes).await?)
     }
 }
+
+/// none if the save has no readable level.dat
+async fn data_version(save: &Save) -> Option<i32> {
+    let level_dat = save
+        .objects()
+        .iter()
+        .find(|obj| obj.org_path == std::path::Path::new("world/level.dat"))?;
+    let bytes = WorldDb::get_object(level_dat.id).await.ok()?;
+    level::data_version(&bytes)
+}
@@ -0,0 +1,903 @@
+use std::time::Duration;
+
+use protocol::{Event, HostId, HostState, HostTimings, ServiceClient, SessionId};
+use protocol::{ChatMessage, PortCheck, TimingOverrides, User, Uuid};
+use shared::tarpc::context;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+
+mod util;
+use util::{free_port, spawn_test_server, test_conn};
+
+async fn log_in(client: &ServiceClient, num: u8) -> SessionId {
+    client
+        .log_in(
+            context::current(),
+            User::test_username(num),
+            User::test_password(num),
+        )
+        .await
+        .expect("rpc failure")
+        .unwrap()
+}
+
+async fn next_event(client: &ServiceClient, session: SessionId) -> Event {
+    loop {
+        let event = client
+            .await_event(shared::context(35), session)
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        match event {
+            Event::AwaitTimeout => continue,
+            event => return event,
+        }
+    }
+}
+
+async fn become_host(client: &ServiceClient, session: SessionId) -> HostId {
+    let host_id = HostId::new_v4();
+    client
+        .request_to_host(
+            context::current(),
+            session,
+            host_id,
+            25565,
+            None,
+            false,
+            None,
+        )
+        .await
+        .expect("rpc failure")
+        .unwrap();
+
+    let done = "[00:08:39] [Server thread/INFO]: Done (9.997s)! For help, type \"help\"";
+    let done = wrapper::parser::parse(done).unwrap();
+    loop {
+        let (state, _) = client
+            .host(context::current(), session)
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        match state {
+            HostState::Up(_) => return host_id,
+            HostState::Loading(_) => client
+                .pub_mc_line(context::current(), host_id, done.clone())
+                .await
+                .expect("rpc failure")
+                .unwrap(),
+            _ => tokio::task::yield_now().await,
+        }
+    }
+}
+
+#[tokio::test]
+async fn takeover() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let host = test_conn(port).await;
+    let host_session = log_in(&host, 0).await;
+    let host_id = become_host(&host, host_session).await;
+
+    let new = test_conn(port).await;
+    let new_session = log_in(&new, 1).await;
+    let new_id = HostId::new_v4();
+    new.request_takeover(
+        context::current(),
+        new_session,
+        new_id,
+        25566,
+        None,
+        false,
+        None,
+    )
+    .await
+    .expect("rpc failure")
+    .unwrap();
+
+    loop {
+        match next_event(&host, host_session).await {
+            Event::TakeoverRequested(name) => {
+                assert_eq!(name, User::test_username(1));
+                break;
+            }
+            _ => continue,
+        }
+    }
+    host.hand_over(context::current(), host_session, host_id)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+
+    loop {
+        match next_event(&new, new_session).await {
+            Event::NewHost(details) => {
+                assert_eq!(details.id, new_id);
+                assert_eq!(details.port, 25566);
+                break;
+            }
+            _ => continue,
+        }
+    }
+}
+
+#[tokio::test]
+async fn takeover_needs_host() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let client = test_conn(port).await;
+    let session = log_in(&client, 0).await;
+    let res = client
+        .request_takeover(
+            context::current(),
+            session,
+            HostId::new_v4(),
+            25565,
+            None,
+            false,
+            None,
+        )
+        .await
+        .expect("rpc failure");
+    assert_eq!(res, Err(protocol::Error::HostNotUp));
+}
+
+#[tokio::test]
+async fn takeover_checks() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let host = test_conn(port).await;
+    let host_session = log_in(&host, 0).await;
+    let host_id = become_host(&host, host_session).await;
+
+    let res = host
+        .hand_over(context::current(), host_session, host_id)
+        .await
+        .expect("rpc failure");
+    assert_eq!(res, Err(protocol::Error::NoTakeover));
+
+    let res = host
+        .request_takeover(
+            context::current(),
+            host_session,
+            HostId::new_v4(),
+            25565,
+            None,
+            false,
+            None,
+        )
+        .await
+        .expect("rpc failure");
+    assert_eq!(res, Err(protocol::Error::AlreadyHost));
+
+    let new = test_conn(port).await;
+    let new_session = log_in(&new, 1).await;
+    new.request_takeover(
+        context::current(),
+        new_session,
+        HostId::new_v4(),
+        25566,
+        None,
+        false,
+        None,
+    )
+    .await
+    .expect("rpc failure")
+    .unwrap();
+    loop {
+        match next_event(&host, host_session).await {
+            Event::TakeoverRequested(_) => break,
+            _ => continue,
+        }
+    }
+
+    // the host stopping instead of handing over ends the takeover
+    host.release_host(context::current(), host_session, host_id, true)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    loop {
+        match next_event(&new, new_session).await {
+            Event::TakeoverFailed => break,
+            Event::NewHost(_) => panic!("host should not change"),
+            _ => continue,
+        }
+    }
+    loop {
+        match next_event(&new, new_session).await {
+            Event::HostShutdown => break,
+            _ => continue,
+        }
+    }
+}
+
+#[tokio::test]
+async fn no_status_without_host() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let client = test_conn(port).await;
+    let session = log_in(&client, 0).await;
+    let status = client
+        .host_status(context::current(), session)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    assert_eq!(status, None);
+}
+
+#[tokio::test]
+async fn shutdown() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let host = test_conn(port).await;
+    let session = log_in(&host, 0).await;
+    let host_id = become_host(&host, session).await;
+
+    let stopping = "[00:12:14] [Server thread/INFO]: Stopping server";
+    let stopping = wrapper::parser::parse(stopping).unwrap();
+    host.pub_mc_line(context::current(), host_id, stopping)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    loop {
+        match next_event(&host, session).await {
+            Event::HostShuttingDown => break,
+            _ => continue,
+        }
+    }
+
+    host.release_host(context::current(), session, host_id, true)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    loop {
+        match next_event(&host, session).await {
+            Event::HostShutdown => break,
+            _ => continue,
+        }
+    }
+    let (state, _) = host
+        .host(context::current(), session)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    assert!(matches!(state, HostState::NoHost));
+}
+
+async fn players(client: &ServiceClient, session: SessionId) -> Vec<String> {
+    client
+        .players(context::current(), session)
+        .await
+        .expect("rpc failure")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn presence() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let host = test_conn(port).await;
+    let session = log_in(&host, 0).await;
+    let host_id = become_host(&host, session).await;
+    assert!(players(&host, session).await.is_empty());
+
+    let lines = [
+        "[01:08:43] [Server thread/INFO]: Bob[/10.0.0.2:52788] logged in with entity id 179 at (238.3, 56.7, 335.3)",
+        "[01:08:44] [Server thread/INFO]: Alice[/10.0.0.3:52790] logged in with entity id 180 at (238.3, 56.7, 335.3)",
+        "[01:09:12] [Server thread/INFO]: Bob lost connection: Disconnected",
+    ];
+    for line in lines {
+        let line = wrapper::parser::parse(line).unwrap();
+        host.pub_mc_line(context::current(), host_id, line)
+            .await
+            .expect("rpc failure")
+            .unwrap();
+    }
+
+    let mut events = Vec::new();
+    while events.len() < 3 {
+        match next_event(&host, session).await {
+            e @ (Event::PlayerJoined(_) | Event::PlayerLeft(_)) => events.push(e),
+            _ => continue,
+        }
+    }
+    assert!(matches!(&events[0], Event::PlayerJoined(name) if name == "Bob"));
+    assert!(matches!(&events[1], Event::PlayerJoined(name) if name == "Alice"));
+    assert!(matches!(&events[2], Event::PlayerLeft(name) if name == "Bob"));
+    assert_eq!(players(&host, session).await, ["Alice"]);
+
+    host.release_host(context::current(), session, host_id, true)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    loop {
+        match next_event(&host, session).await {
+            Event::HostShutdown => break,
+            _ => continue,
+        }
+    }
+    assert!(players(&host, session).await.is_empty());
+}
+
+async fn chat_history(client: &ServiceClient, session: SessionId) -> Vec<ChatMessage> {
+    client
+        .chat_history(context::current(), session)
+        .await
+        .expect("rpc failure")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn chat() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let host = test_conn(port).await;
+    let session = log_in(&host, 0).await;
+    let host_id = become_host(&host, session).await;
+
+    let other = test_conn(port).await;
+    let other_session = log_in(&other, 1).await;
+    let send = |text: &str| other.send_chat(context::current(), other_session, text.to_owned());
+    send("hi, anyone on?").await.expect("rpc failure").unwrap();
+    let err = send("hi\n/op TestUser_1").await.expect("rpc failure");
+    assert_eq!(err, Err(protocol::Error::InvalidChat));
+
+    let lines = [
+        // what the host relays to the game, should not be bridged back
+        "[01:08:43] [Server thread/INFO]: [Server] <TestUser_1> hi, anyone on?",
+        "[01:08:43] [Server thread/INFO]: [Rcon] <TestUser_1> hi, anyone on?",
+        "[01:08:44] [Server thread/INFO]: <Alice> yes, come join",
+    ];
+    for line in lines {
+        let line = wrapper::parser::parse(line).unwrap();
+        host.pub_mc_line(context::current(), host_id, line)
+            .await
+            .expect("rpc failure")
+            .unwrap();
+    }
+
+    let mut chat = Vec::new();
+    while chat.len() < 2 {
+        if let Event::Chat(msg) = next_event(&host, session).await {
+            chat.push(msg);
+        }
+    }
+    let from_client = ChatMessage {
+        from: User::test_username(1),
+        text: "hi, anyone on?".to_owned(),
+        in_game: false,
+    };
+    let in_game = ChatMessage {
+        from: "Alice".to_owned(),
+        text: "yes, come join".to_owned(),
+        in_game: true,
+    };
+    assert_eq!(chat, [from_client.clone(), in_game.clone()]);
+
+    let late = test_conn(port).await;
+    let late_session = log_in(&late, 2).await;
+    assert_eq!(
+        chat_history(&late, late_session).await,
+        [from_client, in_game]
+    );
+}
+
+#[tokio::test]
+async fn console() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let admin = test_conn(port).await;
+    let err = admin
+        .console_command(context::current(), "time set day".to_owned())
+        .await
+        .expect("rpc failure");
+    assert_eq!(err, Err(protocol::Error::HostNotUp));
+
+    let host = test_conn(port).await;
+    let session = log_in(&host, 0).await;
+    let host_id = become_host(&host, session).await;
+    let other = test_conn(port).await;
+    let other_session = log_in(&other, 1).await;
+
+    let err = admin
+        .console_command(context::current(), "say hi\nop TestUser_1".to_owned())
+        .await
+        .expect("rpc failure");
+    assert_eq!(err, Err(protocol::Error::InvalidCommand));
+
+    let hosting = tokio::spawn(async move {
+        loop {
+            if let Event::ConsoleCommand { id, command } = next_event(&host, session).await {
+                assert_eq!(command, "time set day");
+                let lines = vec!["Set the time to 1000".to_owned()];
+                host.console_output(context::current(), session, host_id, id, lines)
+                    .await
+                    .expect("rpc failure")
+                    .unwrap();
+                return;
+            }
+        }
+    });
+
+    let output = admin
+        .console_command(context::current(), "/time set day ".to_owned())
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    hosting.await.unwrap();
+    assert_eq!(output, ["Set the time to 1000"]);
+
+    // the chat comes after the command, by then other would have seen it
+    other
+        .send_chat(context::current(), other_session, "done?".to_owned())
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    loop {
+        match next_event(&other, other_session).await {
+            Event::ConsoleCommand { .. } => panic!("command reached a session not hosting"),
+            Event::Chat(_) => break,
+            _ => continue,
+        }
+    }
+}
+
+#[tokio::test]
+async fn crash_report() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let host = test_conn(port).await;
+    let session = log_in(&host, 0).await;
+    let host_id = become_host(&host, session).await;
+
+    let report = wrapper::CrashReport {
+        file_name: "crash-2022-01-01_12.00.00-server.txt".to_owned(),
+        text: "a".repeat(protocol::MAX_CRASH_REPORT_LEN + 1),
+    };
+    let err = host
+        .crash_report(context::current(), session, host_id, report.clone())
+        .await
+        .expect("rpc failure");
+    assert_eq!(err, Err(protocol::Error::CrashReportTooLarge));
+
+    let report = wrapper::CrashReport {
+        text: "---- Minecraft Crash Report ----".to_owned(),
+        ..report
+    };
+    host.crash_report(context::current(), session, host_id, report.clone())
+        .await
+        .expect("rpc failure")
+        .unwrap();
+
+    let admin = test_conn(port).await;
+    let crashes = admin
+        .crash_reports(context::current(), 10)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    assert_eq!(crashes.len(), 1);
+    assert_eq!(crashes[0].host, User::test_username(0));
+    assert_eq!(crashes[0].report, report);
+}
+
+#[tokio::test]
+async fn server_settings() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let admin = test_conn(port).await;
+    let mut files = std::collections::BTreeMap::new();
+    files.insert("level.dat".to_owned(), String::new());
+    let err = admin
+        .set_settings(context::current(), files.clone())
+        .await
+        .expect("rpc failure");
+    assert_eq!(
+        err,
+        Err(protocol::Error::NotSettingsFile("level.dat".to_owned()))
+    );
+
+    files.clear();
+    files.insert("ops.json".to_owned(), "[]".to_owned());
+    let properties = "motd=shared\nserver-port=25570\n".to_owned();
+    files.insert("server.properties".to_owned(), properties);
+    for expected in 1..=2 {
+        let version = admin
+            .set_settings(context::current(), files.clone())
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        assert_eq!(version, expected);
+    }
+
+    let host = test_conn(port).await;
+    let session = log_in(&host, 0).await;
+    let settings = host
+        .server_settings(context::current(), session)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    assert_eq!(settings.version, 2);
+    assert_eq!(settings.files["ops.json"], "[]");
+    assert_eq!(settings.files["server.properties"], "motd=shared\n");
+}
+
+#[tokio::test]
+async fn server_install() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let dir = std::env::temp_dir().join(format!("install_test_{}", port));
+    std::fs::create_dir_all(dir.join("libraries")).unwrap();
+    std::fs::write(dir.join("libraries/loader.jar"), "loader").unwrap();
+
+    let admin = test_conn(port).await;
+    let err = admin
+        .set_install(context::current(), "1.18.1".to_owned(), dir.clone())
+        .await
+        .expect("rpc failure");
+    assert_eq!(err, Err(protocol::Error::NoServerJar));
+
+    std::fs::write(dir.join("server.jar"), "server").unwrap();
+    admin
+        .set_install(context::current(), "1.18.1".to_owned(), dir.clone())
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    let version = admin
+        .install_version(context::current())
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    assert_eq!(version.as_deref(), Some("1.18.1"));
+
+    let host = test_conn(port).await;
+    let session = log_in(&host, 0).await;
+    let install = host
+        .server_install(context::current(), session)
+        .await
+        .expect("rpc failure")
+        .unwrap()
+        .unwrap();
+    assert_eq!(install.files.objects().len(), 2);
+    for obj in install.files.objects() {
+        let bytes = host
+            .get_object(context::current(), session, obj.id)
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        assert_eq!(bytes, std::fs::read(dir.join(&obj.org_path)).unwrap());
+        assert_eq!(sync::hash(&bytes), obj.hash());
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// gzipped nbt with only the DataVersion in it
+fn level_dat(data_version: i32) -> Vec<u8> {
+    use std::io::Write;
+    let mut nbt = Vec::new();
+    for (tag, name) in [(10u8, ""), (10, "Data"), (3, "DataVersion")] {
+        nbt.push(tag);
+        nbt.extend((name.len() as u16).to_be_bytes());
+        nbt.extend(name.as_bytes());
+    }
+    nbt.extend(data_version.to_be_bytes());
+    nbt.extend([0, 0]);
+
+    let compression = flate2::Compression::default();
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), compression);
+    encoder.write_all(&nbt).unwrap();
+    encoder.finish().unwrap()
+}
+
+async fn save(
+    host: &ServiceClient,
+    session: SessionId,
+    host_id: HostId,
+    level_dat: Vec<u8>,
+) -> Result<(), protocol::Error> {
+    let path = std::path::PathBuf::from("world/level.dat");
+    let file = sync::FileStatus {
+        path: path.clone(),
+        hash: sync::hash(&level_dat),
+        size: level_dat.len() as u64,
+    };
+    let list = host
+        .new_save(
+            context::current(),
+            session,
+            host_id,
+            sync::DirContent(vec![file]),
+        )
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    for (id, _) in list.0 {
+        host.put_object(
+            context::current(),
+            session,
+            host_id,
+            id,
+            path.clone(),
+            level_dat.clone(),
+        )
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    }
+    host.register_save(context::current(), session, host_id)
+        .await
+        .expect("rpc failure")
+}
+
+#[tokio::test]
+async fn version_guard() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let host = test_conn(port).await;
+    let session = log_in(&host, 0).await;
+    let host_id = become_host(&host, session).await;
+
+    let version = "[15:32:07] [Server thread/INFO]: Starting minecraft server version 1.18.1";
+    let version = wrapper::parser::parse(version).unwrap();
+    host.pub_mc_line(context::current(), host_id, version)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    save(&host, session, host_id, level_dat(2865))
+        .await
+        .unwrap();
+    let err = save(&host, session, host_id, level_dat(2860)).await;
+    assert_eq!(
+        err,
+        Err(protocol::Error::DataDowngrade {
+            world: 2865,
+            save: 2860
+        })
+    );
+
+    let version = "[15:40:07] [Server thread/INFO]: Starting minecraft server version 1.18.2";
+    let version = wrapper::parser::parse(version).unwrap();
+    let err = host
+        .pub_mc_line(context::current(), host_id, version.clone())
+        .await
+        .expect("rpc failure");
+    assert_eq!(
+        err,
+        Err(protocol::Error::VersionMismatch {
+            world: "1.18.1".to_owned(),
+            host: "1.18.2".to_owned()
+        })
+    );
+
+    let err = save(&host, session, host_id, level_dat(2975)).await;
+    assert!(matches!(err, Err(protocol::Error::VersionMismatch { .. })));
+
+    // the refused save is registered once the version is approved
+    let admin = test_conn(port).await;
+    admin
+        .approve_version(context::current(), "1.18.2".to_owned())
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    host.register_save(context::current(), session, host_id)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+
+    let (world, approved) = admin
+        .world_version(context::current())
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    let world = world.unwrap();
+    assert_eq!(world.game.as_deref(), Some("1.18.2"));
+    assert_eq!(world.data, Some(2975));
+    assert_eq!(approved, None);
+
+    let dir = std::env::temp_dir().join(format!("install_guard_test_{}", port));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("server.jar"), "server").unwrap();
+    admin
+        .set_install(context::current(), "1.18.1".to_owned(), dir.clone())
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+    let err = host
+        .request_to_host(
+            context::current(),
+            session,
+            HostId::new_v4(),
+            25565,
+            None,
+            false,
+            None,
+        )
+        .await
+        .expect("rpc failure");
+    assert_eq!(
+        err,
+        Err(protocol::Error::VersionMismatch {
+            world: "1.18.2".to_owned(),
+            host: "1.18.1".to_owned()
+        })
+    );
+}
+
+#[tokio::test]
+async fn saves_keep_server_files() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let dir = std::env::temp_dir().join(format!("keep_files_test_{}", port));
+    std::fs::create_dir_all(dir.join("world")).unwrap();
+    std::fs::write(dir.join("server.jar"), "server").unwrap();
+    std::fs::write(dir.join("eula.txt"), "eula=true").unwrap();
+    std::fs::write(dir.join("world/level.dat"), level_dat(2865)).unwrap();
+    let admin = test_conn(port).await;
+    admin
+        .set_save(context::current(), dir.clone())
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+
+    // hosts only upload the world
+    let host = test_conn(port).await;
+    let session = log_in(&host, 0).await;
+    for data_version in [2866, 2867] {
+        let host_id = become_host(&host, session).await;
+        save(&host, session, host_id, level_dat(data_version))
+            .await
+            .unwrap();
+        host.release_host(context::current(), session, host_id, true)
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        loop {
+            match next_event(&host, session).await {
+                Event::HostShutdown => break,
+                _ => continue,
+            }
+        }
+    }
+
+    let update = host
+        .dir_update(context::current(), session, sync::DirContent(Vec::new()))
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    let paths: Vec<_> = update.0.iter().map(sync::SyncAction::path).collect();
+    for path in ["server.jar", "eula.txt", "world/level.dat"] {
+        assert!(paths.contains(&std::path::Path::new(path)), "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn timing_overrides() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let client = test_conn(port).await;
+    let session = log_in(&client, 0).await;
+    let overrides = TimingOverrides {
+        loading: Some(Duration::from_secs(20 * 60)),
+        ..TimingOverrides::default()
+    };
+    client
+        .set_timing_overrides(context::current(), overrides)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+
+    let (_, timings) = client
+        .host(context::current(), session)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    assert_eq!(timings, HostTimings::default().with(&overrides));
+    assert_eq!(timings.loading, Duration::from_secs(20 * 60));
+
+    let zero = TimingOverrides {
+        ping_interval: Some(Duration::ZERO),
+        ..TimingOverrides::default()
+    };
+    let err = client
+        .set_timing_overrides(context::current(), zero)
+        .await
+        .expect("rpc failure");
+    assert_eq!(err, Err(protocol::Error::ZeroPingInterval));
+}
+
+#[tokio::test]
+async fn takeover_times_out() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let host = test_conn(port).await;
+    let host_session = log_in(&host, 0).await;
+    let overrides = TimingOverrides {
+        hand_over: Some(Duration::from_millis(100)),
+        ..TimingOverrides::default()
+    };
+    host.set_timing_overrides(context::current(), overrides)
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    let _ = become_host(&host, host_session).await;
+
+    let new = test_conn(port).await;
+    let new_session = log_in(&new, 1).await;
+    new.request_takeover(
+        context::current(),
+        new_session,
+        HostId::new_v4(),
+        25566,
+        None,
+        false,
+        None,
+    )
+    .await
+    .expect("rpc failure")
+    .unwrap();
+
+    loop {
+        match next_event(&new, new_session).await {
+            Event::TakeoverFailed => break,
+            Event::NewHost(_) => panic!("host should not change"),
+            _ => continue,
+        }
+    }
+}
+
+/// listens on a free port answering everyone with answer
+async fn answering(answer: Vec<u8>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            conn.write_all(&answer).await.unwrap();
+        }
+    });
+    port
+}
+
+#[tokio::test]
+async fn port_check() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let client = test_conn(port).await;
+    let session = log_in(&client, 0).await;
+    let token = Uuid::new_v4();
+
+    let open = answering(token.as_bytes().to_vec()).await;
+    let wrong_target = answering(b"not the token we expect".to_vec()).await;
+    let refused = free_port();
+    let cases = [
+        (open, PortCheck::Open),
+        (wrong_target, PortCheck::WrongTarget),
+        (refused, PortCheck::Refused),
+    ];
+    for (port, expected) in cases {
+        let check = client
+            .check_port(context::current(), session, port, token)
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        assert_eq!(check, expected, "port: {}", port);
+    }
+}
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use protocol::{relay, HostId, HostState, Reach, ServiceClient, SessionId, User};
+use server::relay::Ports;
+use server::util::spawn_relay_test_server;
+use shared::tarpc::context;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, timeout};
+
+mod util;
+use util::{free_port, spawn_test_server, test_conn};
+
+async fn log_in(client: &ServiceClient, num: u8) -> SessionId {
+    client
+        .log_in(
+            context::current(),
+            User::test_username(num),
+            User::test_password(num),
+        )
+        .await
+        .expect("rpc failure")
+        .unwrap()
+}
+
+async fn spawn_relay() -> (ServiceClient, Ports) {
+    let port = free_port();
+    let ports = Ports {
+        public: free_port(),
+        tunnel: free_port(),
+    };
+    spawn_relay_test_server(port, ports).await;
+    (test_conn(port).await, ports)
+}
+
+/// stands in for the minecraft server of the host, echos what it gets
+async fn spawn_echo() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let (mut read, mut write) = conn.split();
+                tokio::io::copy(&mut read, &mut write).await
+            });
+        }
+    });
+    port
+}
+
+async fn echo_through(relay_port: u16, msg: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut player = TcpStream::connect(("127.0.0.1", relay_port)).await?;
+    player.write_all(msg).await?;
+    let mut reply = vec![0u8; msg.len()];
+    player.read_exact(&mut reply).await?;
+    Ok(reply)
+}
+
+#[tokio::test]
+async fn relays_to_host() {
+    let (client, ports) = spawn_relay().await;
+    let session = log_in(&client, 0).await;
+    let tunnel_port = client.tunnel_port(context::current()).await.unwrap();
+    assert_eq!(tunnel_port, Some(ports.tunnel));
+
+    let mc_port = spawn_echo().await;
+    let host_id = HostId::new_v4();
+    client
+        .request_to_host(
+            context::current(),
+            session,
+            host_id,
+            mc_port,
+            None,
+            true,
+            None,
+        )
+        .await
+        .expect("rpc failure")
+        .unwrap();
+    let details = loop {
+        let (state, _) = client
+            .host(context::current(), session)
+            .await
+            .expect("rpc failure")
+            .unwrap();
+        match state {
+            HostState::Loading(details) => break details,
+            _ => tokio::task::yield_now().await,
+        }
+    };
+    let relay_addr = details
+        .addrs
+        .iter()
+        .find(|addr| addr.reach == Reach::Relay)
+        .expect("relay should be advertised");
+    assert_eq!(relay_addr.port, Some(ports.public));
+
+    tokio::spawn(
+        async move { relay::host("127.0.0.1", ports.tunnel, session, host_id, mc_port).await },
+    );
+
+    // the relay drops players until the tunnel is open
+    let reply = loop {
+        match echo_through(ports.public, b"hello host").await {
+            Ok(reply) => break reply,
+            Err(_) => sleep(Duration::from_millis(20)).await,
+        }
+    };
+    assert_eq!(reply, b"hello host");
+    let reply = echo_through(ports.public, b"second player").await.unwrap();
+    assert_eq!(reply, b"second player");
+}
+
+#[tokio::test]
+async fn refuses_non_host() {
+    let (client, ports) = spawn_relay().await;
+    let session = log_in(&client, 0).await;
+    let mc_port = spawn_echo().await;
+
+    let not_hosting = relay::host(
+        "127.0.0.1",
+        ports.tunnel,
+        session,
+        HostId::new_v4(),
+        mc_port,
+    );
+    timeout(Duration::from_secs(10), not_hosting)
+        .await
+        .expect("server should close the tunnel")
+        .unwrap();
+    assert!(echo_through(ports.public, b"hello").await.is_err());
+}
+
+#[tokio::test]
+async fn needs_relay() {
+    let port = free_port();
+    spawn_test_server(port).await;
+
+    let client = test_conn(port).await;
+    let session = log_in(&client, 0).await;
+    let res = client
+        .request_to_host(
+            context::current(),
+            session,
+            HostId::new_v4(),
+            25565,
+            None,
+            true,
+            None,
+        )
+        .await
+        .expect("rpc failure");
+    assert_eq!(res, Err(protocol::Error::NoRelay));
+}
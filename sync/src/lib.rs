@@ -17,6 +17,13 @@ pub struct Object {
     pub size: u64,
 }
 
+impl Object {
+    /// checksum of the content, see [`hash`]
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Ran into an error while walking through save dir: {0}")]
@@ -64,6 +71,22 @@ impl Save {
 
         DirUpdate(update)
     }
+
+    /// like [`Save::needed_update`] but files on the remote that are not
+    /// in this save are left alone
+    pub fn missing(&self, remote: &DirContent) -> DirUpdate {
+        let update = self.needed_update(remote.clone());
+        let update = update
+            .0
+            .into_iter()
+            .filter(|action| !matches!(action, SyncAction::Remove(_)))
+            .collect();
+        DirUpdate(update)
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.0.iter().any(|obj| obj.org_path == path)
+    }
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
@@ -137,7 +160,17 @@ pub enum SyncAction {
     Add(PathBuf, ObjectId),
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+impl SyncAction {
+    pub fn path(&self) -> &Path {
+        match self {
+            SyncAction::Replace(path, _) | SyncAction::Remove(path) | SyncAction::Add(path, _) => {
+                path
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct ObjectId(pub u64);
 /// list of (relative) paths on the remote that need to be uploaded
 /// and the objectid they should be assigned
@@ -242,7 +275,9 @@ pub trait PathCheck {
 
 /// replace any path in self that is not on the allowed list with one
 /// of a known safe Save or remove the path if it is not in a previous
-/// Save or on the allowed list.
+/// Save or on the allowed list. Paths of the safe Save that are not on
+/// the allowed list are kept even if they are missing from self, clients
+/// can not change those files so they can not remove them either.
 pub fn secure_new_save(
     unchecked: (Save, UpdateList),
     safe: Save,
@@ -252,7 +287,7 @@ pub fn secure_new_save(
         .into_iter()
         .map(|o| (o.org_path.clone(), o))
         .collect();
-    let checked_save = unchecked
+    let mut checked_save: Vec<Object> = unchecked
         .0
         .into_iter()
         .filter_map(|obj| match check.is_safe(&obj.org_path) {
@@ -260,6 +295,10 @@ pub fn secure_new_save(
             false => safe_obj.remove(&obj.org_path),
         })
         .collect();
+    let kept = safe_obj
+        .into_values()
+        .filter(|obj| !check.is_safe(&obj.org_path));
+    checked_save.extend(kept);
     let checked_list = unchecked
         .1
         .into_iter()
@@ -146,4 +146,24 @@ mod empty_store {
             ])
         )
     }
+
+    #[test]
+    fn missing_keeps_extra() {
+        let store = Objects::default();
+
+        let (new_save, _) = UpdateList::for_new_save(&store, remote_a());
+        let update = new_save.missing(&remote_b());
+        assert_eq!(
+            update,
+            DirUpdate(vec![
+                SyncAction::Replace(PathBuf::from("none_existing_dir/foo.txt"), ObjectId(1)),
+                SyncAction::Add(
+                    PathBuf::from("none_existing_dir/missing_in_b.mca"),
+                    ObjectId(3)
+                ),
+            ])
+        );
+        assert!(new_save.contains(Path::new("none_existing_dir/foo.txt")));
+        assert!(!new_save.contains(Path::new("none_existing_dir/extra_file.mca")));
+    }
 }
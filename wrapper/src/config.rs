@@ -1,30 +1,346 @@
+use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
 
+use rand::distributions::Alphanumeric;
+use rand::Rng;
 use tokio::io;
 
+/// the `server.properties` file, changing it keeps the order of the
+/// properties, comments and properties we do not know about
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Config(String);
+pub struct Config {
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    /// value as written in the file, escapes included
+    Property { key: String, value: String },
+    /// comment or empty line
+    Other(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Difficulty {
+    Peaceful,
+    Easy,
+    Normal,
+    Hard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameMode {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
 
 impl Default for Config {
     fn default() -> Self {
         const DEFAULT: &str = include_str!("default.properties");
-        Self(DEFAULT.to_owned())
+        Self::parse(DEFAULT)
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Property { key, value } => writeln!(f, "{}={}", key, value)?,
+                Line::Other(text) => writeln!(f, "{}", text)?,
+            }
+        }
+        Ok(())
     }
 }
+
 impl Config {
+    /// never fails, lines that are not a property are kept as is
+    pub fn parse(text: &str) -> Self {
+        let lines = text
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim_start();
+                if trimmed.is_empty() || trimmed.starts_with(['#', '!']) {
+                    return Line::Other(line.to_owned());
+                }
+                match trimmed.split_once(['=', ':']) {
+                    Some((key, value)) => Line::Property {
+                        key: key.trim_end().to_owned(),
+                        value: value.trim_start().to_owned(),
+                    },
+                    None => Line::Property {
+                        key: trimmed.trim_end().to_owned(),
+                        value: String::new(),
+                    },
+                }
+            })
+            .collect();
+        Self { lines }
+    }
+
+    /// sets every property of other on self, comments and properties
+    /// only self has are kept
+    pub fn merge(&mut self, other: &Config) {
+        for line in &other.lines {
+            if let Line::Property { key, value } = line {
+                self.set(key, value);
+            }
+        }
+    }
+
+    /// the value of key as written in the file, escapes included
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.lines.iter().find_map(|line| match line {
+            Line::Property { key: k, value } if k == key => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// sets key to value, which must already be escaped. Appends the
+    /// property if the file does not have it yet
+    pub fn set(&mut self, key: &str, value: &str) -> &mut Self {
+        let existing = self.lines.iter_mut().find_map(|line| match line {
+            Line::Property { key: k, value } if k == key => Some(value),
+            _ => None,
+        });
+        match existing {
+            Some(existing) => *existing = value.to_owned(),
+            None => self.lines.push(Line::Property {
+                key: key.to_owned(),
+                value: value.to_owned(),
+            }),
+        }
+        self
+    }
+
+    pub fn remove(&mut self, key: &str) -> &mut Self {
+        self.lines.retain(|line| match line {
+            Line::Property { key: k, .. } => k != key,
+            Line::Other(_) => true,
+        });
+        self
+    }
+
+    /// none if the property is missing or its value is not valid
+    fn get_parsed<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key)?.trim().parse().ok()
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.get_parsed("server-port")
+    }
+    pub fn set_port(&mut self, port: u16) -> &mut Self {
+        self.set("server-port", &port.to_string())
+    }
     pub fn with_port(&mut self, port: u16) -> Self {
-        let start = self.0.find("server-port=").unwrap();
-        let start = start + "server-port=".len();
-        let stop = self.0[start..].find(char::is_whitespace).unwrap();
-        let range = start..start+stop;
-        self.0.replace_range(range, &port.to_string());
-        self.clone()
+        self.set_port(port).clone()
+    }
+
+    /// the message shown in the server list
+    pub fn motd(&self) -> Option<String> {
+        self.get("motd").map(unescape)
+    }
+    pub fn set_motd(&mut self, motd: &str) -> &mut Self {
+        self.set("motd", &escape(motd))
+    }
+
+    pub fn difficulty(&self) -> Option<Difficulty> {
+        self.get_parsed("difficulty")
+    }
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) -> &mut Self {
+        self.set("difficulty", &difficulty.to_string())
+    }
+
+    /// the game mode of players joining for the first time
+    pub fn gamemode(&self) -> Option<GameMode> {
+        self.get_parsed("gamemode")
+    }
+    pub fn set_gamemode(&mut self, gamemode: GameMode) -> &mut Self {
+        self.set("gamemode", &gamemode.to_string())
+    }
+
+    pub fn max_players(&self) -> Option<u32> {
+        self.get_parsed("max-players")
+    }
+    pub fn set_max_players(&mut self, max: u32) -> &mut Self {
+        self.set("max-players", &max.to_string())
+    }
+
+    /// only players on the whitelist may join
+    pub fn whitelist(&self) -> Option<bool> {
+        self.get_parsed("white-list")
+    }
+    /// also removes players that are not on the whitelist when it is
+    /// reloaded
+    pub fn set_whitelist(&mut self, on: bool) -> &mut Self {
+        self.set("white-list", &on.to_string());
+        self.set("enforce-whitelist", &on.to_string())
+    }
+
+    /// in chunks
+    pub fn view_distance(&self) -> Option<u8> {
+        self.get_parsed("view-distance")
+    }
+    pub fn set_view_distance(&mut self, chunks: u8) -> &mut Self {
+        self.set("view-distance", &chunks.to_string())
+    }
+
+    /// none if the seed is left empty, a new world then gets a random one
+    pub fn level_seed(&self) -> Option<String> {
+        self.get("level-seed")
+            .map(unescape)
+            .filter(|seed| !seed.is_empty())
+    }
+    pub fn set_level_seed(&mut self, seed: &str) -> &mut Self {
+        self.set("level-seed", &escape(seed))
+    }
+
+    /// enables rcon on port with a newly generated password. Feedback
+    /// to rcon commands stays on the console, so we still see the
+    /// server save
+    pub fn set_rcon(&mut self, port: u16) -> &mut Self {
+        let password: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+        self.set("enable-rcon", "true")
+            .set("rcon.port", &port.to_string())
+            .set("rcon.password", &password)
+            .set("broadcast-rcon-to-ops", "true")
+    }
+    pub fn with_rcon(&mut self, port: u16) -> Self {
+        self.set_rcon(port).clone()
+    }
+    /// the rcon port and password, none if rcon is disabled or has no
+    /// password, the server does not start rcon without one
+    pub fn rcon(&self) -> Option<(u16, String)> {
+        if !self.get_parsed::<bool>("enable-rcon")? {
+            return None;
+        }
+        let port = self.get_parsed("rcon.port").unwrap_or(25575);
+        let password = self.get("rcon.password").map(unescape);
+        let password = password.filter(|p| !p.is_empty())?;
+        Some((port, password))
+    }
+
+    /// read the `server.properties` file in dir
+    pub async fn read(dir: &Path) -> io::Result<Self> {
+        let mut path = dir.to_owned();
+        path.push("server.properties");
+        let text = tokio::fs::read_to_string(path).await?;
+        Ok(Self::parse(&text))
     }
     pub async fn write(&self, dir: &Path) -> io::Result<()> {
         let mut path = dir.to_owned();
         path.push("server.properties");
-        tokio::fs::write(path, &self.0).await
+        tokio::fs::write(path, self.to_string()).await
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Difficulty::Peaceful => "peaceful",
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        })
+    }
+}
+
+/// servers before 1.13 wrote the difficulty as a number
+impl FromStr for Difficulty {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "peaceful" | "0" => Ok(Difficulty::Peaceful),
+            "easy" | "1" => Ok(Difficulty::Easy),
+            "normal" | "2" => Ok(Difficulty::Normal),
+            "hard" | "3" => Ok(Difficulty::Hard),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for GameMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GameMode::Survival => "survival",
+            GameMode::Creative => "creative",
+            GameMode::Adventure => "adventure",
+            GameMode::Spectator => "spectator",
+        })
+    }
+}
+
+/// servers before 1.13 wrote the game mode as a number
+impl FromStr for GameMode {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "survival" | "0" => Ok(GameMode::Survival),
+            "creative" | "1" => Ok(GameMode::Creative),
+            "adventure" | "2" => Ok(GameMode::Adventure),
+            "spectator" | "3" => Ok(GameMode::Spectator),
+            _ => Err(()),
+        }
+    }
+}
+
+/// escapes value the way java reads property files. Older servers read
+/// the file as latin-1, so anything else is written as a unicode escape
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            ' '..='~' => escaped.push(c),
+            _ => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    escaped.push_str(&format!("\\u{:04X}", unit));
+                }
+            }
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> String {
+    let mut units = Vec::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('f') => '\u{c}',
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    match u16::from_str_radix(&hex, 16) {
+                        Ok(unit) => units.push(unit),
+                        Err(_) => units.extend(hex.encode_utf16()),
+                    }
+                    continue;
+                }
+                Some(c) => c,
+                None => continue,
+            },
+            c => c,
+        };
+        let mut buf = [0u16; 2];
+        units.extend_from_slice(c.encode_utf16(&mut buf));
     }
+    // escapes may hold half of a surrogate pair
+    String::from_utf16_lossy(&units)
 }
 
 #[cfg(test)]
@@ -38,6 +354,99 @@ mod tests {
         config.with_port(25565);
         assert_eq!(config, Config::default());
     }
-}
 
+    #[test]
+    fn set_missing_port() {
+        let mut config = Config::parse("motd=A Minecraft Server");
+        config.with_port(42);
+        assert_eq!(
+            config.to_string(),
+            "motd=A Minecraft Server\nserver-port=42\n"
+        );
+    }
+
+    #[test]
+    fn enable_rcon() {
+        assert_eq!(Config::default().rcon(), None);
+        let config = Config::default().with_rcon(25580);
+        let (port, password) = config.rcon().unwrap();
+        assert_eq!(port, 25580);
+        assert_eq!(password.len(), 32);
+        assert_ne!(Config::default().with_rcon(25580), config);
+    }
+
+    #[test]
+    fn keeps_unknown() {
+        let text = "#Minecraft server properties\n\
+            \n\
+            some-mod.setting=on\n\
+            difficulty=hard\n\
+            ! old style comment\n";
+        let mut config = Config::parse(text);
+        assert_eq!(config.to_string(), text);
+
+        config.set_difficulty(Difficulty::Peaceful);
+        assert_eq!(config.get("some-mod.setting"), Some("on"));
+        assert_eq!(config.to_string(), text.replace("hard", "peaceful"));
+
+        config.remove("some-mod.setting");
+        assert_eq!(config.get("some-mod.setting"), None);
+        assert!(config.to_string().starts_with("#Minecraft server properties\n\n"));
+    }
+
+    #[test]
+    fn typed() {
+        let mut config = Config::default();
+        assert_eq!(config.port(), Some(25565));
+        assert_eq!(config.motd().as_deref(), Some("Powerd by WorldSync"));
+        assert_eq!(config.difficulty(), Some(Difficulty::Easy));
+        assert_eq!(config.gamemode(), Some(GameMode::Creative));
+        assert_eq!(config.max_players(), Some(20));
+        assert_eq!(config.whitelist(), Some(false));
+        assert_eq!(config.view_distance(), Some(10));
+        assert_eq!(config.level_seed().as_deref(), Some("-1465919862"));
+
+        config
+            .set_gamemode(GameMode::Survival)
+            .set_max_players(8)
+            .set_whitelist(true)
+            .set_view_distance(12)
+            .set_level_seed("");
+        assert_eq!(config.gamemode(), Some(GameMode::Survival));
+        assert_eq!(config.max_players(), Some(8));
+        assert_eq!(config.whitelist(), Some(true));
+        assert_eq!(config.get("enforce-whitelist"), Some("true"));
+        assert_eq!(config.view_distance(), Some(12));
+        assert_eq!(config.level_seed(), None);
+
+        let config = Config::parse("difficulty=2\ngamemode=3\nmax-players=many");
+        assert_eq!(config.difficulty(), Some(Difficulty::Normal));
+        assert_eq!(config.gamemode(), Some(GameMode::Spectator));
+        assert_eq!(config.max_players(), None);
+    }
+
+    #[test]
+    fn escaped_motd() {
+        let mut config = Config::default();
+        let motd = "§6Gold\\Server 🌍\nsecond line";
+        config.set_motd(motd);
+        assert_eq!(
+            config.get("motd"),
+            Some("\\u00A76Gold\\\\Server \\uD83C\\uDF0D\\nsecond line")
+        );
+        assert_eq!(config.motd().as_deref(), Some(motd));
+    }
+
+    #[test]
+    fn merge() {
+        let mut config = Config::default();
+        let existing = Config::parse("#edited by hand\nmotd=Our world\nsome-mod.setting=on");
+        config.merge(&existing);
 
+        assert_eq!(config.motd().as_deref(), Some("Our world"));
+        assert_eq!(config.get("some-mod.setting"), Some("on"));
+        assert_eq!(config.port(), Some(25565));
+        // comments of the file merged in are not copied
+        assert!(!config.to_string().contains("#edited by hand"));
+    }
+}
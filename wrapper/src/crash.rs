@@ -0,0 +1,63 @@
+//! Reports minecraft writes to `crash-reports` in the server folder when
+//! it crashes. They contain the stacktrace and what the server was doing.
+
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CrashReport {
+    /// name of the report file, contains the time of the crash
+    pub file_name: String,
+    pub text: String,
+}
+
+/// the newest report in the `crash-reports` folder of working_dir, only
+/// returns reports written after since
+pub(crate) async fn newest(working_dir: &Path, since: SystemTime) -> Option<CrashReport> {
+    match newest_inner(working_dir, since).await {
+        Ok(report) => report,
+        Err(e) => {
+            warn!("could not read crash reports: {:?}", e);
+            None
+        }
+    }
+}
+
+async fn newest_inner(working_dir: &Path, since: SystemTime) -> io::Result<Option<CrashReport>> {
+    let dir = working_dir.join("crash-reports");
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mut newest = None;
+    while let Some(entry) = entries.next_entry().await? {
+        let modified = entry.metadata().await?.modified()?;
+        if modified < since {
+            continue;
+        }
+        match &newest {
+            Some((_, newest_modified)) if *newest_modified >= modified => (),
+            _ => newest = Some((entry.path(), modified)),
+        }
+    }
+
+    let path = match newest {
+        Some((path, _)) => path,
+        None => return Ok(None),
+    };
+    let bytes = tokio::fs::read(&path).await?;
+    Ok(Some(CrashReport {
+        file_name: path
+            .file_name()
+            .expect("read_dir entries have a name")
+            .to_string_lossy()
+            .into_owned(),
+        text: String::from_utf8_lossy(&bytes).into_owned(),
+    }))
+}
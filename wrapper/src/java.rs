@@ -0,0 +1,280 @@
+//! Finds a java runtime that can run the server jar. New minecraft
+//! releases regularly need a newer java, an older one fails to load the
+//! jar with an error that does not say which java to install.
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+use tokio::time::timeout;
+use tracing::{debug, warn};
+
+use crate::Error;
+
+/// distributions install runtimes here, one runtime per folder
+const INSTALL_DIRS: &[&str] = &[
+    "/usr/lib/jvm",
+    "/usr/lib64/jvm",
+    "/usr/java",
+    "/opt/java",
+    "/opt/jdk",
+];
+
+/// how long `java -version` may take before we skip the runtime
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Runtime {
+    /// the java binary
+    pub path: PathBuf,
+    /// the feature release: 8 for java 1.8.0_312, 17 for java 17.0.2
+    pub version: u16,
+}
+
+/// the runtime to run the server jar with. The configured runtime, either
+/// a java binary or the folder it is installed in, is preferred if it is
+/// new enough. Then the one in `JAVA_HOME`, the one on the `PATH` and
+/// those in the usual install locations. If we can not tell which java
+/// the jar needs, for example as it is missing, the first runtime found
+/// is used
+pub async fn select(configured: Option<&Path>, jar: &Path) -> Result<Runtime, Error> {
+    let required = required(jar).await;
+    let found = discover(configured).await;
+    if let Some(configured) = configured.map(binary) {
+        match found.iter().find(|r| r.path == configured) {
+            None => warn!("configured java is not usable: {}", configured.display()),
+            Some(r) if required.is_some_and(|required| r.version < required) => {
+                warn!("configured java {} is too old for the server", r.version)
+            }
+            Some(_) => (),
+        }
+    }
+    pick(found, required)
+}
+
+/// the installed runtimes, in order of preference
+pub async fn discover(configured: Option<&Path>) -> Vec<Runtime> {
+    let mut found: Vec<Runtime> = Vec::new();
+    let mut seen = Vec::new();
+    for path in candidates(configured) {
+        // the java on the path is usually a link into an install folder
+        let real = match tokio::fs::canonicalize(&path).await {
+            Ok(real) => real,
+            Err(_) => continue,
+        };
+        if seen.contains(&real) {
+            continue;
+        }
+        seen.push(real);
+        match version(&path).await {
+            Some(version) => found.push(Runtime { path, version }),
+            None => debug!("could not get version of java: {}", path.display()),
+        }
+    }
+    found
+}
+
+/// the java version the jar needs. Server jars since minecraft 1.18 are
+/// a bundler that runs on java 8, they list the version the server
+/// needs in `version.json`. Older jars need the java their main class
+/// was compiled for. None if the jar is missing or not a runnable jar
+pub async fn required(jar: &Path) -> Option<u16> {
+    let jar = jar.to_owned();
+    tokio::task::spawn_blocking(move || required_blocking(&jar))
+        .await
+        .expect("reading the jar does not panic")
+}
+
+fn required_blocking(jar: &Path) -> Option<u16> {
+    let jar = File::open(jar).ok()?;
+    let mut jar = zip::ZipArchive::new(jar).ok()?;
+    if let Some(listed) = listed_version(&mut jar) {
+        return Some(listed);
+    }
+    // java 5 introduced class file version 49, every release since
+    // increments it
+    class_version(&mut jar)?.checked_sub(44)
+}
+
+/// paths a runtime may be at, they need not exist
+fn candidates(configured: Option<&Path>) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    paths.extend(configured.map(binary));
+    if let Some(home) = env::var_os("JAVA_HOME") {
+        paths.push(binary(Path::new(&home)));
+    }
+    if let Some(path) = env::var_os("PATH") {
+        paths.extend(env::split_paths(&path).map(|dir| dir.join("java")));
+    }
+    for dir in INSTALL_DIRS {
+        let mut installs: Vec<_> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(Result::ok).map(|e| e.path()).collect(),
+            Err(_) => continue,
+        };
+        installs.sort();
+        paths.extend(installs.iter().map(|install| binary(install)));
+    }
+    paths
+}
+
+/// the java binary of an install folder, other paths are left as is
+fn binary(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        path.join("bin").join("java")
+    } else {
+        path.to_owned()
+    }
+}
+
+async fn version(java: &Path) -> Option<u16> {
+    let output = Command::new(java)
+        .arg("-version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output();
+    let output = timeout(VERSION_TIMEOUT, output).await.ok()?.ok()?;
+    parse_version(&String::from_utf8_lossy(&output.stderr))
+}
+
+/// parses the output of `java -version`, for example:
+/// `openjdk version "17.0.2" 2022-01-18` or `java version "1.8.0_312"`
+fn parse_version(output: &str) -> Option<u16> {
+    let quoted = output.split(" version \"").nth(1)?;
+    let version = &quoted[..quoted.find('"')?];
+    let mut parts = version.split(['.', '-', '_', '+']);
+    let feature = match parts.next()? {
+        // before java 9 versions started with 1.
+        "1" => parts.next()?,
+        feature => feature,
+    };
+    feature.parse().ok()
+}
+
+/// the first runtime that is new enough
+fn pick(found: Vec<Runtime>, required: Option<u16>) -> Result<Runtime, Error> {
+    let newest = found.iter().map(|r| r.version).max().ok_or(Error::NoJava)?;
+    let required = match required {
+        Some(required) => required,
+        None => return Ok(found.into_iter().next().expect("found one above")),
+    };
+    found
+        .into_iter()
+        .find(|r| r.version >= required)
+        .ok_or(Error::JavaTooOld { required, newest })
+}
+
+/// the `java_version` in `version.json`, none if the jar has no such file
+fn listed_version(jar: &mut zip::ZipArchive<File>) -> Option<u16> {
+    let version = jar.by_name("version.json").ok()?;
+    let version: serde_json::Value = serde_json::from_reader(version).ok()?;
+    let java = version.get("java_version")?.as_u64()?;
+    java.try_into().ok()
+}
+
+/// the major class file version of the main class of a jar
+fn class_version(jar: &mut zip::ZipArchive<File>) -> Option<u16> {
+    let mut manifest = String::new();
+    jar.by_name("META-INF/MANIFEST.MF")
+        .ok()?
+        .read_to_string(&mut manifest)
+        .ok()?;
+    let class = main_class(&manifest)?.replace('.', "/") + ".class";
+
+    // magic number, minor version then major version
+    let mut header = [0u8; 8];
+    jar.by_name(&class).ok()?.read_exact(&mut header).ok()?;
+    if header[..4] != [0xCA, 0xFE, 0xBA, 0xBE] {
+        return None;
+    }
+    Some(u16::from_be_bytes([header[6], header[7]]))
+}
+
+fn main_class(manifest: &str) -> Option<String> {
+    let mut lines = manifest.lines();
+    let first = lines.find_map(|l| l.strip_prefix("Main-Class:"))?;
+    let mut class = first.trim().to_owned();
+    // long values continue on the next lines, which start with a space
+    class.extend(lines.map_while(|l| l.strip_prefix(' ')));
+    Some(class)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn versions() {
+        let modern = "openjdk version \"17.0.2\" 2022-01-18\nOpenJDK Runtime Environment";
+        assert_eq!(parse_version(modern), Some(17));
+        assert_eq!(parse_version("java version \"1.8.0_312\""), Some(8));
+        assert_eq!(
+            parse_version("openjdk version \"21-ea\" 2023-09-19"),
+            Some(21)
+        );
+        assert_eq!(parse_version("Error: could not open `jvm.cfg'"), None);
+    }
+
+    fn runtime(version: u16) -> Runtime {
+        let path = PathBuf::from(format!("/usr/lib/jvm/java-{}/bin/java", version));
+        Runtime { path, version }
+    }
+
+    #[test]
+    fn picks_first_new_enough() {
+        let found = vec![runtime(8), runtime(17), runtime(21)];
+        assert_eq!(pick(found.clone(), Some(16)), Ok(runtime(17)));
+        assert_eq!(pick(found.clone(), None), Ok(runtime(8)));
+        assert_eq!(
+            pick(found, Some(25)),
+            Err(Error::JavaTooOld {
+                required: 25,
+                newest: 21
+            })
+        );
+        assert_eq!(pick(Vec::new(), Some(17)), Err(Error::NoJava));
+    }
+
+    /// a jar with a main class compiled for java `compiled_for`
+    fn write_jar(path: &Path, compiled_for: u8, version_json: Option<&str>) {
+        let mut jar = zip::ZipWriter::new(File::create(path).unwrap());
+        let options = zip::write::FileOptions::default();
+        jar.start_file("META-INF/MANIFEST.MF", options).unwrap();
+        let manifest =
+            "Manifest-Version: 1.0\r\nMain-Class: net.minecraft.bun\r\n dler.Main\r\n\r\n";
+        jar.write_all(manifest.as_bytes()).unwrap();
+        jar.start_file("net/minecraft/bundler/Main.class", options)
+            .unwrap();
+        jar.write_all(&[0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, compiled_for + 44])
+            .unwrap();
+        if let Some(version_json) = version_json {
+            jar.start_file("version.json", options).unwrap();
+            jar.write_all(version_json.as_bytes()).unwrap();
+        }
+        jar.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn required_by_jar() {
+        let dir = env::temp_dir().join(format!("java_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("server.jar");
+
+        write_jar(&path, 17, None);
+        assert_eq!(required(&path).await, Some(17));
+
+        // the bundler runs on java 8, the server it starts does not
+        let version_json = r#"{"id": "1.18.1", "java_version": 17, "stable": true}"#;
+        write_jar(&path, 8, Some(version_json));
+        assert_eq!(required(&path).await, Some(17));
+
+        assert_eq!(required(&dir.join("missing.jar")).await, None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,21 +1,27 @@
 use core::fmt;
 use derivative::Derivative;
 use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
 use std::path::{Path, PathBuf};
-use std::process::Stdio;
+use std::process::{ExitStatus, Stdio};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
 use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::sync::broadcast::{self, error::RecvError};
 use tokio::sync::Mutex;
-use tokio::time::timeout;
-use tracing::{debug, instrument};
+use tokio::time::{timeout, timeout_at, Instant};
+use tracing::{debug, info, instrument, warn};
 
 mod config;
+pub mod crash;
 #[cfg(feature="util")]
 pub mod util;
+pub mod java;
 pub mod parser;
-pub use config::Config;
+pub mod rcon;
+pub use config::{Config, Difficulty, GameMode};
+pub use crash::CrashReport;
 pub use parser::{Line, Message};
 
 #[derive(Clone, Debug, thiserror::Error, Hash, PartialEq, Eq)]
@@ -35,20 +41,48 @@ pub enum Error {
     NoJar(PathBuf),
     #[error("Java version outdated")]
     OutdatedJava { required: String },
+    #[error("No java runtime found, install java or pass the path to one")]
+    NoJava,
+    #[error("The minecraft server needs java {required} or newer, the newest installed is java {newest}")]
+    JavaTooOld { required: u16, newest: u16 },
     #[error("Eula not accepted")]
     EulaUnaccepted(&'static str),
+    /// the report is missing if the server exited without writing one
+    #[error("Minecraft server crashed, exit code: {code:?}")]
+    Crashed {
+        code: Option<i32>,
+        report: Option<CrashReport>,
+    },
+    #[error("Minecraft server stopped, exit code: {code:?}")]
+    Stopped { code: Option<i32> },
+    #[error("Minecraft server did not stop in time and was killed")]
+    StopTimeout,
 }
 
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct Instance {
+    /// only taken when dropped
     #[derivative(Debug = "ignore")]
-    _process: Child,
+    process: Option<Child>,
     working_dir: PathBuf,
     stdout: Lines<BufReader<ChildStdout>>,
     stderr: Lines<BufReader<ChildStderr>>,
+    stderr_closed: bool,
+    /// parsed lines, handles look in these for the result of commands
+    lines: broadcast::Sender<Line>,
+    handle: Handle,
+    /// the server printed it is stopping
+    stopping: bool,
+    /// the server printed it ran into an error it can not recover from
+    crashing: bool,
+    /// crash reports older then this are from an earlier run
+    started: SystemTime,
 }
 
+/// how long the server gets to save and exit when stopped
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(60);
+
 const GC_ARGS: &[&str] = &[
     "-Dsun.rmi.dgc.server.gcInterval=2147483646", // do not garbace collect every min
     "-XX:+UnlockExperimentalVMOptions",           // unknown but recommanded
@@ -60,12 +94,15 @@ const GC_ARGS: &[&str] = &[
 
 impl Instance {
     /// this assumes the server jar is named `server.jar` and located in
-    /// the folder passed as paramater `server_path`
+    /// the folder passed as paramater `server_path`. The server runs on
+    /// the java at `java` if it is new enough for the jar, otherwise on
+    /// an installed one that is, see [`java::select`]
     pub async fn start(
         server_path: impl AsRef<Path>,
         mem_size: u8,
+        java: Option<&Path>,
     ) -> Result<(Self, Handle), Error> {
-        Self::start_instance(server_path.as_ref(), mem_size).await
+        Self::start_instance(server_path.as_ref(), mem_size, java).await
     }
 
     pub async fn assert_eula_accepted(server_path: &Path) -> Result<(), Error> {
@@ -78,13 +115,19 @@ impl Instance {
     }
 
     #[instrument(err)]
-    async fn start_instance(server_path: &Path, mem_size: u8) -> Result<(Self, Handle), Error> {
+    async fn start_instance(
+        server_path: &Path,
+        mem_size: u8,
+        java: Option<&Path>,
+    ) -> Result<(Self, Handle), Error> {
         let working_dir = tokio::fs::canonicalize(server_path)
             .await
             .map_err(|_| Error::IncorrectServerPath)?;
         Self::assert_eula_accepted(server_path).await?;
+        let java = java::select(java, &working_dir.join("server.jar")).await?;
+        info!("using java {} at: {}", java.version, java.path.display());
 
-        let mut child = Command::new("java")
+        let child = Command::new(&java.path)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .stdin(Stdio::piped())
@@ -100,20 +143,48 @@ impl Instance {
             .spawn()
             .map_err(|e| Error::SpawnFailed(e.kind()))?;
 
+        let rcon = Config::read(&working_dir)
+            .await
+            .ok()
+            .and_then(|config| config.rcon());
+        let (mut instance, mut handle) = Self::from_child(child, working_dir).await;
+        if let Some((port, password)) = rcon {
+            handle = handle.with_rcon(SocketAddr::from((Ipv4Addr::LOCALHOST, port)), password);
+            instance.handle = handle.clone();
+        }
+        Ok((instance, handle))
+    }
+
+    async fn from_child(mut child: Child, working_dir: PathBuf) -> (Self, Handle) {
         let stdin = wait_for(&mut child.stdin).await;
         let stdout = BufReader::new(wait_for(&mut child.stdout).await).lines();
         let stderr = BufReader::new(wait_for(&mut child.stderr).await).lines();
 
+        let (lines, _) = broadcast::channel(LINE_BUFFER);
+        let handle = Handle::from(stdin, lines.clone());
         let instance = Self {
-            _process: child,
+            process: Some(child),
             working_dir,
             stdout,
             stderr,
+            stderr_closed: false,
+            lines,
+            handle: handle.clone(),
+            stopping: false,
+            crashing: false,
+            started: SystemTime::now(),
         };
-        let handle = Handle::from(stdin);
-        Ok((instance, handle))
+        (instance, handle)
+    }
+
+    fn process(&mut self) -> &mut Child {
+        self.process
+            .as_mut()
+            .expect("process is only taken on drop")
     }
 
+    /// typed commands on the [`Handle`] only complete while this is being
+    /// called, they wait for their result in the output
     #[instrument(err)]
     pub async fn next_event(&mut self) -> Result<parser::Line, Error> {
         loop {
@@ -122,22 +193,135 @@ impl Instance {
                     match res {
                         Err(e) => return Err(Error::Pipe(e.kind())),
                         Ok(Some(line)) => match parser::parse(line) {
-                            Ok(line) => return Ok(line),
+                            Ok(line) => {
+                                self.stopping |= line.msg == Message::Stopping;
+                                self.crashing |= line.msg == Message::Crashing;
+                                let _no_command_waiting = self.lines.send(line.clone());
+                                return Ok(line)
+                            }
                             Err(e) => {debug!("{:?}", e); continue}
                         }
-                        Ok(None) => continue,
+                        Ok(None) => return Err(self.exited().await),
                     }
                 }
-                res = self.stderr.next_line() => {
+                res = self.stderr.next_line(), if !self.stderr_closed => {
                     match res {
                         Err(e) => return Err(Error::Pipe(e.kind())),
                         Ok(Some(line)) => return Err(handle_stderr(line, self).await),
-                        Ok(None) => continue,
+                        Ok(None) => self.stderr_closed = true,
                     }
                 }
             }
         }
     }
+
+    /// the server closed its output, it is exiting
+    async fn exited(&mut self) -> Error {
+        // if the server could not run the reason is on stderr
+        if !self.stderr_closed {
+            if let Ok(Some(line)) = self.stderr.next_line().await {
+                return handle_stderr(line, self).await;
+            }
+            self.stderr_closed = true;
+        }
+        let code = match self.process().wait().await {
+            Ok(status) => status.code(),
+            Err(e) => return Error::Pipe(e.kind()),
+        };
+        // a crashing server stops itself after writing the report
+        let report = crash::newest(&self.working_dir, self.started).await;
+        match (report, self.stopping && !self.crashing) {
+            (None, true) => Error::Stopped { code },
+            (report, _) => Error::Crashed { code, report },
+        }
+    }
+
+    /// wait for the server process to exit, use after sending `stop`
+    /// through the [`Handle`]. Output printed while exiting is logged
+    /// but not parsed
+    #[instrument(err)]
+    pub async fn wait(&mut self) -> Result<ExitStatus, Error> {
+        while let Some(line) = self
+            .stdout
+            .next_line()
+            .await
+            .map_err(|e| Error::Pipe(e.kind()))?
+        {
+            debug!("{}", line);
+        }
+        self.process()
+            .wait()
+            .await
+            .map_err(|e| Error::Pipe(e.kind()))
+    }
+
+    /// stops the server, unless it is already stopping, and waits for it
+    /// to exit. The server is killed if it does not exit within timeout.
+    /// Output printed while stopping is passed on to handles waiting on
+    /// it but not returned. Returns [`Error::Crashed`] if the server
+    /// crashed instead
+    #[instrument(err)]
+    pub async fn stop(&mut self, timeout_after: Duration) -> Result<ExitStatus, Error> {
+        let stop = async {
+            if !self.stopping {
+                // our own output reading confirms it stopped, not the handle
+                if let Err(e) = self.handle.command("stop").await {
+                    warn!("could not ask server to stop: {}", e);
+                }
+            }
+            loop {
+                match self.next_event().await {
+                    Ok(_) => continue,
+                    Err(Error::Stopped { .. }) => break,
+                    Err(e @ Error::Crashed { .. }) => return Err(e),
+                    Err(Error::Pipe(e)) => return Err(Error::Pipe(e)),
+                    Err(e) => warn!("error while stopping: {}", e),
+                }
+            }
+            self.process()
+                .wait()
+                .await
+                .map_err(|e| Error::Pipe(e.kind()))
+        };
+
+        match timeout(timeout_after, stop).await {
+            Ok(res) => res,
+            Err(_) => {
+                warn!("minecraft server did not stop in time, killing it");
+                self.process()
+                    .kill()
+                    .await
+                    .map_err(|e| Error::Pipe(e.kind()))?;
+                Err(Error::StopTimeout)
+            }
+        }
+    }
+}
+
+impl Drop for Instance {
+    /// stops the server in the background, it is killed if that takes
+    /// longer then [`STOP_TIMEOUT`] or when the runtime shuts down
+    fn drop(&mut self) {
+        let mut process = self.process.take().expect("process is only taken on drop");
+        if let Ok(Some(_)) = process.try_wait() {
+            return;
+        }
+        let runtime = match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => runtime,
+            Err(_) => return, // killed as the process is dropped
+        };
+        let mut handle = self.handle.clone();
+        let stopping = self.stopping;
+        runtime.spawn(async move {
+            if !stopping {
+                let _ = handle.command("stop").await;
+            }
+            if timeout(STOP_TIMEOUT, process.wait()).await.is_err() {
+                warn!("minecraft server did not stop in time, killing it");
+                let _ = process.kill().await;
+            }
+        });
+    }
 }
 
 async fn wait_for<T>(source: &mut Option<T>) -> T {
@@ -172,44 +356,297 @@ async fn handle_stderr(line: String, instance: &mut Instance) -> Error {
     }
 }
 
+/// how long commands wait for the server to confirm they ran
+pub const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+/// how long a save may take, large worlds take a while to write
+pub const SAVE_TIMEOUT: Duration = Duration::from_secs(120);
+/// parsed lines kept for handles waiting on the result of a command
+const LINE_BUFFER: usize = 1024;
+/// output of a command typed to stdin ends once the server thread is
+/// quiet for this long
+const OUTPUT_GAP: Duration = Duration::from_millis(250);
+/// commands the server rejects with a player that does not exist
+const PLAYER_COMMANDS: &[&str] = &["kick", "whitelist", "op"];
+
+/// runs commands over rcon if the server has it enabled, otherwise
+/// writes them to its stdin
 #[derive(Clone)]
-pub struct Handle(Arc<Mutex<ChildStdin>>);
+pub struct Handle {
+    stdin: Arc<Mutex<ChildStdin>>,
+    rcon: Option<rcon::Lazy>,
+    lines: broadcast::Sender<Line>,
+    /// typed commands run one at a time so the output they wait
+    /// for is theirs
+    running: Arc<Mutex<()>>,
+}
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, thiserror::Error)]
 pub enum HandleError {
     #[error("Could not write to minecraft server")]
     Io(String),
+    #[error("Could not run command over rcon: {0}")]
+    Rcon(#[from] rcon::Error),
+    #[error("Minecraft server did not confirm the command in time")]
+    NoConfirmation,
+    #[error("Minecraft server refused the command: {0}")]
+    Rejected(String),
+    #[error("Minecraft server printed too much to find the result of the command")]
+    MissedOutput,
 }
 
 impl Handle {
-    pub fn from(stdin: ChildStdin) -> Self {
-        Self(Arc::new(Mutex::new(stdin)))
+    /// lines must carry the parsed output of the server
+    pub fn from(stdin: ChildStdin, lines: broadcast::Sender<Line>) -> Self {
+        Self {
+            stdin: Arc::new(Mutex::new(stdin)),
+            rcon: None,
+            lines,
+            running: Arc::new(Mutex::new(())),
+        }
     }
-    pub async fn save(&mut self) -> Result<(), HandleError> {
-        self.0
+    /// run commands over the rcon listening on addr
+    pub fn with_rcon(mut self, addr: SocketAddr, password: String) -> Self {
+        self.rcon = Some(rcon::Lazy::new(addr, password));
+        self
+    }
+    /// runs a console command, the leading slash is optional. The
+    /// command must be a single line. Returns the reply if the command
+    /// ran over rcon, commands written to stdin get none. Falls back to
+    /// stdin while rcon is unreachable, for example during startup, or
+    /// does not reply within [`COMMAND_TIMEOUT`]
+    pub async fn command(&mut self, cmd: impl fmt::Display) -> Result<Option<String>, HandleError> {
+        self.command_within(&cmd.to_string(), COMMAND_TIMEOUT).await
+    }
+    async fn command_within(
+        &mut self,
+        cmd: &str,
+        timeout_after: Duration,
+    ) -> Result<Option<String>, HandleError> {
+        let cmd = cmd.trim_start_matches('/');
+        if let Some(rcon) = &self.rcon {
+            if let Some(reply) = rcon.command(cmd, timeout_after).await? {
+                return Ok(Some(reply));
+            }
+        }
+        let cmd = format!("/{}\n", cmd);
+        self.stdin
             .lock()
             .await
-            .write_all(b"/save-all\n")
+            .write_all(cmd.as_bytes())
             .await
             .map_err(|e| e.to_string())
             .map_err(HandleError::Io)?;
-        Ok(())
+        Ok(None)
+    }
+    /// runs a console command and returns what it printed: the reply if
+    /// it ran over rcon, otherwise the lines the server thread printed
+    /// right after it. Returns within [`COMMAND_TIMEOUT`], output of other
+    /// commands that ran at the same time can end up in the lines
+    pub async fn command_output(
+        &mut self,
+        cmd: impl fmt::Display,
+    ) -> Result<Vec<String>, HandleError> {
+        let running = self.running.clone();
+        let _one_at_a_time = running.lock().await;
+        let deadline = Instant::now() + COMMAND_TIMEOUT;
+        let mut output = self.lines.subscribe();
+        if let Some(reply) = self.command(cmd).await? {
+            return Ok(reply.lines().map(str::to_owned).collect());
+        }
+
+        let mut lines = Vec::new();
+        let collect = async {
+            loop {
+                let next = output.recv();
+                let line = if lines.is_empty() {
+                    next.await
+                } else {
+                    match timeout(OUTPUT_GAP, next).await {
+                        Ok(line) => line,
+                        Err(_) => return Ok(()),
+                    }
+                };
+                match line {
+                    Ok(line) if is_command_output(&line) => lines.push(line.msg.to_string()),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(_)) => return Err(HandleError::MissedOutput),
+                    Err(RecvError::Closed) => return Ok(()),
+                }
+            }
+        };
+        // nothing printed is a valid result for some commands
+        timeout_at(deadline, collect).await.unwrap_or(Ok(()))?;
+        Ok(lines)
+    }
+    /// runs cmd and waits until the reply or output of the server
+    /// confirms it ran or shows it failed
+    async fn run<T>(
+        &mut self,
+        cmd: &str,
+        timeout_after: Duration,
+        confirmed: impl Fn(&Message) -> Option<T>,
+    ) -> Result<T, HandleError> {
+        let running = self.running.clone();
+        let _one_at_a_time = running.lock().await;
+        let mut output = self.lines.subscribe();
+        if let Some(reply) = self.command_within(cmd, timeout_after).await? {
+            // the reply is for this command only
+            let mut replies = reply.lines().map(parser::parse_message);
+            if let Some(res) = replies.find_map(|msg| match msg {
+                Message::Rejected(reason) => Some(Err(HandleError::Rejected(reason))),
+                msg => confirmed(&msg).map(Ok),
+            }) {
+                return res;
+            }
+        }
+
+        // the output also has the results of commands others ran
+        let wait = async {
+            let mut syntax_error = None;
+            loop {
+                let line = match output.recv().await {
+                    Ok(line) => line,
+                    Err(RecvError::Lagged(_)) => return Err(HandleError::MissedOutput),
+                    Err(RecvError::Closed) => return Err(HandleError::NoConfirmation),
+                };
+                if let Some(reason) = syntax_error.take() {
+                    if echoes(cmd, &line.msg) {
+                        return Err(HandleError::Rejected(reason));
+                    }
+                }
+                match line.msg {
+                    Message::Rejected(reason) if is_syntax_error(&reason) => {
+                        syntax_error = Some(reason)
+                    }
+                    Message::Rejected(reason) if targets_player(cmd) => {
+                        return Err(HandleError::Rejected(reason))
+                    }
+                    msg => match confirmed(&msg) {
+                        Some(res) => return Ok(res),
+                        None => continue,
+                    },
+                }
+            }
+        };
+        timeout(timeout_after, wait)
+            .await
+            .unwrap_or(Err(HandleError::NoConfirmation))
+    }
+    /// saves the world to disk, returns once it is written
+    pub async fn save(&mut self) -> Result<(), HandleError> {
+        self.run("save-all flush", SAVE_TIMEOUT, |msg| {
+            matches!(msg, Message::Saved).then_some(())
+        })
+        .await
+    }
+    /// stop the server from saving on its own
+    pub async fn save_off(&mut self) -> Result<(), HandleError> {
+        self.run("save-off", COMMAND_TIMEOUT, |msg| {
+            matches!(msg, Message::AutoSave(false)).then_some(())
+        })
+        .await
+    }
+    pub async fn save_on(&mut self) -> Result<(), HandleError> {
+        self.run("save-on", COMMAND_TIMEOUT, |msg| {
+            matches!(msg, Message::AutoSave(true)).then_some(())
+        })
+        .await
+    }
+    /// saves the world and stops the server, returns once it is stopping.
+    /// Use [`Instance::wait`] to wait for the server process to exit or
+    /// [`Instance::stop`] to stop it with a timeout
+    pub async fn stop(&mut self) -> Result<(), HandleError> {
+        self.run("stop", COMMAND_TIMEOUT, |msg| {
+            matches!(msg, Message::Stopping).then_some(())
+        })
+        .await
+    }
+    /// names of the players online
+    pub async fn list(&mut self) -> Result<Vec<String>, HandleError> {
+        self.run("list", COMMAND_TIMEOUT, |msg| match msg {
+            Message::Players { names, .. } => Some(names.clone()),
+            _ => None,
+        })
+        .await
+    }
+    pub async fn kick(&mut self, player: &str) -> Result<(), HandleError> {
+        let cmd = format!("kick {}", player);
+        self.run(&cmd, COMMAND_TIMEOUT, |msg| match msg {
+            Message::Kicked(user) if user == player => Some(()),
+            _ => None,
+        })
+        .await
+    }
+    /// also succeeds if the player already is on the whitelist
+    pub async fn whitelist_add(&mut self, player: &str) -> Result<(), HandleError> {
+        let cmd = format!("whitelist add {}", player);
+        self.run(&cmd, COMMAND_TIMEOUT, |msg| match msg {
+            Message::WhitelistAdded(user) if user == player => Some(()),
+            Message::NothingChanged(_) => Some(()),
+            _ => None,
+        })
+        .await
+    }
+    /// also succeeds if the player is not on the whitelist
+    pub async fn whitelist_remove(&mut self, player: &str) -> Result<(), HandleError> {
+        let cmd = format!("whitelist remove {}", player);
+        self.run(&cmd, COMMAND_TIMEOUT, |msg| match msg {
+            Message::WhitelistRemoved(user) if user == player => Some(()),
+            Message::NothingChanged(_) => Some(()),
+            _ => None,
+        })
+        .await
+    }
+    /// also succeeds if the player already is an operator
+    pub async fn op(&mut self, player: &str) -> Result<(), HandleError> {
+        let cmd = format!("op {}", player);
+        self.run(&cmd, COMMAND_TIMEOUT, |msg| match msg {
+            Message::Opped(user) if user == player => Some(()),
+            Message::NothingChanged(_) => Some(()),
+            _ => None,
+        })
+        .await
     }
     /// sends a message to all players in the server chat, message
-    /// must be plain text
+    /// must be plain text. The server does not confirm these
     pub async fn say(&mut self, msg: impl fmt::Display) -> Result<(), HandleError> {
-        let cmd = format!("/say {}\n", msg);
-        self.0
-            .lock()
-            .await
-            .write_all(cmd.as_bytes())
-            .await
-            .map_err(|e| e.to_string())
-            .map_err(HandleError::Io)?;
-        Ok(())
+        self.command(format!("say {}", msg)).await.map(drop)
     }
 }
 
+fn is_syntax_error(reason: &str) -> bool {
+    reason.starts_with("Unknown or incomplete command")
+        || reason.starts_with("Incorrect argument for command")
+}
+
+/// the server follows a syntax error with the last characters of the
+/// command up to the error, for example: `...e-all flus<--[HERE]`
+fn echoes(cmd: &str, msg: &Message) -> bool {
+    let echo = match msg {
+        Message::Other(echo) => echo,
+        _ => return false,
+    };
+    match echo.strip_suffix("<--[HERE]") {
+        Some(echo) => cmd.contains(echo.trim_start_matches("...")),
+        None => false,
+    }
+}
+
+/// the server thread prints the output of commands, it also prints
+/// players coming and going which is never output of a command
+fn is_command_output(line: &Line) -> bool {
+    line.source == "Server thread"
+        && !matches!(
+            line.msg,
+            Message::Joined { .. } | Message::Left(_) | Message::Overloaded(..)
+        )
+}
+
+fn targets_player(cmd: &str) -> bool {
+    let name = cmd.split_whitespace().next().unwrap_or_default();
+    PLAYER_COMMANDS.contains(&name)
+}
+
 pub fn outdated_java_error(lines: &str) -> Error {
     let start = lines.find("class file version ").unwrap();
     let stop = start + lines[start..].find(')').unwrap();
@@ -222,3 +659,110 @@ impl std::fmt::Debug for Handle {
         f.write_str("Handle to mc server")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// runs script with sh in dir as if it was the minecraft server
+    async fn stand_in(dir: &Path, script: &str) -> (Instance, Handle) {
+        let child = Command::new("sh")
+            .args(["-c", script])
+            .current_dir(dir)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .stdin(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        Instance::from_child(child, dir.to_owned()).await
+    }
+
+    #[tokio::test]
+    async fn unexpected_exit() {
+        let script = r#"echo "[12:00:00] [Server thread/INFO]: Preparing spawn area: 10%"; exit 3"#;
+        let (mut instance, _handle) = stand_in(Path::new("."), script).await;
+        assert_eq!(
+            instance.next_event().await.unwrap().msg,
+            Message::Loading(10)
+        );
+        let err = instance.next_event().await.unwrap_err();
+        assert_eq!(
+            err,
+            Error::Crashed {
+                code: Some(3),
+                report: None
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn crash_report() {
+        let dir = std::env::temp_dir().join(format!("crash_test_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("crash-reports")).unwrap();
+        std::fs::write(dir.join("crash-reports/crash-old-server.txt"), "old").unwrap();
+
+        let script = r#"
+            sleep 0.1
+            echo "[12:00:00] [Server thread/ERROR]: Encountered an unexpected exception"
+            echo "---- Minecraft Crash Report ----" > crash-reports/crash-new-server.txt
+            echo "[12:00:00] [Server thread/INFO]: Stopping server"
+            exit 1"#;
+        let (mut instance, _handle) = stand_in(&dir, script).await;
+        assert_eq!(instance.next_event().await.unwrap().msg, Message::Crashing);
+        let err = instance.stop(Duration::from_secs(5)).await.unwrap_err();
+        let report = CrashReport {
+            file_name: "crash-new-server.txt".to_owned(),
+            text: "---- Minecraft Crash Report ----\n".to_owned(),
+        };
+        assert_eq!(
+            err,
+            Error::Crashed {
+                code: Some(1),
+                report: Some(report)
+            }
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stop() {
+        let script = r#"
+            read -r cmd
+            [ "$cmd" = "/stop" ] && echo "[12:00:00] [Server thread/INFO]: Stopping server"
+            exit 0"#;
+        let (mut instance, _handle) = stand_in(Path::new("."), script).await;
+        let status = instance.stop(Duration::from_secs(5)).await.unwrap();
+        assert!(status.success());
+    }
+
+    #[tokio::test]
+    async fn rejections_of_other_commands() {
+        let script = r#"
+            while read -r cmd; do
+                echo "[12:00:00] [Server thread/INFO]: Unknown or incomplete command, see below for error"
+                echo "[12:00:00] [Server thread/INFO]: ...me command<--[HERE]"
+                case "$cmd" in
+                    "/kick Bob") echo "[12:00:00] [Server thread/INFO]: Kicked Bob: Kicked by an operator";;
+                    "/save-all flush")
+                        echo "[12:00:00] [Server thread/INFO]: Unknown or incomplete command, see below for error"
+                        echo "[12:00:00] [Server thread/INFO]: ...e-all flush<--[HERE]";;
+                esac
+            done"#;
+        let (mut instance, mut handle) = stand_in(Path::new("."), script).await;
+        tokio::spawn(async move { while instance.next_event().await.is_ok() {} });
+
+        handle.kick("Bob").await.unwrap();
+        let err = handle.save().await.unwrap_err();
+        assert!(matches!(err, HandleError::Rejected(_)));
+    }
+
+    #[tokio::test]
+    async fn kill_after_timeout() {
+        let script = "while true; do read -r cmd; done";
+        let (mut instance, _handle) = stand_in(Path::new("."), script).await;
+        let err = instance.stop(Duration::from_millis(200)).await.unwrap_err();
+        assert_eq!(err, Error::StopTimeout);
+        assert!(instance.process().try_wait().unwrap().is_some());
+    }
+}
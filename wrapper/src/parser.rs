@@ -1,3 +1,4 @@
+use std::fmt;
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::time::Duration;
@@ -74,13 +75,138 @@ pub enum Message {
     Overloaded(Duration, usize),
     Exception(Exception),
     Stopping,
+    /// the server ran into an error it can not recover from, it writes a
+    /// crash report and stops
+    Crashing,
     Chat {
         from: String,
         msg: String,
     },
+    /// reply to `list`
+    Players {
+        online: usize,
+        max: usize,
+        names: Vec<String>,
+    },
+    WhitelistAdded(String),
+    WhitelistRemoved(String),
+    Opped(String),
+    /// automatic saving is turned on (true) or off, also when it already was
+    AutoSave(bool),
+    /// a command had nothing to do, for example whitelisting a player that
+    /// already is
+    NothingChanged(String),
+    /// a command failed, for example because the player does not exist
+    Rejected(String),
     Other(String),
 }
 
+/// Formats lines close to how the minecraft server printed them, some
+/// details the parser drops are left out
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let t = self.time;
+        write!(
+            f,
+            "[{:02}:{:02}:{:02}] [{}/{}]: {}",
+            t.hour(),
+            t.minute(),
+            t.second(),
+            self.source,
+            self.level,
+            self.msg
+        )
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Version::Pre(major, minor, pre) => write!(f, "{}.{} Pre-Release {}", major, minor, pre),
+            Version::ExpSnapshot(major, minor, snap) => {
+                write!(f, "{}.{} Experimental Snapshot {}", major, minor, snap)
+            }
+            Version::Snapshot {
+                year,
+                week,
+                revision,
+            } => write!(f, "{:02}w{:02}{}", year, week, revision),
+            Version::Rc(major, minor, rc) => {
+                write!(f, "{}.{} Release Candidate {}", major, minor, rc)
+            }
+            Version::Full(major, minor, patch) => write!(f, "{}.{}.{}", major, minor, patch),
+            Version::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Message::EulaUnaccepted => f.write_str("Failed to load eula.txt"),
+            Message::Joined {
+                user,
+                address,
+                entity_id,
+                coords,
+            } => write!(
+                f,
+                "{}[/{}] logged in with entity id {} at ({}, {}, {})",
+                user, address, entity_id, coords.x, coords.y, coords.z
+            ),
+            Message::Left(user) => write!(f, "{} lost connection: Disconnected", user),
+            Message::Kicked(user) => write!(f, "Kicked {}: Kicked by an operator", user),
+            Message::Version(v) => write!(f, "Starting minecraft server version {}", v),
+            Message::Loading(p) => write!(f, "Preparing spawn area: {}%", p),
+            Message::DoneLoading(d) => {
+                write!(f, "Done ({:.3}s)! For help, type \"help\"", d.as_secs_f32())
+            }
+            Message::Saved => f.write_str("Saved the game"),
+            Message::Overloaded(behind, ticks) => write!(
+                f,
+                "Can't keep up! Is the server overloaded? Running {}ms or {} ticks behind",
+                behind.as_millis(),
+                ticks
+            ),
+            Message::Exception(Exception::AddressInUse) => {
+                f.write_str("The exception was: bind(..) failed: Address already in use")
+            }
+            Message::Exception(Exception::Unknown(e)) => write!(f, "The exception was: {}", e),
+            Message::Stopping => f.write_str("Stopping server"),
+            Message::Crashing => f.write_str("Encountered an unexpected exception"),
+            // the console says things with /say, over rcon too
+            Message::Chat { from, msg } if from == "Server" || from == "Rcon" => {
+                write!(f, "[{}] {}", from, msg)
+            }
+            Message::Chat { from, msg } => write!(f, "<{}> {}", from, msg),
+            Message::Players { online, max, names } => write!(
+                f,
+                "There are {} of a max of {} players online: {}",
+                online,
+                max,
+                names.join(", ")
+            ),
+            Message::WhitelistAdded(user) => write!(f, "Added {} to the whitelist", user),
+            Message::WhitelistRemoved(user) => write!(f, "Removed {} from the whitelist", user),
+            Message::Opped(user) => write!(f, "Made {} a server operator", user),
+            Message::AutoSave(true) => f.write_str("Automatic saving is now enabled"),
+            Message::AutoSave(false) => f.write_str("Automatic saving is now disabled"),
+            Message::NothingChanged(s) | Message::Rejected(s) => f.write_str(s),
+            Message::Other(s) => f.write_str(s),
+        }
+    }
+}
+
 /// Incomplete parser, will return Error on non matching lines, these should be 
 /// logged or discarded by the caller
 pub fn parse(input: impl Into<String> + AsRef<str>) -> Result<Line, Error> {
@@ -88,6 +214,12 @@ pub fn parse(input: impl Into<String> + AsRef<str>) -> Result<Line, Error> {
         .map_err(|error| Error::ParsingError{line: input.into(), error})
 }
 
+/// parses the message part of a line, for example the reply to a command
+/// run over rcon
+pub fn parse_message(input: &str) -> Message {
+    line_parser::message(input).unwrap_or_else(|_| Message::Other(input.to_owned()))
+}
+
 peg::parser! {
     grammar line_parser() for str {
 
@@ -162,6 +294,8 @@ peg::parser! {
             = "Saved the game" { Message::Saved }
         rule stopping() -> Message
             = "Stopping server" { Message::Stopping }
+        rule crashing() -> Message
+            = "Encountered an unexpected exception" { Message::Crashing }
 
         rule addr_in_use() -> Exception
             = [^':']+ ": bind(..) failed: Address already in use" {
@@ -233,9 +367,73 @@ peg::parser! {
             }
         }
 
+        // since 1.19 chat without a signature is marked as not secure
+        rule player_chat() -> Message
+            = "[Not Secure] "? "<" from:name() "> " msg:anything_left() {
+            Message::Chat {
+                from,
+                msg,
+            }
+        }
+
+        rule number() -> usize
+            = n:$(['0'..='9']+) { n.parse().unwrap() }
+
+        rule players() -> Message
+            = "There are " online:number() " of a max of " max:number() " players online:" names:$([_]*) {
+            let names = names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_owned)
+                .collect();
+            Message::Players { online, max, names }
+        }
+
+        rule whitelist_added() -> Message
+            = "Added " user:name() " to the whitelist" { Message::WhitelistAdded(user) }
+        rule whitelist_removed() -> Message
+            = "Removed " user:name() " from the whitelist" { Message::WhitelistRemoved(user) }
+        rule opped() -> Message
+            = "Made " user:name() " a server operator" { Message::Opped(user) }
+
+        rule auto_save() -> Message
+            = ("Automatic saving is now enabled" / "Saving is already turned on") {
+            Message::AutoSave(true)
+        }
+            / ("Automatic saving is now disabled" / "Saving is already turned off") {
+            Message::AutoSave(false)
+        }
+
+        rule nothing_changed() -> Message
+            = s:$("Player is already whitelisted" / "Player is not whitelisted"
+                / "Nothing changed. The player already is an operator") {
+            Message::NothingChanged(s.to_owned())
+        }
+
+        rule rejected() -> Message
+            = s:$(("No player was found" / "That player does not exist"
+                / "Unknown or incomplete command" / "Incorrect argument for command") [_]*) {
+            Message::Rejected(s.to_owned())
+        }
+
+        // the console also shows the feedback to commands run over rcon
+        rule rcon_feedback() -> Message
+            = "[Rcon: " s:$([_]+) {?
+            match s.strip_suffix(']') {
+                Some(inner) => message(inner).map_err(|_| "rcon feedback"),
+                None => Err("rcon feedback"),
+            }
+        }
+
         rule msg() -> Message
             = loading() / done_loading() / overloaded() / eula() / joined() / left()
-             / kicked() / chat() / saved() / stopping() / exception() / version() / other_msg()
+             / kicked() / rcon_feedback() / chat() / player_chat() / saved() / stopping()
+             / crashing() / exception() / version() / players() / whitelist_added() / whitelist_removed()
+             / opped() / auto_save() / nothing_changed() / rejected() / other_msg()
+
+        pub rule message() -> Message
+            = msg()
 
         rule info() -> Level
             = "INFO" { Level::Info }
@@ -0,0 +1,307 @@
+//! Minecraft remote console, a tcp protocol to run commands that, unlike
+//! writing to stdin, returns the reply of the server.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::time::{error::Elapsed, timeout};
+use tracing::{debug, warn};
+
+const RESPONSE: i32 = 0;
+const COMMAND: i32 = 2;
+const LOGIN: i32 = 3;
+/// longest command the server accepts, in bytes
+pub const MAX_COMMAND_LEN: usize = 1446;
+/// the server splits longer replies over multiple packets
+const MAX_BODY_LEN: usize = 4096;
+/// how long connecting, logging in and sending a packet may take, a hung
+/// server must not block those waiting to run a command
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, thiserror::Error)]
+pub enum Error {
+    #[error("Io error talking to rcon: {0:?}")]
+    Io(io::ErrorKind),
+    #[error("Rcon refused the password")]
+    AuthFailed,
+    #[error("Rcon send a packet we do not understand")]
+    Malformed,
+    #[error("Command is longer then rcon allows")]
+    TooLong,
+    #[error("Rcon did not answer in time")]
+    Timeout,
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e.kind())
+    }
+}
+
+impl From<Elapsed> for Error {
+    fn from(_: Elapsed) -> Self {
+        Self::Timeout
+    }
+}
+
+#[derive(Debug)]
+pub struct Rcon {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+impl Rcon {
+    pub async fn connect(addr: SocketAddr, password: &str) -> Result<Self, Error> {
+        let stream = timeout(TIMEOUT, TcpStream::connect(addr)).await??;
+        let mut rcon = Self { stream, next_id: 1 };
+        let id = rcon.send(LOGIN, password).await?;
+        match timeout(TIMEOUT, rcon.receive()).await?? {
+            (answer, COMMAND, _) if answer == id => Ok(rcon),
+            (-1, COMMAND, _) => Err(Error::AuthFailed),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    /// runs command, a leading slash is not needed, and returns the reply.
+    /// Fails with [`Error::Timeout`] if the reply takes longer then
+    /// timeout_after, the connection is of no use after
+    pub async fn command(
+        &mut self,
+        command: &str,
+        timeout_after: Duration,
+    ) -> Result<String, Error> {
+        if command.len() > MAX_COMMAND_LEN {
+            return Err(Error::TooLong);
+        }
+        let id = self.send(COMMAND, command).await?;
+        // the server answers packets it does not know in order, once we
+        // get the answer to this one the reply is complete
+        let end = self.send(RESPONSE, "").await?;
+
+        let receive = async {
+            let mut reply = String::new();
+            loop {
+                match self.receive().await? {
+                    (answer, RESPONSE, body) if answer == id => reply.push_str(&body),
+                    (answer, RESPONSE, _) if answer == end => return Ok(reply),
+                    _ => return Err(Error::Malformed),
+                }
+            }
+        };
+        timeout(timeout_after, receive).await?
+    }
+
+    async fn send(&mut self, kind: i32, body: &str) -> Result<i32, Error> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+
+        let len = 4 + 4 + body.len() + 2;
+        let mut packet = Vec::with_capacity(4 + len);
+        packet.extend_from_slice(&(len as i32).to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&kind.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        timeout(TIMEOUT, self.stream.write_all(&packet)).await??;
+        Ok(id)
+    }
+
+    /// returns the id, type and body of the next packet
+    async fn receive(&mut self) -> Result<(i32, i32, String), Error> {
+        let len = self.stream.read_i32_le().await?;
+        let len = usize::try_from(len).map_err(|_| Error::Malformed)?;
+        if !(10..=10 + MAX_BODY_LEN).contains(&len) {
+            return Err(Error::Malformed);
+        }
+        let mut packet = vec![0u8; len];
+        self.stream.read_exact(&mut packet).await?;
+
+        let id = i32::from_le_bytes(packet[0..4].try_into().unwrap());
+        let kind = i32::from_le_bytes(packet[4..8].try_into().unwrap());
+        let body = String::from_utf8_lossy(&packet[8..len - 2]).into_owned();
+        Ok((id, kind, body))
+    }
+}
+
+/// connects on first use, the server opens rcon only once it is done
+/// starting
+#[derive(Clone)]
+pub(crate) struct Lazy {
+    addr: SocketAddr,
+    password: Arc<str>,
+    conn: Arc<Mutex<Option<Rcon>>>,
+}
+
+impl Lazy {
+    pub fn new(addr: SocketAddr, password: String) -> Self {
+        Self {
+            addr,
+            password: password.into(),
+            conn: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// the reply to command, none if rcon can not be reached or stopped
+    /// working. The command should be run some other way in that case
+    pub async fn command(
+        &self,
+        command: &str,
+        timeout_after: Duration,
+    ) -> Result<Option<String>, Error> {
+        let mut conn = self.conn.lock().await;
+        if conn.is_none() {
+            match Rcon::connect(self.addr, &self.password).await {
+                Ok(rcon) => *conn = Some(rcon),
+                Err(e @ (Error::Io(_) | Error::Timeout)) => {
+                    debug!("could not connect to rcon: {}", e);
+                    return Ok(None);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        match conn.as_mut().unwrap().command(command, timeout_after).await {
+            Ok(reply) => Ok(Some(reply)),
+            Err(e @ (Error::Io(_) | Error::Malformed | Error::Timeout)) => {
+                // the connection is of no use anymore, try a new one next time
+                warn!("rcon stopped working, falling back to stdin: {}", e);
+                *conn = None;
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use tokio::net::TcpListener;
+
+    const PASSWORD: &str = "hunter2";
+    const REPLY: Duration = Duration::from_secs(5);
+
+    async fn read_packet(stream: &mut TcpStream) -> Option<(i32, i32, String)> {
+        let len = stream.read_i32_le().await.ok()? as usize;
+        let mut packet = vec![0u8; len];
+        stream.read_exact(&mut packet).await.ok()?;
+        let id = i32::from_le_bytes(packet[0..4].try_into().unwrap());
+        let kind = i32::from_le_bytes(packet[4..8].try_into().unwrap());
+        let body = String::from_utf8(packet[8..len - 2].to_vec()).unwrap();
+        Some((id, kind, body))
+    }
+
+    async fn write_packet(stream: &mut TcpStream, id: i32, kind: i32, body: &str) {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&(10 + body.len() as i32).to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&kind.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        stream.write_all(&packet).await.unwrap();
+    }
+
+    /// answers like the rcon of a minecraft server
+    async fn stand_in() -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    while let Some((id, kind, body)) = read_packet(&mut stream).await {
+                        match (kind, body.as_str()) {
+                            (LOGIN, PASSWORD) => write_packet(&mut stream, id, COMMAND, "").await,
+                            (LOGIN, _) => write_packet(&mut stream, -1, COMMAND, "").await,
+                            (COMMAND, "list") => {
+                                let reply = "There are 0 of a max of 20 players online: ";
+                                write_packet(&mut stream, id, RESPONSE, reply).await
+                            }
+                            // a server stuck on a command
+                            (COMMAND, "hang") => std::future::pending().await,
+                            (COMMAND, "help") => {
+                                let reply = "a".repeat(MAX_BODY_LEN + 10);
+                                let (first, rest) = reply.split_at(MAX_BODY_LEN);
+                                write_packet(&mut stream, id, RESPONSE, first).await;
+                                write_packet(&mut stream, id, RESPONSE, rest).await;
+                            }
+                            (COMMAND, _) => {
+                                let reply = "Unknown or incomplete command";
+                                write_packet(&mut stream, id, RESPONSE, reply).await
+                            }
+                            (kind, _) => {
+                                let reply = format!("Unknown request {:x}", kind);
+                                write_packet(&mut stream, id, RESPONSE, &reply).await
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn command() {
+        let addr = stand_in().await;
+        let mut rcon = Rcon::connect(addr, PASSWORD).await.unwrap();
+        let reply = rcon.command("list", REPLY).await.unwrap();
+        assert_eq!(reply, "There are 0 of a max of 20 players online: ");
+        let reply = rcon.command("tp", REPLY).await.unwrap();
+        assert_eq!(reply, "Unknown or incomplete command");
+    }
+
+    #[tokio::test]
+    async fn split_reply() {
+        let addr = stand_in().await;
+        let mut rcon = Rcon::connect(addr, PASSWORD).await.unwrap();
+        let reply = rcon.command("help", REPLY).await.unwrap();
+        assert_eq!(reply.len(), MAX_BODY_LEN + 10);
+    }
+
+    #[tokio::test]
+    async fn wrong_password() {
+        let addr = stand_in().await;
+        let err = Rcon::connect(addr, "password").await.unwrap_err();
+        assert_eq!(err, Error::AuthFailed);
+    }
+
+    #[tokio::test]
+    async fn too_long() {
+        let addr = stand_in().await;
+        let mut rcon = Rcon::connect(addr, PASSWORD).await.unwrap();
+        let command = "say ".to_owned() + &"a".repeat(MAX_COMMAND_LEN);
+        assert_eq!(rcon.command(&command, REPLY).await, Err(Error::TooLong));
+    }
+
+    #[tokio::test]
+    async fn lazy_connect() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let lazy = Lazy::new(addr, PASSWORD.to_owned());
+        assert_eq!(lazy.command("list", REPLY).await, Ok(None));
+
+        let addr = stand_in().await;
+        let lazy = Lazy::new(addr, PASSWORD.to_owned());
+        let reply = lazy.command("list", REPLY).await.unwrap().unwrap();
+        assert_eq!(reply, "There are 0 of a max of 20 players online: ");
+    }
+
+    #[tokio::test]
+    async fn lazy_hung() {
+        let addr = stand_in().await;
+        let lazy = Lazy::new(addr, PASSWORD.to_owned());
+        let hung = lazy.command("hang", Duration::from_millis(100)).await;
+        assert_eq!(hung, Ok(None));
+        assert!(lazy.conn.lock().await.is_none());
+
+        let reply = lazy.command("list", REPLY).await.unwrap().unwrap();
+        assert_eq!(reply, "There are 0 of a max of 20 players online: ");
+    }
+}
@@ -0,0 +1,78 @@
+use std::process::Stdio;
+
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::broadcast;
+use wrapper::{parser, Handle, HandleError};
+
+/// answers a few commands like a minecraft server would, ignores the rest
+const STAND_IN: &str = r#"
+while read -r cmd; do
+    case "$cmd" in
+        "/list") echo "[12:00:00] [Server thread/INFO]: There are 2 of a max of 20 players online: Alice, Bob" ;;
+        "/whitelist add Bob") echo "[12:00:00] [Server thread/INFO]: Added Bob to the whitelist" ;;
+        "/whitelist add Alice") echo "[12:00:00] [Server thread/INFO]: Player is already whitelisted" ;;
+        "/kick Carol") echo "[12:00:00] [Server thread/INFO]: No player was found" ;;
+        "/save-all flush")
+            echo "[12:00:00] [Server thread/INFO]: Saving the game (this may take a moment!)"
+            echo "[12:00:01] [Server thread/INFO]: Saved the game" ;;
+    esac
+done
+"#;
+
+/// the process must be kept alive for the handle to work
+fn stand_in() -> (Child, Handle) {
+    let mut child = Command::new("sh")
+        .args(["-c", STAND_IN])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+
+    let (tx, _) = broadcast::channel(16);
+    let handle = Handle::from(child.stdin.take().unwrap(), tx.clone());
+    let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+    tokio::spawn(async move {
+        while let Some(line) = stdout.next_line().await.unwrap() {
+            let _ = tx.send(parser::parse(line).unwrap());
+        }
+    });
+    (child, handle)
+}
+
+#[tokio::test]
+async fn confirmed() {
+    let (_server, mut handle) = stand_in();
+    assert_eq!(handle.list().await.unwrap(), ["Alice", "Bob"]);
+    handle.whitelist_add("Bob").await.unwrap();
+    handle.whitelist_add("Alice").await.unwrap();
+    handle.save().await.unwrap();
+}
+
+#[tokio::test]
+async fn rejected() {
+    let (_server, mut handle) = stand_in();
+    let err = handle.kick("Carol").await.unwrap_err();
+    assert_eq!(err, HandleError::Rejected("No player was found".to_owned()));
+}
+
+#[tokio::test]
+async fn unconfirmed() {
+    let (_server, mut handle) = stand_in();
+    let err = handle.op("Bob").await.unwrap_err();
+    assert_eq!(err, HandleError::NoConfirmation);
+}
+
+#[tokio::test]
+async fn output_over_stdin() {
+    let (_server, mut handle) = stand_in();
+    let lines = handle.command_output("/save-all flush").await.unwrap();
+    assert_eq!(
+        lines,
+        [
+            "Saving the game (this may take a moment!)",
+            "Saved the game"
+        ]
+    );
+}
@@ -2,7 +2,7 @@ use std::net::SocketAddr;
 use std::str::FromStr;
 use std::time::Duration;
 use time::Time;
-use wrapper::parser::{parse, Coords, Level, Line, Message, Exception, Version};
+use wrapper::parser::{parse, parse_message, Coords, Level, Line, Message, Exception, Version};
 
 #[test]
 fn parse_loading() {
@@ -117,6 +117,13 @@ fn parse_saved() {
     assert_eq!(msg, correct);
 }
 
+#[test]
+fn parse_saved_over_rcon() {
+    let input = "[20:32:22] [Server thread/INFO]: [Rcon: Saved the game]";
+    let msg = parse(input).unwrap();
+    assert_eq!(msg.msg, Message::Saved);
+}
+
 #[test]
 fn parse_stopping() {
     let input = "[20:32:22] [Server thread/INFO]: Stopping server";
@@ -130,6 +137,19 @@ fn parse_stopping() {
     assert_eq!(msg, correct);
 }
 
+#[test]
+fn parse_crashing() {
+    let input = "[20:32:22] [Server thread/ERROR]: Encountered an unexpected exception";
+    let msg = parse(input).unwrap();
+    let correct = Line {
+        time: Time::from_hms(20, 32, 22).unwrap(),
+        source: "Server thread".to_owned(),
+        level: Level::Error,
+        msg: Message::Crashing,
+    };
+    assert_eq!(msg, correct);
+}
+
 #[test]
 fn parse_addr_in_use() {
     let input = r#"[21:14:50] [Server thread/WARN]: The exception was: io.netty.channel.unix.Errors$NativeIoException: bind(..) failed: Address already in use"#;
@@ -194,3 +214,65 @@ fn parse_chat() {
     let correct = Message::Chat{from: "Server".into(), msg: "test".into()};
     assert_eq!(line.msg, correct);
 }
+
+#[test]
+fn parse_player_chat() {
+    let input = r#"[17:44:44] [Server thread/INFO]: <Alice> hi <3"#;
+    let line = parse(input).unwrap();
+    let correct = Message::Chat{from: "Alice".into(), msg: "hi <3".into()};
+    assert_eq!(line.msg, correct);
+
+    let input = r#"[17:44:44] [Server thread/INFO]: [Not Secure] <Alice> hi"#;
+    let line = parse(input).unwrap();
+    let correct = Message::Chat{from: "Alice".into(), msg: "hi".into()};
+    assert_eq!(line.msg, correct);
+}
+
+#[test]
+fn parse_command_feedback() {
+    let input = "[17:44:44] [Server thread/INFO]: There are 0 of a max of 20 players online: ";
+    let correct = Message::Players {
+        online: 0,
+        max: 20,
+        names: Vec::new(),
+    };
+    assert_eq!(parse(input).unwrap().msg, correct);
+
+    let input = "[17:44:44] [Server thread/INFO]: [Rcon: Removed Alice from the whitelist]";
+    let correct = Message::WhitelistRemoved("Alice".into());
+    assert_eq!(parse(input).unwrap().msg, correct);
+
+    let input = "[17:44:44] [Server thread/INFO]: Saving is already turned on";
+    assert_eq!(parse(input).unwrap().msg, Message::AutoSave(true));
+
+    let input =
+        "[17:44:44] [Server thread/INFO]: Unknown or incomplete command, see below for error";
+    let correct = Message::Rejected("Unknown or incomplete command, see below for error".into());
+    assert_eq!(parse(input).unwrap().msg, correct);
+
+    let correct = Message::NothingChanged("Player is already whitelisted".into());
+    assert_eq!(parse_message("Player is already whitelisted"), correct);
+}
+
+#[test]
+fn display_as_printed() {
+    let lines = [
+        "[10:13:02] [Worker-Main-9/INFO]: Preparing spawn area: 68%",
+        "[00:08:39] [Server thread/INFO]: Done (9.997s)! For help, type \"help\"",
+        "[01:08:43] [Server thread/INFO]: Bob[/10.0.0.2:52788] logged in with entity id 179 at (238.3, 56.7, 335.3)",
+        "[01:09:12] [Server thread/INFO]: Bob lost connection: Disconnected",
+        "[17:44:44] [Server thread/INFO]: <Alice> hi",
+        "[17:44:44] [Server thread/INFO]: [Server] saving the world",
+        "[17:44:44] [Server thread/WARN]: Can't keep up! Is the server overloaded? Running 2001ms or 40 ticks behind",
+        "[17:44:44] [Server thread/INFO]: Starting minecraft server version 21w44a",
+        "[17:44:44] [Server thread/INFO]: Set the time to 1000",
+        "[17:44:44] [Server thread/INFO]: There are 2 of a max of 20 players online: Alice, Bob",
+        "[17:44:44] [Server thread/INFO]: Added Bob to the whitelist",
+        "[17:44:44] [Server thread/INFO]: Made Bob a server operator",
+        "[17:44:44] [Server thread/INFO]: Automatic saving is now disabled",
+        "[17:44:44] [Server thread/ERROR]: Encountered an unexpected exception",
+    ];
+    for input in lines {
+        assert_eq!(parse(input).unwrap().to_string(), input);
+    }
+}
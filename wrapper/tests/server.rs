@@ -1,4 +1,5 @@
 use std::path::Path;
+use std::time::Duration;
 
 use wrapper::parser::{Line, Message};
 use wrapper::{Error, Instance};
@@ -9,7 +10,7 @@ async fn fail_to_start() {
     shared::setup_test_tracing();
     std::fs::create_dir_all("tests/data/fail_to_start").unwrap();
     std::fs::write("tests/data/fail_to_start/eula.txt", "eula=true").unwrap();
-    let (mut instance, _handle) = Instance::start("tests/data/fail_to_start", 2)
+    let (mut instance, _handle) = Instance::start("tests/data/fail_to_start", 2, None)
         .await
         .unwrap();
     loop {
@@ -42,7 +43,7 @@ async fn start_fresh_server() {
     let server_path = Path::new("tests/data/start_fresh");
     setup_server(server_path, 12387).await;
 
-    let (mut instance, _handle) = Instance::start(server_path, 1).await.unwrap();
+    let (mut instance, _handle) = Instance::start(server_path, 1, None).await.unwrap();
     await_loaded(&mut instance).await;
 }
 
@@ -54,13 +55,11 @@ async fn saving() {
     let server_path = Path::new("tests/data/saving");
     setup_server(server_path, 34867).await;
 
-    let (mut instance, mut handle) = Instance::start(server_path, 1).await.unwrap();
+    let (mut instance, mut handle) = Instance::start(server_path, 1, None).await.unwrap();
     await_loaded(&mut instance).await;
 
-    handle
-        .save()
-        .await
-        .expect("could not instruct server to save");
+    // the save only completes while we read the output
+    let saving = tokio::spawn(async move { handle.save().await });
     loop {
         match dbg!(instance.next_event().await) {
             Ok(Line {
@@ -71,6 +70,28 @@ async fn saving() {
             Err(e) => panic!("error after server save: {:?}", e),
         }
     }
+    saving
+        .await
+        .unwrap()
+        .expect("server did not confirm the save");
+}
+
+#[tokio::test]
+#[ignore] // Ignore unless specifically requested (use -- --incude-ignored)
+async fn stop() {
+    shared::setup_test_tracing();
+
+    let server_path = Path::new("tests/data/stop");
+    setup_server(server_path, 34868).await;
+
+    let (mut instance, _handle) = Instance::start(server_path, 1, None).await.unwrap();
+    await_loaded(&mut instance).await;
+
+    let status = instance
+        .stop(Duration::from_secs(30))
+        .await
+        .expect("server did not stop in time");
+    assert!(status.success());
 }
 
 fn random_string(length: usize) -> String {
@@ -90,7 +111,7 @@ async fn say() {
     let server_path = Path::new("tests/data/say");
     setup_server(server_path, 34867).await;
 
-    let (mut instance, mut handle) = Instance::start(server_path, 1).await.unwrap();
+    let (mut instance, mut handle) = Instance::start(server_path, 1, None).await.unwrap();
     await_loaded(&mut instance).await;
 
     let mut test_cases: Vec<_> = (1..2000)